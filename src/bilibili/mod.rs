@@ -1,68 +1,229 @@
+use std::cell::Cell;
 use std::collections::HashSet;
 use std::error::Error;
 
 use chrono::{Duration, TimeZone, Utc};
-use serde::Deserialize;
-use serde_json::Number;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
 
 use crate::calendar::event::*;
 use crate::calendar::event::Duration::StartEnd;
 use crate::common::*;
+use crate::common::diagnostic::ParsedEvents;
+use crate::common::error::ModuleError;
 
 const IDENTIFIER: &str = "bilibili";
+// Assumed when the watched time cannot be told, e.g. for live streams and articles.
+const UNKNOWN_DURATION_SECONDS: i64 = 10;
+// Code returned by the API once the cookie is no longer accepted.
+const NOT_LOGGED_IN_CODE: i64 = -101;
+// Code returned while requests are blocked for a while.
+const BLOCKED_CODE: i64 = -412;
 
 #[derive(Debug, Deserialize)]
-struct Page {
-    cid: Number,
-    page: Number,
+struct History {
+    // aid for videos, room id for live streams and cvid for articles
+    oid: i64,
+    #[serde(default)]
+    epid: i64,
+    #[serde(default)]
+    bvid: String,
+    #[serde(default)]
+    page: i64,
+    #[serde(default)]
     part: String,
-    duration: Number,
+    // "archive", "pgc" (bangumi), "live", "article" or "article-list"
+    business: String,
 }
 
 #[derive(Debug, Deserialize)]
 struct Item {
-    aid: Number,
-    bvid: String,
-    duration: Number,
-    page: Option<Page>,
-    progress: Number,
-    redirect_link: String,
     title: String,
-    view_at: Number,
+    #[serde(default)]
+    show_title: String,
+    #[serde(default)]
+    author_name: String,
+    history: History,
+    // Number of parts of a video.
+    #[serde(default)]
+    videos: i64,
+    view_at: i64,
+    // Position in the part when last watched; -1 once it has been watched to the end.
+    #[serde(default)]
+    progress: i64,
+    // Length of the part.
+    #[serde(default)]
+    duration: i64,
 }
 
 impl Item {
-    fn id(self: &Item) -> String {
-        format!("{}|{}|{}|{}", IDENTIFIER, self.bvid, match &self.page {
-            None => 0,
-            Some(page) => page.page.as_i64().unwrap(),
-        }, self.view_at)
+    fn id(self: &Item, identifier: &str) -> String {
+        match self.history.business.as_str() {
+            // Same as the ids of the legacy history endpoint.
+            "archive" => format!("{}|{}|{}|{}", identifier, self.history.bvid, self.history.page, self.view_at),
+            "pgc" => format!("{}|pgc|{}|{}", identifier, self.history.epid, self.view_at),
+            business => format!("{}|{}|{}|{}", identifier, business, self.history.oid, self.view_at),
+        }
+    }
+
+    fn watched_seconds(self: &Item) -> i64 {
+        let watched = match self.progress {
+            -1 => self.duration,
+            progress if self.duration > 0 => progress.min(self.duration),
+            progress => progress,
+        };
+        if watched > 0 { watched } else { UNKNOWN_DURATION_SECONDS }
+    }
+
+    fn title(self: &Item) -> String {
+        match self.history.business.as_str() {
+            "archive" if self.videos > 1 => format!("[Bilibili] {} – P{} {}", self.title, self.history.page, self.history.part),
+            "archive" => format!("[Bilibili] {}", self.title),
+            "pgc" => format!("[Bilibili Bangumi] {} – {}", self.title, self.show_title),
+            "live" => format!("[Bilibili Live] {} ({})", self.title, self.author_name),
+            _ => format!("[Bilibili Article] {}", self.title),
+        }
     }
+
+    fn link(self: &Item) -> String {
+        match self.history.business.as_str() {
+            "archive" if self.videos > 1 => format!("https://www.bilibili.com/video/{}?p={}", self.history.bvid, self.history.page),
+            "archive" => format!("https://www.bilibili.com/video/{}", self.history.bvid),
+            "pgc" => format!("https://www.bilibili.com/bangumi/play/ep{}", self.history.epid),
+            "live" => format!("https://live.bilibili.com/{}", self.history.oid),
+            "article-list" => format!("https://www.bilibili.com/read/readlist/rl{}", self.history.oid),
+            _ => format!("https://www.bilibili.com/read/cv{}", self.history.oid),
+        }
+    }
+
+    fn to_event_with_id(self: &Item, identifier: &str) -> Result<EventWithId, ModuleError> {
+        let start_time = Utc.timestamp_opt(self.view_at, 0).single()
+            .ok_or_else(|| ModuleError::parse(format!("history item \"{}\"", self.title), format!("invalid view time {}", self.view_at), ""))?;
+        let end_time = start_time + Duration::seconds(self.watched_seconds());
+        let mut description = format!("[link] {}\n", self.link());
+        if !self.history.bvid.is_empty() {
+            description += format!("[bvid] {}\n", self.history.bvid).as_str();
+        }
+        description += format!("[hash] {}", self.id(identifier)).as_str();
+        Ok(EventWithId {
+            summary: self.title(),
+            description,
+            duration: StartEnd(start_time, end_time),
+            id: self.id(identifier),
+            location: None,
+            url: None,
+            ical_uid: None,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Cursor {
+    max: i64,
+    view_at: i64,
+    business: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Data {
+    cursor: Cursor,
+    #[serde(default)]
+    list: Vec<Item>,
 }
 
 #[derive(Debug, Deserialize)]
 struct Response {
-    code: Number,
-    data: Vec<Item>,
+    code: i64,
+    #[serde(default)]
+    message: String,
+    data: Option<Data>,
+}
+
+// An expired login is caught by `check_auth_health` before the response gets here when syncing, but not
+// when replaying; codes other than the known ones tell that the API changed.
+fn parse_response(response: &str) -> Result<Data, ModuleError> {
+    let json = serde_json::from_str::<Response>(response).map_err(|e| ModuleError::parse("history", e, response))?;
+    let reason = format!("Bilibili responds with code {}: {}", json.code, json.message);
+    match json.data {
+        Some(data) if json.code == 0 => Ok(data),
+        _ if json.code == NOT_LOGGED_IN_CODE => Err(ModuleError::Auth(reason)),
+        _ if json.code == BLOCKED_CODE => Err(ModuleError::RateLimited(None)),
+        _ => Err(ModuleError::SourceChanged(reason)),
+    }
+}
+
+// Query of the page following the ones in `data`.
+fn next_page(data: &Data) -> String {
+    format!("&max={}&view_at={}&business={}", data.cursor.max, data.cursor.view_at, data.cursor.business)
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq)]
+struct BilibiliState {
+    // Latest view time synced; history is paged back until it.
+    last_view_at: Option<i64>,
+    // Set while the views since `last_view_at` are more than the pages of a cycle.
+    #[serde(default)]
+    backlog: Option<Backlog>,
+}
+
+// Paging goes on from `page` in the next cycle, and `last_view_at` only moves on once it is done.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+struct Backlog {
+    page: String,
+    latest_view_at: i64,
+    // View time of the oldest row skipped so far, which is fetched again once the backlog is done.
+    skipped_view_at: Option<i64>,
 }
 
 pub struct Bilibili {
+    identifier: String,
     request_config: RequestConfig,
     event_ids: HashSet<String>,
+    state: BilibiliState,
+    // State to move to once the events of the cycle are posted.
+    pending_state: Cell<Option<BilibiliState>>,
+}
+
+impl Bilibili {
+    // `next_page` is the query of the page after the last one fetched, if the cycle stopped before
+    // reaching `last_view_at`; rows skipped in `skipped_view_at` are not counted as synced.
+    fn advance_state(&self, latest_view_at: Option<i64>, skipped_view_at: Option<i64>, next_page: Option<String>) -> BilibiliState {
+        let backlog = self.state.backlog.as_ref();
+        let latest_view_at = latest_view_at.into_iter().chain(backlog.map(|backlog| backlog.latest_view_at)).max();
+        let skipped_view_at = skipped_view_at.into_iter().chain(backlog.and_then(|backlog| backlog.skipped_view_at)).min();
+        match (next_page, latest_view_at) {
+            (Some(page), Some(latest_view_at)) => BilibiliState {
+                last_view_at: self.state.last_view_at,
+                backlog: Some(Backlog { page, latest_view_at, skipped_view_at }),
+            },
+            _ => BilibiliState {
+                last_view_at: match (latest_view_at, skipped_view_at) {
+                    (Some(latest), Some(skipped)) => Some(latest.min(skipped - 1)),
+                    (latest, _) => latest.or(self.state.last_view_at),
+                },
+                backlog: None,
+            },
+        }
+    }
 }
 
 impl Module for Bilibili {
-    fn new(calendar_id: Option<String>) -> Result<Box<dyn Module>, Box<dyn Error>> {
-        let request_config = RequestConfig::new(IDENTIFIER, calendar_id)?;
-        let event_ids = read_dumped_event_id(IDENTIFIER).unwrap_or(HashSet::new());
+    fn new(instance: Option<&str>, calendar_id: Option<String>) -> Result<Box<dyn Module>, Box<dyn Error>> {
+        let identifier = instance_identifier(IDENTIFIER, instance);
+        let request_config = RequestConfig::new(identifier.as_str(), calendar_id)?;
+        let event_ids = read_dumped_event_id(identifier.as_str()).unwrap_or_default();
         Ok(Box::new(Bilibili {
+            state: read_state::<BilibiliState>(identifier.as_str()),
+            identifier,
             request_config,
             event_ids,
+            pending_state: Cell::new(None),
         }))
     }
 
     fn dump(&self) {
-        dump_event_id_wrapper(IDENTIFIER, &self.event_ids);
+        dump_event_id_wrapper(self.identifier.as_str(), &self.event_ids);
+        dump_state(self.identifier.as_str(), &self.state);
     }
 
     fn get_config(&self) -> &RequestConfig {
@@ -74,40 +235,189 @@ impl Module for Bilibili {
     }
 
     fn get_identifier(&self) -> &str {
-        IDENTIFIER
+        self.identifier.as_str()
     }
 
     fn get_request_url(&self) -> String {
-        self.request_config.url.to_string()
+        match &self.state.backlog {
+            Some(backlog) => format!("{}{}", self.request_config.url, backlog.page),
+            None => self.request_config.url.to_string(),
+        }
+    }
+
+    fn check_auth_health(&self, status: StatusCode, _final_url: &str, response: &str) -> Option<String> {
+        match serde_json::from_str::<Response>(response) {
+            Ok(json) if json.code == NOT_LOGGED_IN_CODE => Some(format!("code {} ({})", json.code, json.message)),
+            _ if status == StatusCode::UNAUTHORIZED => Some(format!("HTTP status {}", status)),
+            _ => None,
+        }
+    }
+
+    // Without any view seen before, only the first page is taken.
+    fn next_page_url(&self, response: &String) -> Option<String> {
+        let data = parse_response(response.as_str()).ok()?;
+        let last_view_at = self.state.last_view_at?;
+        let oldest_view_at = data.list.iter().map(|item| item.view_at).min()?;
+        if oldest_view_at <= last_view_at || data.cursor.max == 0 {
+            return None;
+        }
+        Some(format!("{}{}", self.request_config.url, next_page(&data)))
+    }
+
+    fn get_cursor(&self) -> Option<String> {
+        self.state.last_view_at.map(|view_at| view_at.to_string())
     }
 
     fn need_for_detail(&self, _response: &String) -> Option<Vec<String>> {
         None
     }
 
-    fn process_response_into_event_with_id(&self, responses: Vec<String>) -> Result<Vec<EventWithId>, Box<dyn Error>> {
-        let response = responses[0].clone();
-        let items = match serde_json::from_str::<Response>(response.as_str()) {
-            Ok(json) => json.data,
-            Err(e) => panic!("Cannot parse {} response!, {:#?}. The original response reads:\n{}", IDENTIFIER, e, response),
-        };
-
-        Ok(items.iter().map(|item| {
-            let view_duration = match item.progress.as_i64().unwrap() {
-                -1 => match &item.page {
-                    None => 10,
-                    Some(page) => page.duration.as_i64().unwrap(),
-                },
-                k => k,
-            };
-            let start_time = Utc.ymd(1970, 1, 1).and_hms(0, 0, 0) + Duration::seconds(item.view_at.as_i64().unwrap());
-            let end_time = Utc.ymd(1970, 1, 1).and_hms(0, 0, 0) + Duration::seconds(item.view_at.as_i64().unwrap() + view_duration);
-            EventWithId {
-                summary: format!("[Bilibili] {}", item.title),
-                description: format!("[link] {}\n[bvid] {}\n[hash] {}", item.redirect_link, item.bvid, item.id()),
-                duration: StartEnd(start_time, end_time),
-                id: item.id(),
+    fn process_response_into_event_with_id(&self, responses: Vec<String>) -> Result<ParsedEvents, ModuleError> {
+        let mut parsed = ParsedEvents::default();
+        let mut row = 0;
+        let mut latest_view_at = None;
+        let mut skipped_view_at = None;
+        let mut unfetched_page = None;
+        for response in responses {
+            let data = parse_response(response.as_str())?;
+            for item in &data.list {
+                latest_view_at = latest_view_at.max(Some(item.view_at));
+                match item.to_event_with_id(self.identifier.as_str()) {
+                    Ok(event) => parsed.events.push(event),
+                    Err(e) => {
+                        if self.state.last_view_at.is_none_or(|last| item.view_at > last) {
+                            skipped_view_at = Some(skipped_view_at.map_or(item.view_at, |skipped: i64| skipped.min(item.view_at)));
+                        }
+                        parsed.skip(row, &e, "");
+                    }
+                }
+                row += 1;
             }
-        }).collect())
+            // Only the last page tells whether the cycle stopped before reaching `last_view_at`.
+            unfetched_page = self.next_page_url(&response).map(|_| next_page(&data));
+        }
+        self.pending_state.set(Some(self.advance_state(latest_view_at, skipped_view_at, unfetched_page)));
+        Ok(parsed)
+    }
+
+    fn commit_cycle(&mut self) {
+        if let Some(state) = self.pending_state.take() {
+            self.state = state;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::collections::HashSet;
+
+    use chrono::Utc;
+    use reqwest::StatusCode;
+
+    use crate::calendar::event::Duration::StartEnd;
+    use crate::common::{Module, RequestConfig};
+    use crate::bilibili::{Backlog, Bilibili, BilibiliState};
+    use crate::common::error::ModuleError;
+    use crate::test_support::{assert_golden, fetch_events, StubServer};
+
+    const RESPONSE: &str = r#"{"code":0,"message":"0","ttl":1,"data":{
+        "cursor":{"max":1010,"view_at":1666400000,"business":"archive","ps":20},
+        "list":[
+            {"title":"Lecture series","show_title":"","author_name":"up","history":{"oid":1001,"epid":0,"bvid":"BV1xx411c7mD","page":3,"cid":5,"part":"Part three","business":"archive","dt":2},
+             "videos":5,"view_at":1666500000,"progress":-1,"duration":600},
+            {"title":"Short clip","history":{"oid":1002,"bvid":"BV1yy411c7mE","page":1,"part":"","business":"archive"},
+             "videos":1,"view_at":1666450000,"progress":7200,"duration":300},
+            {"title":"Some anime","show_title":"第3话 Episode","history":{"oid":2001,"epid":3003,"business":"pgc"},
+             "view_at":1666420000,"progress":900,"duration":1420},
+            {"title":"Live room","author_name":"streamer","history":{"oid":4004,"business":"live"},
+             "view_at":1666410000,"progress":0,"duration":0},
+            {"title":"An article","history":{"oid":5005,"business":"article"},
+             "view_at":1666400000}
+        ]}}"#;
+
+    fn bilibili(last_view_at: Option<i64>) -> Bilibili {
+        Bilibili {
+            identifier: "bilibili".to_string(),
+            request_config: RequestConfig::for_test("https://api.bilibili.com/x/web-interface/history/cursor?ps=20"),
+            event_ids: HashSet::new(),
+            state: BilibiliState { last_view_at, backlog: None },
+            pending_state: Cell::new(None),
+        }
+    }
+
+    #[test]
+    fn test_process_response() {
+        let module = bilibili(None);
+        let events = module.process_response_into_event_with_id(vec![RESPONSE.to_string()]).unwrap().events;
+        let watched: Vec<i64> = events.iter().map(|e| match e.duration {
+            StartEnd(start, end) => (end - start).num_seconds(),
+            _ => panic!(),
+        }).collect();
+        assert_eq!(watched, vec![600, 300, 900, 10, 10]);
+        assert_eq!(events[0].summary, "[Bilibili] Lecture series – P3 Part three");
+        assert_eq!(events[0].id, "bilibili|BV1xx411c7mD|3|1666500000");
+        assert!(events[0].description.starts_with("[link] https://www.bilibili.com/video/BV1xx411c7mD?p=3\n"));
+        assert_eq!(events[1].summary, "[Bilibili] Short clip");
+        assert_eq!(events[2].summary, "[Bilibili Bangumi] Some anime – 第3话 Episode");
+        assert_eq!(events[2].id, "bilibili|pgc|3003|1666420000");
+        assert_eq!(events[3].summary, "[Bilibili Live] Live room (streamer)");
+        assert_eq!(events[4].id, "bilibili|article|5005|1666400000");
+        assert_eq!(module.pending_state.take(), Some(BilibiliState { last_view_at: Some(1666500000), backlog: None }));
+        assert_eq!(module.state.last_view_at, None);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_advance_state() {
+        let mut module = bilibili(Some(100));
+        assert_eq!(module.advance_state(None, None, None), BilibiliState { last_view_at: Some(100), backlog: None });
+
+        // Pages are left over, so the cursor stays until they are fetched.
+        module.pending_state.set(Some(module.advance_state(Some(300), Some(250), Some("&max=7&view_at=200&business=archive".to_string()))));
+        module.commit_cycle();
+        assert_eq!(module.state, BilibiliState {
+            last_view_at: Some(100),
+            backlog: Some(Backlog { page: "&max=7&view_at=200&business=archive".to_string(), latest_view_at: 300, skipped_view_at: Some(250) }),
+        });
+        assert_eq!(module.get_request_url(), "https://api.bilibili.com/x/web-interface/history/cursor?ps=20&max=7&view_at=200&business=archive");
+
+        // Once they are, it moves to the latest view, but not past a skipped row.
+        assert_eq!(module.advance_state(Some(200), None, None), BilibiliState { last_view_at: Some(249), backlog: None });
+        module.state.backlog.as_mut().unwrap().skipped_view_at = None;
+        assert_eq!(module.advance_state(Some(200), None, None), BilibiliState { last_view_at: Some(300), backlog: None });
+    }
+
+    #[test]
+    fn test_next_page_url() {
+        let response = RESPONSE.to_string();
+        assert_eq!(bilibili(None).next_page_url(&response), None);
+        assert_eq!(bilibili(Some(1666450000)).next_page_url(&response), None);
+        assert_eq!(
+            bilibili(Some(1666000000)).next_page_url(&response).unwrap(),
+            "https://api.bilibili.com/x/web-interface/history/cursor?ps=20&max=1010&view_at=1666400000&business=archive",
+        );
+        let failure = r#"{"code":-101,"message":"账号未登录","ttl":1}"#.to_string();
+        assert!(matches!(bilibili(None).process_response_into_event_with_id(vec![failure.clone()]), Err(ModuleError::Auth(_))));
+        let blocked = r#"{"code":-412,"message":"请求被拦截","ttl":1}"#.to_string();
+        assert!(matches!(bilibili(None).process_response_into_event_with_id(vec![blocked]), Err(ModuleError::RateLimited(None))));
+        let unknown = r#"{"code":-404,"message":"啥都木有","ttl":1}"#.to_string();
+        assert!(matches!(bilibili(None).process_response_into_event_with_id(vec![unknown]), Err(ModuleError::SourceChanged(_))));
+        assert!(bilibili(None).check_auth_health(StatusCode::OK, "", failure.as_str()).is_some());
+        assert!(bilibili(None).check_auth_health(StatusCode::OK, "", RESPONSE).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_fixture() {
+        let server = StubServer::start(&[
+            ("/x/web-interface/history/cursor?ps=20", "bilibili/page_1.json"),
+            ("/x/web-interface/history/cursor?ps=20&max=1010&view_at=1666400000&business=archive", "bilibili/page_2.json"),
+        ]);
+        let mut module = bilibili(Some(1666300000));
+        module.request_config.url = server.url("/x/web-interface/history/cursor?ps=20");
+        let mut module: Box<dyn Module> = Box::new(module);
+
+        let events = fetch_events(&mut module).await;
+        assert_eq!(server.requests().len(), 2);
+        assert_golden("bilibili/expected.txt", &events, &Utc);
+    }
+}
@@ -1,5 +1,5 @@
-use calendar3::api::{Event, EventDateTime};
-use chrono::{Date, DateTime, Utc};
+use calendar3::api::{Event, EventDateTime, EventSource};
+use chrono::{DateTime, Days, NaiveDate, Utc};
 
 #[derive(Debug)]
 pub struct EventWithId {
@@ -7,29 +7,55 @@ pub struct EventWithId {
     pub description: String,
     pub duration: Duration,
     pub id: String,
+    pub location: Option<String>,
+    // Page of the event at its source, e.g. an online meeting.
+    pub url: Option<String>,
+    // UID the event is known by at its source (e.g. an iCal feed), so that a changed copy updates the
+    // posted event instead of adding another one.
+    pub ical_uid: Option<String>,
 }
 
 #[derive(Debug)]
 pub enum Duration {
     StartEnd(DateTime<Utc>, DateTime<Utc>),
-    WholeDay(Date<Utc>),
+    WholeDay(NaiveDate),
+    // First and last day of the event, both inclusive.
+    MultiDay(NaiveDate, NaiveDate),
+}
+
+// Google Calendar expects the end date of an all-day event to be exclusive.
+fn all_day_range(first: NaiveDate, last: NaiveDate) -> (String, String) {
+    let end = last.checked_add_days(Days::new(1)).unwrap_or(last);
+    (first.format("%Y-%m-%d").to_string(), end.format("%Y-%m-%d").to_string())
 }
 
 impl From<EventWithId> for Event {
     fn from(e: EventWithId) -> Self {
+        let summary = e.summary.clone();
+        let source = e.url.map(|url| EventSource {
+            title: Some(summary),
+            url: Some(url),
+        });
         let ((start_time, start_date), (end_time, end_date)) = match e.duration {
             Duration::StartEnd(start, end) => (
                 (Some(start.to_rfc3339()), None),
                 (Some(end.to_rfc3339()), None),
             ),
-            Duration::WholeDay(day) => (
-                (None, Some(day.format("%Y-%m-%d").to_string())),
-                (None, Some(day.format("%Y-%m-%d").to_string())),
-            ),
+            Duration::WholeDay(day) => {
+                let (start, end) = all_day_range(day, day);
+                ((None, Some(start)), (None, Some(end)))
+            }
+            Duration::MultiDay(first, last) => {
+                let (start, end) = all_day_range(first, last);
+                ((None, Some(start)), (None, Some(end)))
+            }
         };
         Event {
             summary: Some(e.summary),
             description: Some(e.description),
+            location: e.location,
+            i_cal_uid: e.ical_uid,
+            source,
             start: Some(EventDateTime {
                 date_time: start_time,
                 date: start_date,
@@ -43,4 +69,39 @@ impl From<EventWithId> for Event {
             ..Event::default()
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert(duration: Duration) -> (Option<String>, Option<String>) {
+        let event: Event = EventWithId {
+            summary: "".to_string(),
+            description: "".to_string(),
+            duration,
+            id: "".to_string(),
+            location: None,
+            url: None,
+            ical_uid: None,
+        }.into();
+        (event.start.unwrap().date, event.end.unwrap().date)
+    }
+
+    #[test]
+    fn test_whole_day_end_is_exclusive() {
+        let (start, end) = convert(Duration::WholeDay(NaiveDate::from_ymd_opt(2022, 12, 31).unwrap()));
+        assert_eq!(start.unwrap(), "2022-12-31");
+        assert_eq!(end.unwrap(), "2023-01-01");
+    }
+
+    #[test]
+    fn test_multi_day_end_is_exclusive() {
+        let (start, end) = convert(Duration::MultiDay(
+            NaiveDate::from_ymd_opt(2022, 2, 27).unwrap(),
+            NaiveDate::from_ymd_opt(2022, 3, 1).unwrap(),
+        ));
+        assert_eq!(start.unwrap(), "2022-02-27");
+        assert_eq!(end.unwrap(), "2022-03-02");
+    }
+}
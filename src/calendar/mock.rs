@@ -0,0 +1,289 @@
+// In-process stand-in for the part of the Google Calendar API v3 used here: listing, creating and deleting
+// calendars, and inserting, listing, patching and deleting events. Events are kept as the json they were
+// posted as, so that tests see exactly what would have reached Google Calendar.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use async_trait::async_trait;
+use calendar3::{CalendarHub, oauth2};
+use calendar3::api::Event;
+use reqwest::Url;
+use serde_json::{json, Value};
+use tiny_http::Method;
+use yup_oauth2::storage::{TokenInfo, TokenStorage};
+
+use crate::calendar::{CalHub, https_client};
+use crate::common::config::merge;
+
+const ACCESS_TOKEN: &str = "mock-access-token";
+const API_PREFIX: &str = "/calendar/v3/";
+// Id of the calendar every account has, which requests may also call "primary".
+pub const PRIMARY_CALENDAR_ID: &str = "me@example.com";
+
+// Hands out a token which never expires, so that no OAuth flow is started.
+struct FixedToken;
+
+#[async_trait]
+impl TokenStorage for FixedToken {
+    async fn set(&self, _scopes: &[&str], _token: TokenInfo) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn get(&self, _scopes: &[&str]) -> Option<TokenInfo> {
+        Some(TokenInfo {
+            access_token: ACCESS_TOKEN.to_string(),
+            refresh_token: None,
+            expires_at: None,
+            id_token: None,
+        })
+    }
+}
+
+#[derive(Default)]
+struct MockState {
+    calendars: Vec<Value>,
+    // Events by calendar id, deleted ones included as Google keeps them as cancelled.
+    events: HashMap<String, Vec<Value>>,
+    next_id: usize,
+    requests: Vec<String>,
+}
+
+type MockResponse = (u16, Option<Value>);
+
+impl MockState {
+    fn new() -> MockState {
+        let mut state = MockState::default();
+        state.calendars.push(json!({"kind": "calendar#calendar", "id": PRIMARY_CALENDAR_ID, "summary": PRIMARY_CALENDAR_ID}));
+        state.events.insert(PRIMARY_CALENDAR_ID.to_string(), vec![]);
+        state
+    }
+
+    fn handle(&mut self, method: &Method, url: &str, body: &str) -> MockResponse {
+        let parsed = match Url::parse(format!("http://localhost{}", url).as_str()) {
+            Ok(parsed) if parsed.path().starts_with(API_PREFIX) => parsed,
+            _ => return error_response(404, "notFound", "Not Found"),
+        };
+        let query: HashMap<String, String> = parsed.query_pairs().into_owned().collect();
+        let segments: Vec<&str> = parsed.path()[API_PREFIX.len()..].split('/').collect();
+        let body = match body.is_empty() {
+            true => Value::Null,
+            false => match serde_json::from_str::<Value>(body) {
+                Ok(body @ Value::Object(_)) => body,
+                _ => return error_response(400, "parseError", "Parse Error"),
+            },
+        };
+
+        match (method, segments.as_slice()) {
+            (Method::Get, ["users", "me", "calendarList"]) => self.list_calendars(),
+            (Method::Post, ["calendars"]) => self.insert_calendar(body),
+            (Method::Delete, ["calendars", calendar_id]) => self.delete_calendar(calendar_id),
+            (Method::Get, ["calendars", calendar_id, "events"]) => self.list_events(calendar_id, &query),
+            (Method::Post, ["calendars", calendar_id, "events"]) => self.insert_event(calendar_id, body),
+            (Method::Patch, ["calendars", calendar_id, "events", event_id]) => self.patch_event(calendar_id, event_id, body),
+            (Method::Delete, ["calendars", calendar_id, "events", event_id]) => self.delete_event(calendar_id, event_id),
+            _ => error_response(404, "notFound", "Not Found"),
+        }
+    }
+
+    fn generate_id(&mut self, prefix: &str) -> String {
+        self.next_id += 1;
+        format!("{}{}", prefix, self.next_id)
+    }
+
+    fn list_calendars(&self) -> MockResponse {
+        let items: Vec<Value> = self.calendars.iter()
+            .map(|calendar| json!({
+                "kind": "calendar#calendarListEntry",
+                "id": calendar["id"],
+                "summary": calendar["summary"],
+                "accessRole": "owner",
+                "primary": calendar["id"] == PRIMARY_CALENDAR_ID,
+            }))
+            .collect();
+        (200, Some(json!({"kind": "calendar#calendarList", "items": items})))
+    }
+
+    fn insert_calendar(&mut self, mut calendar: Value) -> MockResponse {
+        if calendar.get("summary").and_then(Value::as_str).is_none() {
+            return error_response(400, "required", "Missing title.");
+        }
+        let id = format!("{}@group.calendar.google.com", self.generate_id("mock"));
+        calendar["kind"] = json!("calendar#calendar");
+        calendar["id"] = json!(id);
+        self.calendars.push(calendar.clone());
+        self.events.insert(id, vec![]);
+        (200, Some(calendar))
+    }
+
+    fn delete_calendar(&mut self, calendar_id: &str) -> MockResponse {
+        let calendar_id = resolve(calendar_id);
+        if calendar_id == PRIMARY_CALENDAR_ID {
+            return error_response(400, "cannotDeletePrimaryCalendar", "Cannot delete primary calendar.");
+        }
+        match self.calendars.iter().position(|calendar| calendar["id"] == calendar_id) {
+            Some(i) => {
+                self.calendars.remove(i);
+                self.events.remove(calendar_id);
+                (204, None)
+            }
+            None => error_response(404, "notFound", "Not Found"),
+        }
+    }
+
+    fn list_events(&self, calendar_id: &str, query: &HashMap<String, String>) -> MockResponse {
+        let events = match self.events.get(resolve(calendar_id)) {
+            Some(events) => events,
+            None => return error_response(404, "notFound", "Not Found"),
+        };
+        let show_deleted = query.get("showDeleted").map(String::as_str) == Some("true");
+        let items: Vec<&Value> = events.iter()
+            .filter(|event| show_deleted || event["status"] != "cancelled")
+            .filter(|event| query.get("iCalUID").is_none_or(|ical_uid| event["iCalUID"] == ical_uid.as_str()))
+            .collect();
+        (200, Some(json!({"kind": "calendar#events", "summary": calendar_id, "items": items})))
+    }
+
+    fn insert_event(&mut self, calendar_id: &str, mut event: Value) -> MockResponse {
+        let calendar_id = resolve(calendar_id);
+        if !self.events.contains_key(calendar_id) {
+            return error_response(404, "notFound", "Not Found");
+        }
+        if event.get("start").is_none() || event.get("end").is_none() {
+            return error_response(400, "required", "Missing time range.");
+        }
+        let ical_uid = event.get("iCalUID").cloned();
+        if ical_uid.is_some() && self.events[calendar_id].iter().any(|existing| existing.get("iCalUID") == ical_uid.as_ref()) {
+            return error_response(409, "duplicate", "The requested identifier already exists.");
+        }
+        let id = self.generate_id("event");
+        event["kind"] = json!("calendar#event");
+        event["iCalUID"] = ical_uid.unwrap_or_else(|| json!(format!("{}@google.com", id)));
+        event["id"] = json!(id);
+        if event.get("status").is_none() {
+            event["status"] = json!("confirmed");
+        }
+        self.events.get_mut(calendar_id).unwrap().push(event.clone());
+        (200, Some(event))
+    }
+
+    fn patch_event(&mut self, calendar_id: &str, event_id: &str, patch: Value) -> MockResponse {
+        match self.find_event(calendar_id, event_id) {
+            Some(event) => {
+                merge(event, patch);
+                (200, Some(event.clone()))
+            }
+            None => error_response(404, "notFound", "Not Found"),
+        }
+    }
+
+    fn delete_event(&mut self, calendar_id: &str, event_id: &str) -> MockResponse {
+        match self.find_event(calendar_id, event_id) {
+            Some(event) if event["status"] == "cancelled" => error_response(410, "deleted", "Resource has been deleted"),
+            Some(event) => {
+                event["status"] = json!("cancelled");
+                (204, None)
+            }
+            None => error_response(404, "notFound", "Not Found"),
+        }
+    }
+
+    fn find_event(&mut self, calendar_id: &str, event_id: &str) -> Option<&mut Value> {
+        self.events.get_mut(resolve(calendar_id))?.iter_mut().find(|event| event["id"] == event_id)
+    }
+}
+
+fn resolve(calendar_id: &str) -> &str {
+    match calendar_id {
+        "primary" => PRIMARY_CALENDAR_ID,
+        _ => calendar_id,
+    }
+}
+
+// Error in the shape Google returns, which the hub turns into `Error::BadRequest`.
+fn error_response(code: u16, reason: &str, message: &str) -> MockResponse {
+    (code, Some(json!({"error": {
+        "code": code,
+        "message": message,
+        "errors": [{"domain": "global", "reason": reason, "message": message}],
+    }})))
+}
+
+// Serves the mock API on a local port until dropped; point a hub at it with `hub()`.
+pub struct MockCalendarServer {
+    server: Arc<tiny_http::Server>,
+    state: Arc<Mutex<MockState>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MockCalendarServer {
+    pub fn start() -> MockCalendarServer {
+        let server = Arc::new(tiny_http::Server::http("127.0.0.1:0").unwrap());
+        let state = Arc::new(Mutex::new(MockState::new()));
+        let thread = {
+            let server = server.clone();
+            let state = state.clone();
+            thread::spawn(move || {
+                for mut request in server.incoming_requests() {
+                    let url = request.url().to_string();
+                    let method = request.method().clone();
+                    let authorized = request.headers().iter()
+                        .any(|header| header.field.equiv("Authorization") && header.value.as_str() == format!("Bearer {}", ACCESS_TOKEN));
+                    let mut body = String::new();
+                    let (code, content) = match request.as_reader().read_to_string(&mut body) {
+                        Err(_) => error_response(400, "parseError", "Parse Error"),
+                        Ok(_) if !authorized => error_response(401, "authError", "Invalid Credentials"),
+                        Ok(_) => {
+                            let mut state = state.lock().unwrap();
+                            state.requests.push(format!("{} {}", method, url));
+                            state.handle(&method, url.as_str(), body.as_str())
+                        }
+                    };
+                    let response = match content {
+                        Some(content) => tiny_http::Response::from_string(content.to_string())
+                            .with_header("Content-Type: application/json; charset=UTF-8".parse::<tiny_http::Header>().unwrap()),
+                        None => tiny_http::Response::from_string(""),
+                    };
+                    let _ = request.respond(response.with_status_code(code));
+                }
+            })
+        };
+        MockCalendarServer { server, state, thread: Some(thread) }
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.server.server_addr().to_ip().unwrap())
+    }
+
+    // Hub sending its requests to this server, authorized without any OAuth flow.
+    pub async fn hub(&self) -> CalHub {
+        let auth = oauth2::InstalledFlowAuthenticator::builder(oauth2::ApplicationSecret::default(), oauth2::InstalledFlowReturnMethod::Interactive)
+            .with_storage(Box::new(FixedToken)).build().await.unwrap();
+        let mut hub = CalendarHub::new(https_client(), auth);
+        hub.base_url(format!("{}{}", self.url(), API_PREFIX));
+        hub.root_url(format!("{}/", self.url()));
+        hub
+    }
+
+    // Events of the calendar in the order they were inserted, deleted ones included.
+    pub fn events(&self, calendar_id: &str) -> Vec<Event> {
+        self.state.lock().unwrap().events.get(resolve(calendar_id)).into_iter().flatten()
+            .map(|event| serde_json::from_value(event.clone()).unwrap())
+            .collect()
+    }
+
+    // Authorized requests received so far, e.g. "PATCH /calendar/v3/calendars/primary/events/event1".
+    pub fn requests(&self) -> Vec<String> {
+        self.state.lock().unwrap().requests.clone()
+    }
+}
+
+impl Drop for MockCalendarServer {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -1,30 +1,45 @@
-use calendar3::{CalendarHub, Error, oauth2};
+use calendar3::{CalendarHub, oauth2};
 use calendar3::api::Event;
 use hyper::client::HttpConnector;
 use hyper_rustls::HttpsConnector;
 
 use crate::common::RequestConfig;
+use crate::common::secrets::restrict_permissions;
 
 pub mod event;
+#[cfg(test)]
+pub mod mock;
 
 pub type CalHub = CalendarHub<HttpsConnector<HttpConnector>>;
 
-pub async fn calendar_post(hub: &mut CalHub, config: &RequestConfig, req: Event) {
-    let result = hub.events().insert(req, config.calendar_id.as_str()).doit().await;
+// Where synced events end up: Google Calendar through the hub, or a stand-in recording them in tests.
+pub trait CalendarSink {
+    // Whether the event made it to the calendar; failures are logged by the sink.
+    async fn post(&mut self, config: &RequestConfig, event: Event) -> bool;
+}
+
+impl CalendarSink for CalHub {
+    async fn post(&mut self, config: &RequestConfig, event: Event) -> bool {
+        calendar_post(self, config, event).await
+    }
+}
+
+pub async fn calendar_post(hub: &mut CalHub, config: &RequestConfig, mut req: Event) -> bool {
+    let result = match find_posted_event(hub, config, &req).await {
+        Some(event_id) => {
+            debug!("Event with iCalUID {:?} is already posted; updating.", req.i_cal_uid);
+            // Also restores the event if it has been deleted in the meantime.
+            req.status = Some("confirmed".to_string());
+            hub.events().patch(req, config.calendar_id.as_str(), event_id.as_str()).doit().await
+        }
+        None => hub.events().insert(req, config.calendar_id.as_str()).doit().await,
+    };
 
     match result {
-        Err(e) => match e {
-            Error::HttpError(_)
-            | Error::MissingAPIKey
-            | Error::MissingToken(_)
-            | Error::Cancelled
-            | Error::UploadSizeLimitExceeded(_, _)
-            | Error::Failure(_)
-            | Error::BadRequest(_)
-            | Error::FieldClash(_)
-            | Error::JsonDecodeError(_, _) => error!("Error occurred in posting an event: {}.", e),
-            _ => {}
-        },
+        Err(e) => {
+            error!("Error occurred in posting an event: {}.", e);
+            false
+        }
         Ok((_res, event)) => {
             info!("Success in posting an event \"{}\" which starts at {}.", match &event.summary {
                 Some(str) => str.clone(),
@@ -42,24 +57,52 @@ pub async fn calendar_post(hub: &mut CalHub, config: &RequestConfig, req: Event)
                 None => "[No start time]".to_string(),
             });
             debug!("Detail info about this event: {:?}.", &event);
+            true
+        }
+    }
+}
+
+// Id of the event in the calendar sharing the iCalUID of `req`, if any.
+async fn find_posted_event(hub: &CalHub, config: &RequestConfig, req: &Event) -> Option<String> {
+    let ical_uid = req.i_cal_uid.as_deref()?;
+    let result = hub.events().list(config.calendar_id.as_str())
+        .i_cal_uid(ical_uid)
+        .show_deleted(true)
+        .doit().await;
+    match result {
+        Ok((_res, events)) => events.items?.into_iter().next()?.id,
+        Err(e) => {
+            warn!("Cannot look up the event with iCalUID {} where the error {} is raised; inserting.", ical_uid, e);
+            None
         }
     }
 }
 
 pub async fn init_hub() -> CalHub {
+    restrict_permissions("config/clientsecret.json");
+    restrict_permissions("config/tokenstorage.json");
     let secret: oauth2::ApplicationSecret = yup_oauth2::read_application_secret("config/clientsecret.json")
         .await.expect("client secret not found!");
     let auth = oauth2::InstalledFlowAuthenticator::builder(secret, oauth2::InstalledFlowReturnMethod::Interactive)
         .persist_tokens_to_disk("config/tokenstorage.json").build().await.unwrap();
-    let hub = CalendarHub::new(hyper::Client::builder().build(hyper_rustls::HttpsConnectorBuilder::new().with_native_roots().https_or_http().enable_http1().enable_http2().build()), auth);
-    hub
+    CalendarHub::new(https_client(), auth)
+}
+
+fn https_client() -> hyper::Client<HttpsConnector<HttpConnector>> {
+    hyper::Client::builder().build(hyper_rustls::HttpsConnectorBuilder::new().with_native_roots().https_or_http().enable_http1().enable_http2().build())
 }
 
 #[cfg(test)]
 mod tests {
+    use calendar3::api::{Event, EventDateTime};
     use yup_oauth2::{InstalledFlowAuthenticator, InstalledFlowReturnMethod};
 
+    use crate::calendar::calendar_post;
+    use crate::calendar::mock::{MockCalendarServer, PRIMARY_CALENDAR_ID};
+    use crate::common::{RequestConfig};
+
     #[tokio::test]
+    #[ignore = "signs in to Google interactively; needs config/clientsecret.json"]
     async fn test_yup_oauth2() {
         // From the official test example.
         env_logger::init();
@@ -75,4 +118,44 @@ mod tests {
             Err(e) => println!("error: {:?}", e),
         }
     }
-}
\ No newline at end of file
+
+    // Posting an event again, even after it has been deleted in the calendar, updates it in place.
+    #[tokio::test]
+    async fn test_calendar_post() {
+        let calendar = MockCalendarServer::start();
+        let mut hub = calendar.hub().await;
+        let config = RequestConfig { calendar_id: "primary".to_string(), ..RequestConfig::for_test(&calendar.url()) };
+        let event = |summary: &str, ical_uid: Option<&str>| Event {
+            summary: Some(summary.to_string()),
+            start: Some(EventDateTime { date: Some("2022-11-04".to_string()), ..EventDateTime::default() }),
+            end: Some(EventDateTime { date: Some("2022-11-05".to_string()), ..EventDateTime::default() }),
+            i_cal_uid: ical_uid.map(str::to_string),
+            ..Event::default()
+        };
+
+        assert!(calendar_post(&mut hub, &config, event("First", Some("abc@example.com"))).await);
+        assert!(calendar_post(&mut hub, &config, event("Second", Some("abc@example.com"))).await);
+        let posted = calendar.events(PRIMARY_CALENDAR_ID);
+        assert_eq!(posted.len(), 1);
+        assert_eq!(posted[0].summary.as_deref(), Some("Second"));
+
+        hub.events().delete("primary", posted[0].id.as_deref().unwrap()).doit().await.unwrap();
+        assert_eq!(calendar.events(PRIMARY_CALENDAR_ID)[0].status.as_deref(), Some("cancelled"));
+        calendar_post(&mut hub, &config, event("Third", Some("abc@example.com"))).await;
+        let posted = calendar.events(PRIMARY_CALENDAR_ID);
+        assert_eq!(posted.len(), 1);
+        assert_eq!(posted[0].summary.as_deref(), Some("Third"));
+        assert_eq!(posted[0].status.as_deref(), Some("confirmed"));
+
+        // Events without an iCalUID cannot be matched and are always inserted.
+        calendar_post(&mut hub, &config, event("Fourth", None)).await;
+        calendar_post(&mut hub, &config, event("Fourth", None)).await;
+        assert_eq!(calendar.events(PRIMARY_CALENDAR_ID).len(), 3);
+        let requests = calendar.requests();
+        assert_eq!(requests.iter().filter(|request| request.starts_with("POST ")).count(), 3);
+        assert_eq!(requests.iter().filter(|request| request.starts_with("PATCH ")).count(), 2);
+
+        let missing = RequestConfig { calendar_id: "missing@group.calendar.google.com".to_string(), ..config };
+        assert!(!calendar_post(&mut hub, &missing, event("Fifth", None)).await);
+    }
+}
@@ -0,0 +1,313 @@
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+
+use crate::common::{Module, read_json};
+use crate::common::diagnostic::Diagnostic;
+use crate::common::secrets::redact_url;
+
+const ARCHIVE_DIRECTORY: &str = "archive";
+const ARCHIVE_CONFIG_FILE: &str = "config/archive.json";
+const INDEX_FILE: &str = "index.json";
+const DIAGNOSTICS_FILE: &str = "diagnostics.json";
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+struct ArchiveConfig {
+    enabled: bool,
+    // Cycles older than this are removed.
+    max_age_days: u64,
+    // Oldest cycles are removed until the whole archive fits.
+    max_size_mb: u64,
+}
+
+impl Default for ArchiveConfig {
+    fn default() -> Self {
+        ArchiveConfig {
+            enabled: true,
+            max_age_days: 7,
+            max_size_mb: 100,
+        }
+    }
+}
+
+fn read_archive_config() -> ArchiveConfig {
+    read_json::<ArchiveConfig>(ARCHIVE_CONFIG_FILE).unwrap_or_default()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResponseKind {
+    // Response handed to `Module::setup`.
+    Setup,
+    // The regular request and the pages following it.
+    Page,
+    // Requested by `Module::need_for_detail`.
+    Detail,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct ArchivedResponse {
+    kind: ResponseKind,
+    // Redacted, as urls may carry api keys.
+    url: String,
+    file: String,
+    // Number of details a page is replaced by, as asked for by `Module::need_for_detail`; None for a
+    // page which is parsed itself.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    details: Option<usize>,
+}
+
+// Raw responses of one sync cycle of a module, kept in `archive/<identifier>/<time>/` along with an
+// index of the urls they come from, so that the cycle can be replayed offline.
+pub struct ResponseArchive {
+    directory: Option<PathBuf>,
+    entries: Vec<ArchivedResponse>,
+}
+
+impl ResponseArchive {
+    pub fn new(identifier: &str) -> ResponseArchive {
+        if !read_archive_config().enabled {
+            return ResponseArchive::disabled();
+        }
+        let time = Local::now().format("%Y%m%d-%H%M%S-%3f").to_string();
+        ResponseArchive {
+            directory: Some(Path::new(ARCHIVE_DIRECTORY).join(identifier).join(time)),
+            entries: vec![],
+        }
+    }
+
+    pub fn disabled() -> ResponseArchive {
+        ResponseArchive {
+            directory: None,
+            entries: vec![],
+        }
+    }
+
+    // Archiving never fails the cycle; problems are only logged.
+    pub fn record(&mut self, kind: ResponseKind, url: &str, response: &str) {
+        let directory = match &self.directory {
+            Some(directory) => directory,
+            None => return,
+        };
+        let file = format!("{:03}.txt", self.entries.len());
+        self.entries.push(ArchivedResponse { kind, url: redact_url(url), file: file.clone(), details: None });
+        let result = fs::create_dir_all(directory)
+            .and_then(|_| fs::write(directory.join(file), response))
+            .and_then(|_| self.write_index(directory));
+        if let Err(e) = result {
+            warn!("Cannot archive the response of {} where the error {} is raised.", redact_url(url), e);
+        }
+    }
+
+    // Tells that the `page`-th page (counted from 0) is replaced by the next `count` details.
+    pub fn record_details(&mut self, page: usize, count: usize) {
+        let directory = match &self.directory {
+            Some(directory) => directory,
+            None => return,
+        };
+        if let Some(entry) = self.entries.iter_mut().filter(|entry| entry.kind == ResponseKind::Page).nth(page) {
+            entry.details = Some(count);
+        }
+        if let Err(e) = self.write_index(directory) {
+            warn!("Cannot archive the index of the responses where the error {} is raised.", e);
+        }
+    }
+
+    fn write_index(&self, directory: &Path) -> Result<(), std::io::Error> {
+        fs::write(directory.join(INDEX_FILE), serde_json::to_string(&self.entries)?)
+    }
+
+    // Rows skipped when parsing the responses, to be compared with the ones of a replay.
+    pub fn record_diagnostics(&mut self, diagnostics: &[Diagnostic]) {
+        let directory = match &self.directory {
+            Some(directory) => directory,
+            None => return,
+        };
+        let result = fs::create_dir_all(directory)
+            .and_then(|_| fs::write(directory.join(DIAGNOSTICS_FILE), serde_json::to_string_pretty(diagnostics)?));
+        if let Err(e) = result {
+            warn!("Cannot archive the skipped rows where the error {} is raised.", e);
+        }
+    }
+}
+
+// Rows skipped when the archived cycle at `path` was parsed; none for a single saved response or a cycle
+// which skipped nothing.
+pub fn read_archived_diagnostics(path: &str) -> Result<Vec<Diagnostic>, Box<dyn Error>> {
+    let path = Path::new(path).join(DIAGNOSTICS_FILE);
+    if !path.is_file() {
+        return Ok(vec![]);
+    }
+    Ok(read_json::<Vec<Diagnostic>>(path.to_string_lossy().as_ref())?)
+}
+
+// Removes archived cycles older than the configured age, and then the oldest ones until the archive fits
+// in the configured size.
+pub fn prune_archive() {
+    let config = read_archive_config();
+    let mut cycles: Vec<(PathBuf, SystemTime, u64)> = fs::read_dir(ARCHIVE_DIRECTORY).into_iter().flatten()
+        .filter_map(|module_directory| fs::read_dir(module_directory.ok()?.path()).ok())
+        .flatten()
+        .filter_map(|cycle| {
+            let path = cycle.ok()?.path();
+            let modified = fs::metadata(&path).ok()?.modified().ok()?;
+            let size = fs::read_dir(&path).ok()?
+                .filter_map(|file| file.ok()?.metadata().ok())
+                .map(|metadata| metadata.len())
+                .sum();
+            Some((path, modified, size))
+        })
+        .collect();
+    cycles.sort_by_key(|(_, modified, _)| *modified);
+
+    let max_age = Duration::from_secs(config.max_age_days * 24 * 60 * 60);
+    let mut total_size: u64 = cycles.iter().map(|(_, _, size)| size).sum();
+    for (path, modified, size) in cycles {
+        let expired = modified.elapsed().map(|age| age > max_age).unwrap_or(false);
+        if !expired && total_size <= config.max_size_mb * 1024 * 1024 {
+            break;
+        }
+        match fs::remove_dir_all(&path) {
+            Ok(_) => {
+                debug!("Removed archived responses {}.", path.display());
+                total_size -= size;
+            }
+            Err(e) => warn!("Cannot remove archived responses {} where the error {} is raised.", path.display(), e),
+        }
+    }
+}
+
+// Responses to be handed to `process_response_into_event_with_id` when replaying `path`: either a single
+// saved response, or an archived cycle, whose pages are replaced by their details in the order they were
+// archived. Pages still go through `need_for_detail`, for modules keeping track of the details asked for,
+// which only tells how many there are for archives made before their number was recorded.
+pub fn read_archived_responses(module: &mut Box<dyn Module>, path: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    let path = Path::new(path);
+    if path.is_file() {
+        return Ok(vec![fs::read_to_string(path)?]);
+    }
+    let entries = read_json::<Vec<ArchivedResponse>>(path.join(INDEX_FILE).to_string_lossy().as_ref())?;
+    let read = |entry: &ArchivedResponse| fs::read_to_string(path.join(entry.file.as_str()));
+
+    for entry in entries.iter().filter(|entry| entry.kind == ResponseKind::Setup) {
+        module.setup(read(entry)?)?;
+    }
+    let mut details = entries.iter().filter(|entry| entry.kind == ResponseKind::Detail);
+    let mut responses = vec![];
+    for entry in entries.iter().filter(|entry| entry.kind == ResponseKind::Page) {
+        let page = read(entry)?;
+        let asked_for = module.need_for_detail(&page).map(|urls| urls.len());
+        match entry.details.or(asked_for) {
+            Some(count) => {
+                for _ in 0..count {
+                    let detail = details.next().ok_or_else(|| format!("{} holds fewer details than its pages ask for.", INDEX_FILE))?;
+                    responses.push(read(detail)?);
+                }
+            }
+            None => responses.push(page),
+        }
+    }
+    Ok(responses)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::error::Error;
+    use std::fs;
+
+    use crate::common::{Module, read_json, RequestConfig};
+    use crate::common::archive::{ArchivedResponse, INDEX_FILE, read_archived_diagnostics, read_archived_responses, ResponseArchive, ResponseKind};
+    use crate::common::diagnostic::{Diagnostic, ParsedEvents};
+    use crate::common::error::ModuleError;
+
+    // Pages like "list:a,b" ask for the details "/a" and "/b"; any other page is parsed itself.
+    struct Listing {
+        request_config: RequestConfig,
+        event_ids: HashSet<String>,
+    }
+
+    impl Module for Listing {
+        fn new(_instance: Option<&str>, _calendar_id: Option<String>) -> Result<Box<dyn Module>, Box<dyn Error>> {
+            Err("not loaded from config".into())
+        }
+
+        fn dump(&self) {}
+
+        fn get_config(&self) -> &RequestConfig {
+            &self.request_config
+        }
+
+        fn get_event_ids(&mut self) -> &mut HashSet<String> {
+            &mut self.event_ids
+        }
+
+        fn get_identifier(&self) -> &str {
+            "listing"
+        }
+
+        fn get_request_url(&self) -> String {
+            "/list".to_string()
+        }
+
+        fn need_for_detail(&self, response: &String) -> Option<Vec<String>> {
+            let names = response.strip_prefix("list:")?;
+            Some(names.split(',').map(|name| format!("/{}", name)).collect())
+        }
+
+        fn process_response_into_event_with_id(&self, _responses: Vec<String>) -> Result<ParsedEvents, ModuleError> {
+            Ok(ParsedEvents::default())
+        }
+    }
+
+    #[test]
+    fn test_record() {
+        let directory = std::env::temp_dir().join("calendar_as_diary_test_archive");
+        let _ = fs::remove_dir_all(&directory);
+        let mut archive = ResponseArchive { directory: Some(directory.clone()), entries: vec![] };
+        archive.record(ResponseKind::Page, "https://example.com/list?api_key=abc", "<ul></ul>");
+        archive.record(ResponseKind::Detail, "https://example.com/1", "<h1>1</h1>");
+
+        let entries = read_json::<Vec<ArchivedResponse>>(directory.join(INDEX_FILE).to_string_lossy().as_ref()).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].url, "https://example.com/list?api_key=%5Bredacted%5D");
+        assert_eq!(entries[1].kind, ResponseKind::Detail);
+        assert_eq!(fs::read_to_string(directory.join(entries[1].file.as_str())).unwrap(), "<h1>1</h1>");
+
+        let diagnostic = Diagnostic { row: 1, reason: "cannot parse title: h1 is missing".to_string(), snippet: "<h2>1</h2>".to_string() };
+        archive.record_diagnostics(std::slice::from_ref(&diagnostic));
+        assert_eq!(read_archived_diagnostics(directory.to_str().unwrap()).unwrap(), vec![diagnostic]);
+        let response = directory.join(entries[1].file.as_str());
+        assert!(read_archived_diagnostics(response.to_str().unwrap()).unwrap().is_empty());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_read_archived_responses() {
+        let directory = std::env::temp_dir().join("calendar_as_diary_test_replay");
+        let _ = fs::remove_dir_all(&directory);
+        let mut archive = ResponseArchive { directory: Some(directory.clone()), entries: vec![] };
+        archive.record(ResponseKind::Page, "/list?api_key=abc", "list:a,b");
+        archive.record(ResponseKind::Page, "/list?page=2", "plain");
+        // Asks for fewer details now than when the cycle was archived.
+        archive.record(ResponseKind::Page, "/list?page=3", "list:c");
+        archive.record_details(0, 2);
+        archive.record_details(2, 2);
+        for (url, detail) in [("/a", "A"), ("/b", "B"), ("/c", "C"), ("/d", "D")] {
+            archive.record(ResponseKind::Detail, url, detail);
+        }
+
+        let mut module: Box<dyn Module> = Box::new(Listing {
+            request_config: RequestConfig::for_test("/list"),
+            event_ids: HashSet::new(),
+        });
+        let responses = read_archived_responses(&mut module, directory.to_string_lossy().as_ref()).unwrap();
+        assert_eq!(responses, vec!["A", "B", "plain", "C", "D"]);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
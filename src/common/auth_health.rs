@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+use std::process::Command;
+
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+
+use crate::common::{ensure_directory, read_dumped_json, read_json, write_json};
+
+const AUTH_STATUS_FILE: &str = "dump/auth_status.json";
+const NOTIFICATION_CONFIG_FILE: &str = "config/notification.json";
+
+// Kept for every module instance which needs to be signed in again, e.g. after its cookie expired.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AuthStatus {
+    pub reason: String,
+    pub since: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct NotificationConfig {
+    // Program and leading arguments run when a module needs to be signed in again; the message is
+    // passed as the last argument, e.g. ["notify-send", "Calendar as diary"].
+    command: Vec<String>,
+}
+
+pub fn read_auth_statuses() -> HashMap<String, AuthStatus> {
+    read_dumped_json::<HashMap<String, AuthStatus>>(AUTH_STATUS_FILE).unwrap_or_default()
+}
+
+fn write_auth_statuses(statuses: &HashMap<String, AuthStatus>) {
+    ensure_directory("dump");
+    if let Err(e) = write_json(AUTH_STATUS_FILE, statuses) {
+        warn!("Cannot dump json to file {} where the error {} is raised!", AUTH_STATUS_FILE, e);
+    }
+}
+
+// Records that the module needs to be signed in again; the notification is only sent the first time.
+pub fn mark_needs_reauth(identifier: &str, reason: &str) {
+    let mut statuses = read_auth_statuses();
+    error!("Module {} needs to be signed in again: {}.", identifier, reason);
+    if statuses.contains_key(identifier) {
+        return;
+    }
+    statuses.insert(identifier.to_string(), AuthStatus {
+        reason: reason.to_string(),
+        since: Local::now().format("%Y-%m-%d %H:%M").to_string(),
+    });
+    write_auth_statuses(&statuses);
+    notify(format!("Module {} needs to be signed in again: {}.", identifier, reason).as_str());
+}
+
+pub fn mark_authenticated(identifier: &str) {
+    let mut statuses = read_auth_statuses();
+    if statuses.remove(identifier).is_some() {
+        info!("Module {} is signed in again.", identifier);
+        write_auth_statuses(&statuses);
+    }
+}
+
+// Runs the configured notification command, if any.
+pub fn notify(message: &str) {
+    let config = match read_json::<NotificationConfig>(NOTIFICATION_CONFIG_FILE) {
+        Ok(config) => config,
+        Err(_) => return,
+    };
+    let (program, args) = match config.command.split_first() {
+        Some(command) => command,
+        None => return,
+    };
+    match Command::new(program).args(args).arg(message).spawn() {
+        Ok(_) => debug!("Notification sent through {}.", program),
+        Err(e) => warn!("Cannot send notification through {} where the error {} is raised.", program, e),
+    }
+}
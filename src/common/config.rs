@@ -0,0 +1,304 @@
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+
+use lazy_static::lazy_static;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use regex::Regex;
+use serde_json::{Map, Value};
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::common::module_of_instance;
+
+// Prefix of environment overrides, e.g. `CALENDAR_AS_DIARY__BILIBILI_ALICE__HEADERS__COOKIE` sets the
+// cookie header of the instance `bilibili.alice`.
+const ENV_PREFIX: &str = "CALENDAR_AS_DIARY";
+const ENV_SEPARATOR: &str = "__";
+
+const CONFIG_DIRECTORY: &str = "config";
+// Files in the config directory written by the daemon itself, which do not call for a reload.
+const UNWATCHED_FILES: [&str; 2] = ["tokenstorage.json", "tokencache.json"];
+
+// Every problem found in the config of a module instance, so that all of them can be fixed at once.
+#[derive(Debug)]
+pub struct ConfigError {
+    pub problems: Vec<String>,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "config has {} problem(s):", self.problems.len())?;
+        for problem in &self.problems {
+            write!(f, "\n  - {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for ConfigError {}
+
+impl ConfigError {
+    pub fn check(problems: Vec<String>) -> Result<(), ConfigError> {
+        match problems.is_empty() {
+            true => Ok(()),
+            false => Err(ConfigError { problems }),
+        }
+    }
+}
+
+// Config of a module instance: the module defaults in `config/<module>.json.default`, overridden by the
+// instance file `config/<identifier>.json` and then by environment variables. Objects are merged key by
+// key, while any other value replaces the one below it.
+pub fn layered_config(identifier: &str) -> Result<Value, ConfigError> {
+    let mut problems = vec![];
+    let default_path = format!("{}/{}.json.default", CONFIG_DIRECTORY, module_of_instance(identifier));
+    let mut config = read_layer(default_path.as_str(), &mut problems).unwrap_or_else(|| Value::Object(Map::new()));
+
+    let overrides = env_overrides(identifier);
+    let instance_path = format!("{}/{}.json", CONFIG_DIRECTORY, identifier);
+    if fs::metadata(instance_path.as_str()).is_ok() {
+        if let Some(layer) = read_layer(instance_path.as_str(), &mut problems) {
+            merge(&mut config, layer);
+        }
+    } else if overrides.is_empty() {
+        problems.push(format!("{} is missing; copy it from {} and fill it in.", instance_path, default_path));
+    }
+    for (path, value) in overrides {
+        set_override(&mut config, path.as_slice(), value);
+    }
+
+    ConfigError::check(problems)?;
+    Ok(config)
+}
+
+fn read_layer(path: &str, problems: &mut Vec<String>) -> Option<Value> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) => {
+            problems.push(format!("cannot read {}: {}", path, e));
+            return None;
+        }
+    };
+    match serde_json::from_str::<Value>(content.as_str()) {
+        Ok(layer @ Value::Object(_)) => Some(layer),
+        Ok(_) => {
+            problems.push(format!("{} is not a json object.", path));
+            None
+        }
+        Err(e) => {
+            problems.push(format!("{} is not valid json: {}", path, e));
+            None
+        }
+    }
+}
+
+pub fn merge(base: &mut Value, layer: Value) {
+    match (base, layer) {
+        (Value::Object(base), Value::Object(layer)) => {
+            for (key, value) in layer {
+                match base.get_mut(key.as_str()) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, layer) => *base = layer,
+    }
+}
+
+#[cfg(test)]
+thread_local! {
+    // Overrides set by the tests running on this thread, in the form of environment variables, so that
+    // tests running in parallel leave the environment of the process alone.
+    static TEST_OVERRIDES: std::cell::RefCell<Vec<(String, String)>> = const { std::cell::RefCell::new(vec![]) };
+}
+
+// Sets e.g. `URL` for the instance in the modules created on this thread from now on.
+#[cfg(test)]
+pub fn set_test_override(identifier: &str, key: &str, value: String) {
+    let name = format!("{}{}", env_prefix(identifier), key);
+    TEST_OVERRIDES.with(|overrides| {
+        let mut overrides = overrides.borrow_mut();
+        overrides.retain(|(existing, _)| *existing != name);
+        overrides.push((name, value));
+    });
+}
+
+#[cfg(test)]
+pub fn clear_test_overrides(identifier: &str) {
+    let prefix = env_prefix(identifier);
+    TEST_OVERRIDES.with(|overrides| overrides.borrow_mut().retain(|(name, _)| !name.starts_with(prefix.as_str())));
+}
+
+fn env_variables() -> Vec<(String, String)> {
+    let variables = env::vars();
+    #[cfg(test)]
+    let variables = variables.chain(TEST_OVERRIDES.with(|overrides| overrides.borrow().clone()));
+    variables.collect()
+}
+
+fn env_prefix(identifier: &str) -> String {
+    format!("{}{}{}{}", ENV_PREFIX, ENV_SEPARATOR, identifier.replace('.', "_").to_uppercase(), ENV_SEPARATOR)
+}
+
+// Key paths and values of the environment variables aimed at the instance, with keys lowercased. Names of
+// environment variables cannot hold `-`, so `_` in a header name stands for it, e.g. `HEADERS__USER_AGENT`
+// for `user-agent`; header names hardly ever hold `_` themselves.
+fn env_overrides(identifier: &str) -> Vec<(Vec<String>, String)> {
+    let prefix = env_prefix(identifier);
+    let mut overrides: Vec<(Vec<String>, String)> = env_variables().into_iter()
+        .filter_map(|(name, value)| {
+            let mut path: Vec<String> = name.strip_prefix(prefix.as_str())?.split(ENV_SEPARATOR).map(str::to_lowercase).collect();
+            if let [headers, name] = path.as_mut_slice() {
+                if headers == "headers" {
+                    *name = name.replace('_', "-");
+                }
+            }
+            Some((path, value))
+        })
+        .collect();
+    overrides.sort();
+    overrides
+}
+
+// Strings stay strings, so that e.g. a numeric cookie is not taken as a number; other values are read as
+// json if possible.
+fn set_override(config: &mut Value, path: &[String], value: String) {
+    let (key, rest) = match path.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    if !config.is_object() {
+        *config = Value::Object(Map::new());
+    }
+    let entry = config.as_object_mut().unwrap().entry(key.as_str()).or_insert(Value::Null);
+    if !rest.is_empty() {
+        return set_override(entry, rest, value);
+    }
+    *entry = match entry {
+        Value::String(_) => Value::String(value),
+        _ => serde_json::from_str(value.as_str()).unwrap_or(Value::String(value)),
+    };
+}
+
+// Signals `sender` whenever a file in the config directory changes; the watcher stops once dropped.
+pub fn watch_config(sender: UnboundedSender<()>) -> Option<RecommendedWatcher> {
+    let watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let event = match event {
+            Ok(event) => event,
+            Err(e) => return warn!("Error raised in watching the config directory: {}.", e),
+        };
+        let relevant = matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_))
+            && event.paths.iter().any(|path| {
+                path.file_name().and_then(|name| name.to_str()).is_some_and(|name| !UNWATCHED_FILES.contains(&name))
+            });
+        if relevant {
+            let _ = sender.send(());
+        }
+    });
+    match watcher.and_then(|mut watcher| {
+        watcher.watch(CONFIG_DIRECTORY.as_ref(), RecursiveMode::NonRecursive)?;
+        Ok(watcher)
+    }) {
+        Ok(watcher) => Some(watcher),
+        Err(e) => {
+            warn!("Cannot watch the config directory where the error {} is raised; send SIGHUP to reload instead.", e);
+            None
+        }
+    }
+}
+
+// Values left as in the templates, e.g. "[cookie]"; regexes, CSS selectors and formats are not looked
+// into since brackets are part of their syntax.
+pub fn find_placeholders(config: &Value) -> Vec<String> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"\[[a-z0-9_]+\]").unwrap();
+    }
+    let mut found = vec![];
+    let mut stack = vec![(String::new(), config)];
+    while let Some((path, value)) = stack.pop() {
+        match value {
+            Value::String(text) if RE.is_match(text) => found.push(format!("{} still holds the placeholder {:?}.", path, text)),
+            Value::Object(map) => {
+                for (key, value) in map {
+                    if key.ends_with("_regex") || key.ends_with("_format") || key == "selectors" {
+                        continue;
+                    }
+                    let path = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+                    stack.push((path, value));
+                }
+            }
+            Value::Array(values) => {
+                for (i, value) in values.iter().enumerate() {
+                    stack.push((format!("{}[{}]", path, i), value));
+                }
+            }
+            _ => {}
+        }
+    }
+    found.sort();
+    found
+}
+
+// Either "primary" or an address like `abc@group.calendar.google.com`.
+pub fn is_valid_calendar_id(calendar_id: &str) -> bool {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"^[^@\s]+@[^@\s]+\.[^@\s]+$").unwrap();
+    }
+    calendar_id == "primary" || RE.is_match(calendar_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::common::config::{clear_test_overrides, env_overrides, find_placeholders, is_valid_calendar_id, merge, set_override, set_test_override};
+
+    #[test]
+    fn test_merge_and_override() {
+        let mut config = json!({"url": "a", "headers": {"accept": "*/*", "cookie": "[cookie]"}, "past_days": 30});
+        merge(&mut config, json!({"headers": {"cookie": "SESSDATA=1"}, "profiles": ["me"]}));
+        set_override(&mut config, &["past_days".to_string()], "7".to_string());
+        set_override(&mut config, &["headers".to_string(), "cookie".to_string()], "123".to_string());
+        set_override(&mut config, &["selectors".to_string(), "title".to_string()], "h2".to_string());
+        assert_eq!(config, json!({
+            "url": "a",
+            "headers": {"accept": "*/*", "cookie": "123"},
+            "past_days": 7,
+            "profiles": ["me"],
+            "selectors": {"title": "h2"},
+        }));
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        set_test_override("config_test", "HEADERS__USER_AGENT", "diary".to_string());
+        set_test_override("config_test", "PAST_DAYS", "7".to_string());
+        assert_eq!(env_overrides("config_test"), vec![
+            (vec!["headers".to_string(), "user-agent".to_string()], "diary".to_string()),
+            (vec!["past_days".to_string()], "7".to_string()),
+        ]);
+        clear_test_overrides("config_test");
+    }
+
+    #[test]
+    fn test_validation() {
+        let config = json!({
+            "headers": {"cookie": "[cookie]", "accept": "*/*"},
+            "riot_id": "[name]#[tag]",
+            "link_regex": "[0-9]+",
+            "selectors": {"link": "a[href]"},
+        });
+        assert_eq!(find_placeholders(&config), vec![
+            "headers.cookie still holds the placeholder \"[cookie]\".",
+            "riot_id still holds the placeholder \"[name]#[tag]\".",
+        ]);
+        assert!(is_valid_calendar_id("primary"));
+        assert!(is_valid_calendar_id("abc123@group.calendar.google.com"));
+        assert!(!is_valid_calendar_id("@group.calendar.google.com"));
+        assert!(!is_valid_calendar_id(""));
+    }
+}
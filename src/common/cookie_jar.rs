@@ -0,0 +1,364 @@
+use std::cell::RefCell;
+use std::error::Error;
+use std::fs;
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::SystemTime;
+
+use aes::cipher::{block_padding::Pkcs7, BlockDecryptMut, KeyIvInit};
+use chrono::{TimeZone, Utc};
+use cookie_store::CookieStore;
+use reqwest::header::{COOKIE, HeaderMap, HeaderValue, SET_COOKIE};
+use reqwest::Url;
+use rusqlite::{Connection, OpenFlags};
+use sha1::Sha1;
+
+use crate::common::{ensure_directory, write_privately};
+use crate::common::secrets::{mark_if_sensitive, redact_url};
+
+// Seconds between 1601-01-01, where Chromium counts from, and the Unix epoch.
+const CHROMIUM_EPOCH_OFFSET_SECONDS: i64 = 11_644_473_600;
+
+// Set by `replay`, which must leave the state of the module as it is.
+static READ_ONLY: AtomicBool = AtomicBool::new(false);
+
+// Cookie jars are still read and imported into, but no longer written.
+pub fn keep_cookie_jars_read_only() {
+    READ_ONLY.store(true, Ordering::Relaxed);
+}
+
+// A cookie as stored by a browser; `domain` starts with a dot if the cookie is sent to subdomains too.
+#[derive(Debug, PartialEq)]
+struct BrowserCookie {
+    domain: String,
+    path: String,
+    secure: bool,
+    // Unix timestamp; None for session cookies.
+    expires: Option<i64>,
+    name: String,
+    value: String,
+}
+
+impl BrowserCookie {
+    fn host(&self) -> &str {
+        self.domain.trim_start_matches('.')
+    }
+
+    // Whether the cookie is sent along with requests to `host`.
+    fn applies_to(&self, host: &str) -> bool {
+        host == self.host() || (self.domain.starts_with('.') && host.ends_with(format!(".{}", self.host()).as_str()))
+    }
+
+    fn to_set_cookie(&self) -> String {
+        let mut set_cookie = format!("{}={}; Path={}", self.name, self.value, self.path);
+        if self.domain.starts_with('.') {
+            set_cookie += format!("; Domain={}", self.host()).as_str();
+        }
+        if let Some(expires) = self.expires.and_then(|expires| Utc.timestamp_opt(expires, 0).single()) {
+            set_cookie += format!("; Expires={}", expires.format("%a, %d %b %Y %H:%M:%S GMT")).as_str();
+        }
+        if self.secure {
+            set_cookie += "; Secure";
+        }
+        set_cookie
+    }
+}
+
+// Lines of `domain, include subdomains, path, secure, expiry, name, value` separated by tabs; cookies
+// marked as HTTP only are prefixed by `#HttpOnly_`.
+fn parse_netscape_cookies(content: &str) -> Vec<BrowserCookie> {
+    content.lines()
+        .map(|line| line.strip_prefix("#HttpOnly_").unwrap_or(line))
+        .filter(|line| !line.starts_with('#') && !line.trim().is_empty())
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() < 7 {
+                return None;
+            }
+            let host = fields[0].trim_start_matches('.');
+            let expires = fields[4].parse::<i64>().ok().filter(|expires| *expires > 0);
+            Some(BrowserCookie {
+                domain: if fields[1] == "TRUE" { format!(".{}", host) } else { host.to_string() },
+                path: fields[2].to_string(),
+                secure: fields[3] == "TRUE",
+                expires,
+                name: fields[5].to_string(),
+                value: fields[6].trim_end_matches('\r').to_string(),
+            })
+        })
+        .collect()
+}
+
+// Opened as immutable since the browser may hold a lock on it while running.
+fn open_browser_database(path: &str) -> Result<Connection, Box<dyn Error>> {
+    let flags = OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_URI;
+    Ok(Connection::open_with_flags(format!("file:{}?immutable=1", path), flags)?)
+}
+
+fn has_table(connection: &Connection, table: &str) -> bool {
+    connection.query_row("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1", [table], |_row| Ok(()))
+        .is_ok()
+}
+
+fn read_firefox_cookies(connection: &Connection) -> Result<Vec<BrowserCookie>, Box<dyn Error>> {
+    let mut statement = connection.prepare("SELECT host, path, isSecure, expiry, name, value FROM moz_cookies")?;
+    let cookies = statement.query_map([], |row| {
+        let expiry: i64 = row.get(3)?;
+        Ok(BrowserCookie {
+            domain: row.get(0)?,
+            path: row.get(1)?,
+            secure: row.get::<_, i64>(2)? != 0,
+            // Newer versions count in milliseconds.
+            expires: Some(if expiry > 100_000_000_000 { expiry / 1000 } else { expiry }),
+            name: row.get(4)?,
+            value: row.get(5)?,
+        })
+    })?.filter_map(|cookie| cookie.ok()).collect();
+    Ok(cookies)
+}
+
+// Values encrypted with the fixed key Chromium falls back to on Linux ("v10"); the ones encrypted by
+// a key from the system keyring ("v11", or any on macOS and Windows) cannot be read.
+fn decrypt_chromium_value(encrypted: &[u8], strip_domain_hash: bool) -> Option<String> {
+    let ciphertext = encrypted.strip_prefix(b"v10")?;
+    let key = pbkdf2::pbkdf2_hmac_array::<Sha1, 16>(b"peanuts", b"saltysalt", 1);
+    let iv = [b' '; 16];
+    let mut plaintext = cbc::Decryptor::<aes::Aes128>::new(&key.into(), &iv.into())
+        .decrypt_padded_vec_mut::<Pkcs7>(ciphertext).ok()?;
+    // Since database version 24, the value is preceded by the SHA-256 hash of the domain.
+    if strip_domain_hash && plaintext.len() >= 32 {
+        plaintext.drain(..32);
+    }
+    String::from_utf8(plaintext).ok()
+}
+
+fn read_chromium_cookies(connection: &Connection) -> Result<Vec<BrowserCookie>, Box<dyn Error>> {
+    let version = connection.query_row("SELECT value FROM meta WHERE key = 'version'", [], |row| row.get::<_, String>(0))
+        .ok().and_then(|version| version.parse::<i64>().ok()).unwrap_or(0);
+    let mut statement = connection.prepare("SELECT host_key, path, is_secure, expires_utc, name, value, encrypted_value FROM cookies")?;
+    let mut unreadable = 0;
+    let cookies = statement.query_map([], |row| {
+        let expires_utc: i64 = row.get(3)?;
+        let value: String = row.get(5)?;
+        let encrypted_value: Vec<u8> = row.get(6)?;
+        Ok((BrowserCookie {
+            domain: row.get(0)?,
+            path: row.get(1)?,
+            secure: row.get::<_, i64>(2)? != 0,
+            expires: if expires_utc > 0 { Some(expires_utc / 1_000_000 - CHROMIUM_EPOCH_OFFSET_SECONDS) } else { None },
+            name: row.get(4)?,
+            value,
+        }, encrypted_value))
+    })?.filter_map(|cookie| cookie.ok()).filter_map(|(mut cookie, encrypted_value)| {
+        if cookie.value.is_empty() && !encrypted_value.is_empty() {
+            match decrypt_chromium_value(encrypted_value.as_slice(), version >= 24) {
+                Some(value) => cookie.value = value,
+                None => {
+                    unreadable += 1;
+                    return None;
+                }
+            }
+        }
+        Some(cookie)
+    }).collect();
+    if unreadable > 0 {
+        warn!("{} cookies are encrypted by a key from the system keyring and cannot be read; skipped.", unreadable);
+    }
+    Ok(cookies)
+}
+
+// Reads a Netscape `cookies.txt`, a Firefox `cookies.sqlite` or a Chromium `Cookies` file.
+fn read_browser_cookies(path: &str) -> Result<Vec<BrowserCookie>, Box<dyn Error>> {
+    if path.ends_with(".txt") {
+        return Ok(parse_netscape_cookies(fs::read_to_string(path)?.as_str()));
+    }
+    let connection = open_browser_database(path)?;
+    if has_table(&connection, "moz_cookies") {
+        read_firefox_cookies(&connection)
+    } else if has_table(&connection, "cookies") {
+        read_chromium_cookies(&connection)
+    } else {
+        Err(format!("{} is neither a cookies.txt nor a cookie database of Firefox or Chromium", path).into())
+    }
+}
+
+fn modified_time(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+// Cookies of one module instance with `cookies_from` set, kept in `dump/<identifier>_cookies.json`
+// (readable by its owner only) and refreshed by the `Set-Cookie` headers of its responses.
+pub struct CookieJar {
+    file_path: String,
+    store: RefCell<CookieStore>,
+}
+
+impl CookieJar {
+    // Cookies for `url` are imported from the browser file `cookies_from` if it has changed since the
+    // jar was last written, so that signing in again in the browser is picked up.
+    pub fn new(identifier: &str, url: &str, cookies_from: &str) -> CookieJar {
+        let file_path = format!("dump/{}_cookies.json", identifier);
+        let mut store = [file_path.clone(), format!("{}.bak", file_path)].iter()
+            .filter_map(|path| File::open(path).ok())
+            .find_map(|file| CookieStore::load_json_all(BufReader::new(file)).ok())
+            .unwrap_or_default();
+        let jar_modified = modified_time(file_path.as_str());
+        if jar_modified.is_none() || modified_time(cookies_from) > jar_modified {
+            let host = Url::parse(url).ok().and_then(|url| url.host_str().map(str::to_string)).unwrap_or_default();
+            match read_browser_cookies(cookies_from) {
+                Ok(cookies) => {
+                    let cookies: Vec<BrowserCookie> = cookies.into_iter().filter(|cookie| cookie.applies_to(host.as_str())).collect();
+                    for cookie in cookies.iter() {
+                        if let Ok(cookie_url) = Url::parse(format!("https://{}{}", cookie.host(), cookie.path).as_str()) {
+                            let _ = store.parse(cookie.to_set_cookie().as_str(), &cookie_url);
+                        }
+                    }
+                    info!("Imported {} cookies for {} from {}.", cookies.len(), identifier, cookies_from);
+                }
+                Err(e) => warn!("Cannot import cookies for {} from {} where the error {} is raised.", identifier, cookies_from, e),
+            }
+        }
+        let jar = CookieJar {
+            file_path,
+            store: RefCell::new(store),
+        };
+        jar.save();
+        jar
+    }
+
+    // Adds the cookies in the jar to the ones configured in the `cookie` header; the jar wins for
+    // cookies of the same name.
+    pub fn apply(&self, url: &str, headers: &mut HeaderMap) {
+        let url = match Url::parse(url) {
+            Ok(url) => url,
+            Err(_) => return,
+        };
+        let store = self.store.borrow();
+        let stored: Vec<(&str, &str)> = store.get_request_values(&url).collect();
+        if stored.is_empty() {
+            return;
+        }
+        let configured = headers.get(COOKIE).and_then(|value| value.to_str().ok()).unwrap_or_default().to_string();
+        let mut pairs: Vec<String> = configured.split(';')
+            .map(str::trim)
+            .filter(|pair| !pair.is_empty())
+            .filter(|pair| {
+                let name = pair.split('=').next().unwrap_or_default();
+                !stored.iter().any(|(stored_name, _)| *stored_name == name)
+            })
+            .map(str::to_string)
+            .collect();
+        pairs.extend(stored.iter().map(|(name, value)| format!("{}={}", name, value)));
+        if let Ok(mut value) = HeaderValue::from_str(pairs.join("; ").as_str()) {
+            mark_if_sensitive(&COOKIE, &mut value);
+            headers.insert(COOKIE, value);
+        }
+    }
+
+    pub fn store_response_cookies(&self, url: &Url, headers: &HeaderMap) {
+        let set_cookies: Vec<&str> = headers.get_all(SET_COOKIE).iter().filter_map(|value| value.to_str().ok()).collect();
+        if set_cookies.is_empty() {
+            return;
+        }
+        {
+            let mut store = self.store.borrow_mut();
+            for set_cookie in set_cookies {
+                let _ = store.parse(set_cookie, url);
+            }
+        }
+        debug!("Cookies refreshed by {}.", redact_url(url.as_str()));
+        self.save();
+    }
+
+    fn save(&self) {
+        if READ_ONLY.load(Ordering::Relaxed) {
+            return;
+        }
+        ensure_directory("dump");
+        let mut content = vec![];
+        let result = self.store.borrow().save_incl_expired_and_nonpersistent_json(&mut content)
+            .map_err(|e| -> Box<dyn Error> { e })
+            .and_then(|_| Ok(write_privately(self.file_path.as_str(), content.as_slice())?));
+        match result {
+            Ok(_) => debug!("Dumped cookies to file {}.", self.file_path),
+            Err(e) => warn!("Cannot dump cookies to file {} where the error {} is raised!", self.file_path, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use aes::cipher::{block_padding::Pkcs7, BlockEncryptMut, KeyIvInit};
+    use cookie_store::CookieStore;
+    use reqwest::header::{COOKIE, HeaderMap, HeaderValue, SET_COOKIE};
+    use reqwest::Url;
+    use sha1::Sha1;
+
+    use crate::common::cookie_jar::{CookieJar, decrypt_chromium_value, parse_netscape_cookies};
+
+    fn jar(name: &str) -> CookieJar {
+        CookieJar {
+            file_path: std::env::temp_dir().join(format!("calendar_as_diary_{}_cookies.json", name)).to_string_lossy().to_string(),
+            store: RefCell::new(CookieStore::default()),
+        }
+    }
+
+    #[test]
+    fn test_parse_netscape_cookies() {
+        let content = "# Netscape HTTP Cookie File\n\
+.bilibili.com\tTRUE\t/\tFALSE\t1999999999\tSESSDATA\tabc%2C123\n\
+#HttpOnly_.bilibili.com\tTRUE\t/\tTRUE\t0\tbili_jct\txyz\n\
+www.netflix.com\tFALSE\t/\tTRUE\t1999999999\tNetflixId\tv%3D2\n";
+        let cookies = parse_netscape_cookies(content);
+        assert_eq!(cookies.len(), 3);
+        assert_eq!(cookies[0].to_set_cookie(), "SESSDATA=abc%2C123; Path=/; Domain=bilibili.com; Expires=Wed, 18 May 2033 03:33:19 GMT");
+        assert_eq!(cookies[1].expires, None);
+        assert!(cookies[1].secure);
+        assert!(cookies[0].applies_to("api.bilibili.com"));
+        assert!(!cookies[2].applies_to("api.netflix.com"));
+        assert!(cookies[2].applies_to("www.netflix.com"));
+    }
+
+    #[test]
+    fn test_decrypt_chromium_value() {
+        let key = pbkdf2::pbkdf2_hmac_array::<Sha1, 16>(b"peanuts", b"saltysalt", 1);
+        let encrypted = cbc::Encryptor::<aes::Aes128>::new(&key.into(), &[b' '; 16].into())
+            .encrypt_padded_vec_mut::<Pkcs7>(b"secret-value");
+        let mut value = b"v10".to_vec();
+        value.extend(encrypted);
+        assert_eq!(decrypt_chromium_value(value.as_slice(), false).unwrap(), "secret-value");
+        assert_eq!(decrypt_chromium_value(b"v11whatever", false), None);
+    }
+
+    #[test]
+    fn test_refreshed_cookie_overrides_configured_one() {
+        let jar = jar("refreshed");
+        let url = Url::parse("https://api.bilibili.com/x/web-interface/history/cursor").unwrap();
+        let mut response_headers = HeaderMap::new();
+        response_headers.append(SET_COOKIE, HeaderValue::from_static("SESSDATA=fresh; Domain=bilibili.com; Path=/"));
+        jar.store_response_cookies(&url, &response_headers);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(COOKIE, HeaderValue::from_static("SESSDATA=stale; buvid3=keep"));
+        jar.apply(url.as_str(), &mut headers);
+        assert_eq!(headers.get(COOKIE).unwrap(), "buvid3=keep; SESSDATA=fresh");
+        assert!(headers.get(COOKIE).unwrap().is_sensitive());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_saved_jar_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let jar = jar("private");
+        jar.save();
+        jar.save();
+        for path in [jar.file_path.clone(), format!("{}.bak", jar.file_path)] {
+            assert_eq!(std::fs::metadata(path.as_str()).unwrap().permissions().mode() & 0o077, 0, "{} is readable by others", path);
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+}
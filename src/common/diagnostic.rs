@@ -0,0 +1,102 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::calendar::event::EventWithId;
+use crate::common::error::{ModuleError, shorten};
+
+// A row of the responses which is skipped while the other rows are kept, e.g. a game whose date cannot
+// be read.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Diagnostic {
+    // Position of the row among the rows of the cycle, counted from 0 across the responses.
+    pub row: usize,
+    pub reason: String,
+    // Beginning of the offending row, or of the part of it which cannot be parsed.
+    pub snippet: String,
+}
+
+impl Diagnostic {
+    // The snippet of a parse error is preferred to the one of the whole row, as it tells more precisely
+    // what went wrong.
+    pub fn new(row: usize, error: &ModuleError, snippet: &str) -> Diagnostic {
+        match error {
+            ModuleError::Parse { context, reason, snippet: part } => Diagnostic {
+                row,
+                reason: format!("cannot parse {}: {}", context, reason),
+                snippet: if part.is_empty() { shorten(snippet) } else { part.clone() },
+            },
+            _ => Diagnostic {
+                row,
+                reason: error.to_string(),
+                snippet: shorten(snippet),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.snippet.is_empty() {
+            write!(f, "row {}: {}", self.row, self.reason)
+        } else {
+            write!(f, "row {}: {} in {:?}", self.row, self.reason, self.snippet)
+        }
+    }
+}
+
+// Events parsed from the responses of a cycle, along with the rows which are skipped.
+#[derive(Debug, Default)]
+pub struct ParsedEvents {
+    pub events: Vec<EventWithId>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl ParsedEvents {
+    pub fn skip(&mut self, row: usize, error: &ModuleError, snippet: &str) {
+        self.diagnostics.push(Diagnostic::new(row, error, snippet));
+    }
+
+    // A cycle whose rows are all skipped tells that the source changed rather than that each row is odd,
+    // so it fails as a whole instead of passing with nothing posted.
+    pub fn check_usable(&self) -> Result<(), ModuleError> {
+        match self.diagnostics.first() {
+            Some(diagnostic) if self.events.is_empty() => Err(ModuleError::SourceChanged(
+                format!("none of the {} rows can be parsed, e.g. {}", self.diagnostics.len(), diagnostic),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl From<Vec<EventWithId>> for ParsedEvents {
+    fn from(events: Vec<EventWithId>) -> Self {
+        ParsedEvents { events, diagnostics: vec![] }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::diagnostic::{Diagnostic, ParsedEvents};
+    use crate::common::error::ModuleError;
+
+    #[test]
+    fn test_diagnostic() {
+        let row = "<tr>\n  <td>Ranked Solo</td>\n</tr>";
+        let error = ModuleError::parse("game duration", "no minutes and seconds", "25 minutes");
+        let diagnostic = Diagnostic::new(3, &error, row);
+        assert_eq!(diagnostic.to_string(), r#"row 3: cannot parse game duration: no minutes and seconds in "25 minutes""#);
+
+        let mut parsed = ParsedEvents::from(vec![]);
+        assert!(parsed.check_usable().is_ok());
+        parsed.skip(0, &ModuleError::SourceChanged("unknown element <span>".to_string()), row);
+        parsed.skip(1, &ModuleError::parse("game row", "div.gameMode is missing", ""), row);
+        assert_eq!(parsed.diagnostics[0].snippet, "<tr> <td>Ranked Solo</td> </tr>");
+        assert_eq!(parsed.diagnostics[0].reason, "source seems to have changed: unknown element <span>");
+        assert_eq!(parsed.diagnostics[1].snippet, "<tr> <td>Ranked Solo</td> </tr>");
+        match parsed.check_usable() {
+            Err(ModuleError::SourceChanged(reason)) => assert!(reason.starts_with("none of the 2 rows can be parsed, e.g. row 0: ")),
+            other => panic!("Expected a change of the source but got {:?}", other),
+        }
+    }
+}
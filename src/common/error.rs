@@ -0,0 +1,91 @@
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+// Characters of the offending response kept in a parse error.
+const SNIPPET_LENGTH: usize = 200;
+
+// Why a cycle of a module fails, telling the scheduler whether trying again later may help or someone
+// has to look into it.
+#[derive(Debug, Clone)]
+pub enum ModuleError {
+    // The source cannot be reached or fails on its side, e.g. a timeout or an HTTP status 5xx.
+    Network(String),
+    // The login expired or the credentials cannot be used.
+    Auth(String),
+    // Part of a response cannot be understood; `context` tells what was being parsed, e.g. "match
+    // NA1_4471269577", and `snippet` shows the beginning of the offending part.
+    Parse { context: String, reason: String, snippet: String },
+    // Still rate limited after the retries of the cycle, with the wait asked for by the source if any.
+    RateLimited(Option<Duration>),
+    // The source no longer looks the way the module expects, e.g. after a redesign of the page or a
+    // move of the endpoint.
+    SourceChanged(String),
+    // The config cannot be used as it is, e.g. a secret it refers to cannot be read.
+    Config(String),
+}
+
+impl ModuleError {
+    pub fn parse(context: impl Into<String>, reason: impl fmt::Display, snippet: &str) -> ModuleError {
+        ModuleError::Parse {
+            context: context.into(),
+            reason: reason.to_string(),
+            snippet: shorten(snippet),
+        }
+    }
+
+    // Whether the next cycle may succeed by itself; other failures call for a fix of the config, the
+    // login or the parser.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, ModuleError::Network(_) | ModuleError::RateLimited(_))
+    }
+}
+
+impl fmt::Display for ModuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModuleError::Network(t) => write!(f, "network error: {}", t),
+            ModuleError::Auth(t) => write!(f, "authentication error: {}", t),
+            ModuleError::Parse { context, reason, snippet } if snippet.is_empty() => write!(f, "cannot parse {}: {}", context, reason),
+            ModuleError::Parse { context, reason, snippet } => write!(f, "cannot parse {}: {} in {:?}", context, reason, snippet),
+            ModuleError::RateLimited(Some(wait)) => write!(f, "rate limited; asked to wait {} seconds", wait.as_secs()),
+            ModuleError::RateLimited(None) => write!(f, "rate limited"),
+            ModuleError::SourceChanged(t) => write!(f, "source seems to have changed: {}", t),
+            ModuleError::Config(t) => write!(f, "config error: {}", t),
+        }
+    }
+}
+
+impl Error for ModuleError {}
+
+// Whitespace collapsed, so that a piece of HTML fits on a line of the log.
+pub fn shorten(text: &str) -> String {
+    let collapsed = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+    match collapsed.char_indices().nth(SNIPPET_LENGTH) {
+        Some((i, _)) => format!("{}…", &collapsed[..i]),
+        None => collapsed,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::common::error::ModuleError;
+
+    #[test]
+    fn test_module_error() {
+        let error = ModuleError::parse("match NA1_1", "missing field `info`", "{\n  \"metadata\": {}\n}");
+        assert_eq!(error.to_string(), r#"cannot parse match NA1_1: missing field `info` in "{ \"metadata\": {} }""#);
+        assert!(!error.is_transient());
+        let long = ModuleError::parse("page", "unexpected element", "年".repeat(300).as_str());
+        match long {
+            ModuleError::Parse { snippet, .. } => assert_eq!(snippet.chars().count(), 201),
+            _ => unreachable!(),
+        }
+        assert!(ModuleError::RateLimited(Some(Duration::from_secs(60))).is_transient());
+        assert!(ModuleError::Network("timeout".to_string()).is_transient());
+        assert!(!ModuleError::SourceChanged("HTTP status 404".to_string()).is_transient());
+        assert!(!ModuleError::Config("cannot read the secret".to_string()).is_transient());
+    }
+}
@@ -0,0 +1,192 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::thread;
+use std::time::SystemTime;
+
+use lazy_static::lazy_static;
+use serde::Deserialize;
+
+use crate::common::auth_health::read_auth_statuses;
+use crate::common::read_json;
+
+const METRICS_CONFIG_FILE: &str = "config/metrics.json";
+const PREFIX: &str = "calendar_as_diary";
+
+// Counters of a module instance since the daemon started.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ModuleMetrics {
+    pub last_success: Option<SystemTime>,
+    // Why the last cycle failed when it needs attention, i.e. unless it is retried by itself; cleared by
+    // the next successful cycle.
+    pub failure: Option<String>,
+    pub fetch_seconds: f64,
+    pub fetches: u64,
+    pub events_parsed: u64,
+    // Events dropped by `filter_event`, and then the ones dropped as already posted.
+    pub events_filtered: u64,
+    pub events_deduped: u64,
+    pub events_posted: u64,
+    pub post_failures: u64,
+    // Rows skipped as they cannot be parsed.
+    pub parse_diagnostics: u64,
+}
+
+lazy_static! {
+    static ref METRICS: Mutex<HashMap<String, ModuleMetrics>> = Mutex::new(HashMap::new());
+}
+
+pub fn update_metrics(identifier: &str, update: impl FnOnce(&mut ModuleMetrics)) {
+    let mut metrics = METRICS.lock().unwrap_or_else(|e| e.into_inner());
+    update(metrics.entry(identifier.to_string()).or_default());
+}
+
+pub fn module_metrics() -> HashMap<String, ModuleMetrics> {
+    METRICS.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+#[derive(Debug, Deserialize)]
+struct MetricsConfig {
+    // e.g. "127.0.0.1:9898"; better not exposed beyond the machine, as nothing is authenticated.
+    address: String,
+}
+
+// Serves `/metrics` for Prometheus and `/healthz` in the background, when `config/metrics.json` exists.
+pub fn start_metrics_server() {
+    let config = match read_json::<MetricsConfig>(METRICS_CONFIG_FILE) {
+        Ok(config) => config,
+        Err(_) => return,
+    };
+    let server = match tiny_http::Server::http(config.address.as_str()) {
+        Ok(server) => server,
+        Err(e) => {
+            warn!("Cannot serve metrics on {} where the error {} is raised.", config.address, e);
+            return;
+        }
+    };
+    info!("Serving metrics on http://{}/metrics.", config.address);
+    thread::spawn(move || {
+        for request in server.incoming_requests() {
+            let (status, body) = respond(request.url());
+            let _ = request.respond(tiny_http::Response::from_string(body).with_status_code(status));
+        }
+    });
+}
+
+fn respond(url: &str) -> (u16, String) {
+    let auth_expired: Vec<String> = read_auth_statuses().into_keys().collect();
+    let metrics = module_metrics();
+    match url.split('?').next().unwrap_or_default() {
+        "/metrics" => (200, render_metrics(&metrics, &auth_expired)),
+        "/healthz" => render_health(&metrics, &auth_expired),
+        _ => (404, "Not found\n".to_string()),
+    }
+}
+
+// Unhealthy while a module needs to be signed in again or fails in a way which needs attention.
+fn render_health(metrics: &HashMap<String, ModuleMetrics>, auth_expired: &[String]) -> (u16, String) {
+    let mut problems: BTreeMap<&str, String> = metrics.iter()
+        .filter_map(|(identifier, metrics)| Some((identifier.as_str(), metrics.failure.clone()?)))
+        .collect();
+    for identifier in auth_expired {
+        problems.insert(identifier.as_str(), "needs to be signed in again".to_string());
+    }
+    if problems.is_empty() {
+        return (200, "ok\n".to_string());
+    }
+    (503, problems.iter().map(|(identifier, problem)| format!("{}: {}\n", identifier, problem)).collect())
+}
+
+// Suffix of the sample name along with its value for a module, if any.
+type Sample<'a> = (&'a str, &'a dyn Fn(&str, &ModuleMetrics) -> Option<f64>);
+
+// Text exposition format of Prometheus, one series per module instance.
+fn render_metrics(metrics: &HashMap<String, ModuleMetrics>, auth_expired: &[String]) -> String {
+    let mut identifiers: Vec<&str> = metrics.keys().map(String::as_str).chain(auth_expired.iter().map(String::as_str)).collect();
+    identifiers.sort();
+    identifiers.dedup();
+    let empty = ModuleMetrics::default();
+    let series: Vec<(&str, &ModuleMetrics)> = identifiers.iter().map(|identifier| (*identifier, metrics.get(*identifier).unwrap_or(&empty))).collect();
+
+    let mut output = String::new();
+    // Samples are named after the family with the given suffix, e.g. `_sum` and `_count` for a summary.
+    let mut family = |name: &str, kind: &str, help: &str, samples: &[Sample]| {
+        let _ = writeln!(output, "# HELP {}_{} {}", PREFIX, name, help);
+        let _ = writeln!(output, "# TYPE {}_{} {}", PREFIX, name, kind);
+        for (suffix, value) in samples {
+            for (identifier, metrics) in &series {
+                if let Some(value) = value(identifier, metrics) {
+                    let _ = writeln!(output, "{}_{}{}{{module=\"{}\"}} {}", PREFIX, name, suffix, escape_label(identifier), value);
+                }
+            }
+        }
+    };
+    family("last_success_timestamp_seconds", "gauge", "Time of the last successful cycle.",
+           &[("", &|_, m| m.last_success.and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok()).map(|d| d.as_secs() as f64))]);
+    family("fetch_duration_seconds", "summary", "Time spent fetching the responses of the cycles.",
+           &[("_sum", &|_, m| Some(m.fetch_seconds)), ("_count", &|_, m| Some(m.fetches as f64))]);
+    family("events_parsed_total", "counter", "Events parsed from the responses.", &[("", &|_, m| Some(m.events_parsed as f64))]);
+    family("events_filtered_total", "counter", "Events dropped by the filter.", &[("", &|_, m| Some(m.events_filtered as f64))]);
+    family("events_deduped_total", "counter", "Events dropped as already posted.", &[("", &|_, m| Some(m.events_deduped as f64))]);
+    family("events_posted_total", "counter", "Events posted to the calendar.", &[("", &|_, m| Some(m.events_posted as f64))]);
+    family("post_failures_total", "counter", "Events which fail to be posted.", &[("", &|_, m| Some(m.post_failures as f64))]);
+    family("parse_diagnostics_total", "counter", "Rows skipped as they cannot be parsed.", &[("", &|_, m| Some(m.parse_diagnostics as f64))]);
+    family("auth_expired", "gauge", "Whether the module needs to be signed in again.",
+           &[("", &|identifier, _| Some(if auth_expired.iter().any(|expired| expired == identifier) { 1.0 } else { 0.0 }))]);
+    output
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::{Duration, SystemTime};
+
+    use crate::common::metrics::{module_metrics, ModuleMetrics, render_health, render_metrics, update_metrics};
+
+    #[test]
+    fn test_update_metrics() {
+        update_metrics("metrics_test", |metrics| metrics.parse_diagnostics += 2);
+        update_metrics("metrics_test", |metrics| metrics.parse_diagnostics += 1);
+        assert_eq!(module_metrics()["metrics_test"].parse_diagnostics, 3);
+    }
+
+    #[test]
+    fn test_render() {
+        let mut metrics = HashMap::new();
+        metrics.insert("ics_feed.team".to_string(), ModuleMetrics {
+            last_success: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1666411915)),
+            fetch_seconds: 1.5,
+            fetches: 2,
+            events_parsed: 8,
+            events_deduped: 4,
+            events_posted: 3,
+            post_failures: 1,
+            ..ModuleMetrics::default()
+        });
+        metrics.insert("youtube".to_string(), ModuleMetrics {
+            failure: Some("source seems to have changed: HTTP status 404".to_string()),
+            ..ModuleMetrics::default()
+        });
+        let expired = vec!["netflix".to_string()];
+
+        let rendered = render_metrics(&metrics, &expired);
+        assert!(rendered.contains("# TYPE calendar_as_diary_events_posted_total counter\n"));
+        assert!(rendered.contains("calendar_as_diary_last_success_timestamp_seconds{module=\"ics_feed.team\"} 1666411915\n"));
+        assert!(!rendered.contains("calendar_as_diary_last_success_timestamp_seconds{module=\"youtube\"}"));
+        assert!(rendered.contains("# TYPE calendar_as_diary_fetch_duration_seconds summary\n"));
+        assert!(!rendered.contains("# TYPE calendar_as_diary_fetch_duration_seconds_sum"));
+        assert!(rendered.contains("calendar_as_diary_fetch_duration_seconds_sum{module=\"ics_feed.team\"} 1.5\n"));
+        assert!(rendered.contains("calendar_as_diary_fetch_duration_seconds_count{module=\"ics_feed.team\"} 2\n"));
+        assert!(rendered.contains("calendar_as_diary_post_failures_total{module=\"ics_feed.team\"} 1\n"));
+        assert!(rendered.contains("calendar_as_diary_auth_expired{module=\"netflix\"} 1\n"));
+        assert!(rendered.contains("calendar_as_diary_auth_expired{module=\"youtube\"} 0\n"));
+
+        assert_eq!(render_health(&metrics, &expired), (503, "netflix: needs to be signed in again\nyoutube: source seems to have changed: HTTP status 404\n".to_string()));
+        metrics.remove("youtube");
+        assert_eq!(render_health(&metrics, &[]), (200, "ok\n".to_string()));
+    }
+}
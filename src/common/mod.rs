@@ -2,124 +2,369 @@ use std::{fs, io};
 use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::fs::File;
+use std::io::Write;
 use std::path::Path;
 
-use chrono::{Date, Datelike, Local};
+use chrono::{Datelike, Local, NaiveDate};
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
 use reqwest::header::*;
+use reqwest::{Method, RequestBuilder, StatusCode};
 use serde::{de, Deserialize, Serialize};
 use serde_json as json;
 
 use crate::calendar::event::*;
+use crate::common::config::*;
+use crate::common::cookie_jar::CookieJar;
+use crate::common::diagnostic::ParsedEvents;
+use crate::common::error::ModuleError;
+use crate::common::secrets::*;
 
+pub mod archive;
+pub mod auth_health;
+pub mod config;
+pub mod cookie_jar;
+pub mod diagnostic;
+pub mod error;
+pub mod metrics;
+pub mod secrets;
 pub mod utc_date_format;
 
 #[derive(Debug, Deserialize)]
 pub struct RequestConfigJson {
     pub url: String,
     pub calendar_id: String,
+    #[serde(default)]
     pub headers: HashMap<String, String>,
+    // HTTP method of the regular request, GET if not given.
+    #[serde(default)]
+    pub method: Option<String>,
+    // Query parameters appended to the url of the regular request.
+    #[serde(default)]
+    pub query: HashMap<String, String>,
+    // Raw request body, or form fields sent url-encoded; only one of them can be given.
+    #[serde(default)]
+    pub body: Option<String>,
+    #[serde(default)]
+    pub form: HashMap<String, String>,
+    // Netscape cookies.txt, Firefox cookies.sqlite or Chromium Cookies file to take cookies from.
+    #[serde(default)]
+    pub cookies_from: Option<String>,
 }
 
+// Header values, query parameters, body and form fields may contain `{date}` (today), `{cursor}` (see
+// `Module::get_cursor`) and secret references `{env:VAR}`, `{file:path}` and `{secret:name}`, which are
+// filled in for every request.
 pub struct RequestConfig {
     pub url: String,
     pub calendar_id: String,
     pub headers: HeaderMap,
+    pub payload: RequestPayload,
+    pub cookie_jar: Option<CookieJar>,
+}
+
+// What the regular request sends besides its headers.
+#[derive(Debug, Default)]
+pub struct RequestPayload {
+    pub method: Method,
+    pub query: HashMap<String, String>,
+    pub body: Option<String>,
+    pub form: HashMap<String, String>,
+}
+
+impl RequestPayload {
+    fn new(config: &RequestConfigJson) -> Result<RequestPayload, String> {
+        let method = match &config.method {
+            Some(method) => Method::from_bytes(method.to_uppercase().as_bytes())
+                .map_err(|_| format!("method {} is not a valid HTTP method.", method))?,
+            None => Method::GET,
+        };
+        if config.body.is_some() && !config.form.is_empty() {
+            return Err("body and form cannot be given together.".to_string());
+        }
+        Ok(RequestPayload {
+            method,
+            query: config.query.clone(),
+            body: config.body.clone(),
+            form: config.form.clone(),
+        })
+    }
 }
 
 impl RequestConfig {
+    // `source` is the instance identifier; all instances of a module share the default config file.
+    // Every problem with the config is reported at once through a `ConfigError`.
     pub fn new(source: &str, calendar_id: Option<String>) -> Result<RequestConfig, Box<dyn Error>> {
-        let mut config = RequestConfig {
-            url: String::from(""),
-            calendar_id: String::from(""),
-            headers: HeaderMap::new(),
-        };
+        RequestConfig::from_config(source, layered_config(source)?, calendar_id, vec![])
+    }
 
-        match read_json::<RequestConfigJson>(format!("config/{}.json.default", source).as_str()) {
-            Ok(default_config) => {
-                debug!("Default {} config file loaded.", source);
-                headers_modifier(&default_config.headers, &mut config.headers);
-                config.url = default_config.url;
-                config.calendar_id = default_config.calendar_id;
-            }
-            Err(e) => {
-                warn!("Default {} config not found! {}", source, e);
-                return Err(Box::new(e));
-            }
+    // `module_problems` are the ones found in the module specific part of the same config, which are
+    // reported along with the ones of the request settings.
+    fn from_config(source: &str, mut value: json::Value, calendar_id: Option<String>, module_problems: Vec<String>) -> Result<RequestConfig, Box<dyn Error>> {
+        if let Some(calendar_id) = calendar_id {
+            value["calendar_id"] = json::Value::String(calendar_id);
         }
-        match read_json::<RequestConfigJson>(format!("config/{}.json", source).as_str()) {
-            Ok(custom_config) => {
-                debug!("Custom {} config file loaded.", source);
-                headers_modifier(&custom_config.headers, &mut config.headers);
-                config.url = custom_config.url;
-                config.calendar_id = custom_config.calendar_id;
+        // Cookies taken from a browser make the cookie header of the template unnecessary.
+        if value.get("cookies_from").is_some_and(|cookies_from| !cookies_from.is_null()) {
+            if let Some(headers) = value.get_mut("headers").and_then(json::Value::as_object_mut) {
+                headers.retain(|name, header| name != "cookie" || find_placeholders(header).is_empty());
             }
+        }
+
+        let mut problems = find_placeholders(&value);
+        let config_json = match json::from_value::<RequestConfigJson>(value) {
+            Ok(config_json) => config_json,
             Err(e) => {
-                warn!("Custom {} config file not found! {}", source, e);
-                return Err(Box::new(e));
+                problems.push(format!("invalid request settings: {}", e));
+                problems.extend(module_problems);
+                return Err(Box::new(ConfigError { problems }));
             }
+        };
+        if config_json.url.is_empty() {
+            problems.push("url is empty.".to_string());
+        }
+        if !is_valid_calendar_id(config_json.calendar_id.as_str()) {
+            problems.push(format!("calendar_id {:?} is not a calendar id like \"abc@group.calendar.google.com\" or \"primary\".", config_json.calendar_id));
+        }
+        let mut headers = HeaderMap::new();
+        if let Err(e) = headers_modifier(&config_json.headers, &mut headers) {
+            problems.push(e.to_string());
         }
+        let payload = RequestPayload::new(&config_json).unwrap_or_else(|e| {
+            problems.push(e);
+            RequestPayload::default()
+        });
+        problems.extend(module_problems);
+        ConfigError::check(problems)?;
+        debug!("Config of {} loaded.", source);
 
-        if let Some(calendar_id) = calendar_id {
-            config.calendar_id = calendar_id;
+        Ok(RequestConfig {
+            cookie_jar: config_json.cookies_from.as_deref().map(|cookies_from| CookieJar::new(source, config_json.url.as_str(), cookies_from)),
+            url: config_json.url,
+            calendar_id: config_json.calendar_id,
+            headers,
+            payload,
+        })
+    }
+
+    // Builds the request for `url` with the configured headers; the method, query, body and form only
+    // apply to the regular request and its pages, while setup and detail requests are plain GETs.
+    pub fn build_request(&self, client: &reqwest::Client, url: &str, with_payload: bool, cursor: Option<&str>) -> Result<RequestBuilder, Box<dyn Error>> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in self.headers.iter() {
+            let value = render_template(value.to_str()?, cursor)?;
+            let mut value = HeaderValue::from_str(value.as_str())?;
+            mark_if_sensitive(name, &mut value);
+            headers.insert(name, value);
+        }
+        if let Some(cookie_jar) = &self.cookie_jar {
+            cookie_jar.apply(url, &mut headers);
+        }
+        if !with_payload {
+            return Ok(client.get(url).headers(headers));
         }
+        let payload = &self.payload;
+        let mut request = client.request(payload.method.clone(), url).headers(headers);
+        if !payload.query.is_empty() {
+            request = request.query(&render_map(&payload.query, cursor)?);
+        }
+        if let Some(body) = &payload.body {
+            request = request.body(render_template(body.as_str(), cursor)?);
+        }
+        if !payload.form.is_empty() {
+            request = request.form(&render_map(&payload.form, cursor)?);
+        }
+        Ok(request)
+    }
+}
+
+// Request config and module specific config `T` of an instance, read from one layered config; `check`
+// tells what else is wrong with `T`, and the problems of both are reported together.
+pub fn read_configs<T: de::DeserializeOwned>(source: &str, calendar_id: Option<String>, check: impl FnOnce(&T) -> Vec<String>) -> Result<(RequestConfig, T), Box<dyn Error>> {
+    let value = layered_config(source)?;
+    let (module_config, problems) = match json::from_value::<T>(value.clone()) {
+        Ok(module_config) => {
+            let problems = check(&module_config);
+            (Some(module_config), problems)
+        }
+        Err(e) => (None, vec![format!("invalid module settings: {}", e)]),
+    };
+    let request_config = RequestConfig::from_config(source, value, calendar_id, problems)?;
+    match module_config {
+        Some(module_config) => Ok((request_config, module_config)),
+        None => Err("invalid module settings".into()),
+    }
+}
+
+// Fills in `{date}`, `{cursor}` and secret references: `{env:VAR}` for an environment variable,
+// `{file:path}` for the content of a file only readable by its owner, and `{secret:name}` for an entry of
+// the encrypted secrets file. Other placeholders, e.g. `{region}` in some module urls, are left as they
+// are. A missing cursor is taken as empty, a missing secret as an error.
+pub fn render_template(template: &str, cursor: Option<&str>) -> Result<String, Box<dyn Error>> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"\{(date|cursor|env:([A-Za-z_][A-Za-z0-9_]*)|file:([^{}]+)|secret:([A-Za-z0-9_.-]+))\}").unwrap();
+    }
+    let mut error: Option<Box<dyn Error>> = None;
+    let rendered = RE.replace_all(template, |captures: &Captures| {
+        let value = match (&captures[1], captures.get(2), captures.get(3), captures.get(4)) {
+            ("date", _, _, _) => Ok(Local::now().format("%Y-%m-%d").to_string()),
+            ("cursor", _, _, _) => Ok(cursor.unwrap_or_default().to_string()),
+            (_, Some(variable), _, _) => std::env::var(variable.as_str())
+                .map_err(|_| format!("Environment variable {} is not set.", variable.as_str()).into()),
+            (_, _, Some(path), _) => read_secret_file(path.as_str()),
+            (_, _, _, Some(name)) => read_stored_secret(name.as_str()),
+            _ => unreachable!(),
+        };
+        value.unwrap_or_else(|e| {
+            error.get_or_insert(e);
+            String::new()
+        })
+    });
+    match error {
+        Some(e) => Err(e),
+        None => Ok(rendered.into_owned()),
+    }
+}
+
+fn render_map(map: &HashMap<String, String>, cursor: Option<&str>) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+    let mut pairs = map.iter()
+        .map(|(key, value)| Ok((key.clone(), render_template(value.as_str(), cursor)?)))
+        .collect::<Result<Vec<(String, String)>, Box<dyn Error>>>()?;
+    pairs.sort();
+    Ok(pairs)
+}
+
+// Identifier of a module instance, under which its config is read, its state is dumped and its event
+// ids are prefixed, e.g. "bilibili" for the default instance and "bilibili.alice" for the one named
+// "alice" (configured in `config/bilibili.alice.json`).
+pub fn instance_identifier(module: &str, instance: Option<&str>) -> String {
+    match instance {
+        None => module.to_string(),
+        Some(instance) => format!("{}.{}", module, instance),
+    }
+}
+
+pub fn module_of_instance(identifier: &str) -> &str {
+    identifier.split('.').next().unwrap_or(identifier)
+}
+
+// Tells which instance of the module a config file belongs to, if any: `Some(None)` stands for the
+// default instance.
+fn instance_of_config_file(module: &str, file_name: &str) -> Option<Option<String>> {
+    let rest = file_name.strip_prefix(module)?.strip_suffix(".json")?;
+    match rest.strip_prefix('.') {
+        None if rest.is_empty() => Some(None),
+        Some(instance) if !instance.is_empty() && !instance.contains('.') => Some(Some(instance.to_string())),
+        _ => None,
+    }
+}
 
-        Ok(config)
+// Instances of a module found in the config directory; the default instance is assumed if there is
+// none, so that the usual error shows up when the module is loaded.
+pub fn find_instances(module: &str) -> Vec<Option<String>> {
+    let mut instances: Vec<Option<String>> = match fs::read_dir("config") {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter_map(|file_name| instance_of_config_file(module, file_name.as_str()))
+            .collect(),
+        Err(_) => vec![],
+    };
+    instances.sort();
+    if instances.is_empty() {
+        instances.push(None);
     }
+    instances
 }
 
 pub trait Module {
-    fn new(calendar_id: Option<String>) -> Result<Box<dyn Module>, Box<dyn Error>> where Self: Sized;
+    #[allow(clippy::new_ret_no_self)]
+    fn new(instance: Option<&str>, calendar_id: Option<String>) -> Result<Box<dyn Module>, Box<dyn Error>> where Self: Sized;
     fn dump(&self);
     fn get_config(&self) -> &RequestConfig;
     fn get_event_ids(&mut self) -> &mut HashSet<String>;
     fn get_identifier(&self) -> &str;
     fn get_request_url(&self) -> String;
+    #[allow(clippy::ptr_arg)]
     fn need_for_detail(&self, response: &String) -> Option<Vec<String>>;
-    fn process_response_into_event_with_id(&self, responses: Vec<String>) -> Result<Vec<EventWithId>, Box<dyn Error>>;
+    // Rows which cannot be parsed are reported along with the events of the others; an error is only
+    // returned when the responses cannot be read at all. A cycle whose rows are all reported fails as a
+    // change of the source (see `ParsedEvents::check_usable`).
+    fn process_response_into_event_with_id(&self, responses: Vec<String>) -> Result<ParsedEvents, ModuleError>;
+    // Converts the content of an exported file (e.g. from Google Takeout) into events; ids should
+    // coincide with the ones produced by `process_response_into_event_with_id` so that dedup works.
+    fn process_import_into_event_with_id(&self, _content: String) -> Result<Vec<EventWithId>, Box<dyn Error>> {
+        Err(format!("Module {} does not support importing from files.", self.get_identifier()).into())
+    }
+    // Restricts the next cycle to the given days (both inclusive) instead of the recent ones.
+    fn set_backfill_range(&mut self, _from: NaiveDate, _to: NaiveDate) -> Result<(), Box<dyn Error>> {
+        Err(format!("Module {} does not support backfilling.", self.get_identifier()).into())
+    }
+    // Url to be fetched before the regular request, e.g. to resolve an account id; the response is
+    // handed to `setup`.
+    fn need_for_setup(&self) -> Option<String> {
+        None
+    }
+    fn setup(&mut self, _response: String) -> Result<(), ModuleError> {
+        Ok(())
+    }
+    // Tells why the response shows that the login expired, e.g. a redirect to a sign-in page or an
+    // error code of the API; the module is then marked as needing to be signed in again.
+    fn check_auth_health(&self, status: StatusCode, _final_url: &str, _response: &str) -> Option<String> {
+        match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Some(format!("HTTP status {}", status)),
+            _ => None,
+        }
+    }
+    // Url of the page following `response` for sources paged by a cursor; pages are fetched until none
+    // is left, and each of them goes through `need_for_detail`.
+    fn next_page_url(&self, _response: &String) -> Option<String> {
+        None
+    }
+    // Value of `{cursor}` in the configured request, e.g. the position synced up to before.
+    fn get_cursor(&self) -> Option<String> {
+        None
+    }
+    // Pause between consecutive page or detail requests of one cycle, so that rate limits are respected.
+    fn get_request_interval(&self) -> std::time::Duration {
+        std::time::Duration::ZERO
+    }
+    // Called once the events of a cycle are posted and before the state is dumped; how far the module
+    // has synced (e.g. its cursor) is only moved forward here, so that a failed cycle is fetched again.
+    fn commit_cycle(&mut self) {}
 }
 
-pub fn filter_loaded_modules(modules: Vec<Result<Box<dyn Module>, Box<dyn Error>>>) -> Vec<Box<dyn Module>> {
-    modules.into_iter().filter_map(|module_res| {
-        module_res
-            .map(|module| {
+// Every module which cannot be loaded is reported, so that all config problems show up before startup.
+pub fn load_modules(modules: Vec<Result<Box<dyn Module>, Box<dyn Error>>>) -> Result<Vec<Box<dyn Module>>, Box<dyn Error>> {
+    let mut loaded = vec![];
+    let mut failures = 0;
+    for module_res in modules {
+        match module_res {
+            Ok(module) => {
                 info!("Loaded module {}.", module.get_identifier());
-                module
-            })
-            .map_err(|e| {
-                // warn!("Error raised in loading module: {}.", e);
-                e
-            }).ok()
-    }).collect()
-}
-
-pub fn headers_modifier(headers: &HashMap<String, String>, header_map: &mut HeaderMap) {
-    let header_dict: HashMap<&str, HeaderName> = get_header_dict();
-
-    for key in headers.keys() {
-        match header_dict.get(key.as_str()) {
-            Some(header) => {
-                debug!("Inserted header {} with value {}.", key, headers[key]);
-                header_map.insert(header, headers[key].parse().unwrap());
+                loaded.push(module);
+            }
+            Err(e) => {
+                error!("{}", e);
+                failures += 1;
             }
-            None => panic!("Unknown header {}.", key),
         }
     }
+    match failures {
+        0 => Ok(loaded),
+        _ => Err(format!("{} module instance(s) cannot be loaded; see the errors above.", failures).into()),
+    }
 }
 
-fn get_header_dict() -> HashMap<&'static str, HeaderName> {
-    let mut dict = HashMap::<&str, HeaderName>::new();
-    dict.insert("accept", ACCEPT);
-    dict.insert("accept-language", ACCEPT_LANGUAGE);
-    dict.insert("authorization", AUTHORIZATION);
-    dict.insert("cache-control", CACHE_CONTROL);
-    dict.insert("cookie", COOKIE);
-    dict.insert("dnt", DNT);
-    dict.insert("origin", ORIGIN);
-    dict.insert("referer", REFERER);
-    dict.insert("upgrade-insecure-requests", UPGRADE_INSECURE_REQUESTS);
-    dict.insert("user-agent", USER_AGENT);
-
-    dict
+// Any valid header name is accepted, e.g. `x-api-key`.
+pub fn headers_modifier(headers: &HashMap<String, String>, header_map: &mut HeaderMap) -> Result<(), Box<dyn Error>> {
+    for (key, value) in headers {
+        let header = HeaderName::from_bytes(key.as_bytes()).map_err(|_| format!("Invalid header name {}.", key))?;
+        let mut header_value = HeaderValue::from_str(value).map_err(|_| format!("Invalid value of header {}.", key))?;
+        mark_if_sensitive(&header, &mut header_value);
+        debug!("Inserted header {} with value {}.", key, redact_header(&header, value));
+        header_map.insert(header, header_value);
+    }
+    Ok(())
 }
 
 #[derive(Debug, Deserialize)]
@@ -140,7 +385,7 @@ pub struct DaylightSavingConfig {
 }
 
 impl DaylightSavingConfig {
-    pub fn get_offset_on(&self, date: &Date<Local>) -> i32 {
+    pub fn get_offset_on(&self, date: NaiveDate) -> i32 {
         let month = date.month();
         let day = date.day();
         let date = (month, day);
@@ -164,8 +409,24 @@ pub fn read_json<T: de::DeserializeOwned>(file_path: &str) -> Result<T, io::Erro
     }
 }
 
+// Reads a file written by `write_json`, falling back to its backup if the file is missing or broken,
+// e.g. after a crash between the two renames.
+pub fn read_dumped_json<T: de::DeserializeOwned>(file_path: &str) -> Result<T, io::Error> {
+    match read_json::<T>(file_path) {
+        Ok(result) => Ok(result),
+        Err(e) => {
+            let backup_path = backup_path_of(file_path);
+            if !path_exists(backup_path.as_str()) {
+                return Err(e);
+            }
+            warn!("Cannot read {} where the error {} is raised; reading its backup instead.", file_path, e);
+            read_json::<T>(backup_path.as_str())
+        }
+    }
+}
+
 pub fn read_dumped_event_id(identifier: &str) -> Result<HashSet<String>, Box<dyn Error>> {
-    match read_json::<HashSet<String>>(format!("dump/{}.json", identifier).as_str()) {
+    match read_dumped_json::<HashSet<String>>(format!("dump/{}.json", identifier).as_str()) {
         Ok(set) => Ok(set),
         Err(e) => Err(Box::new(e)),
     }
@@ -186,11 +447,71 @@ pub fn ensure_directory(path: &str) {
 
 pub fn write_json<T: Serialize>(file_path: &str, object: &T) -> Result<(), io::Error> {
     match serde_json::to_string(object) {
-        Ok(serialized) => fs::write(file_path, serialized),
+        Ok(serialized) => write_atomically(file_path, serialized.as_bytes()),
         Err(e) => Err(e.into()),
     }
 }
 
+fn backup_path_of(file_path: &str) -> String {
+    format!("{}.bak", file_path)
+}
+
+// The content is written to a temporary file first, and then renamed over the file, whose previous
+// version is kept as `<file>.bak`; the file is there at any time, and a crash in between never leaves a
+// truncated one behind.
+pub fn write_atomically(file_path: &str, content: &[u8]) -> Result<(), io::Error> {
+    write_file_atomically(file_path, content, false)
+}
+
+// Same as `write_atomically`, but the file is readable by its owner only from the start, e.g. the
+// cookie jar; a backup left readable by others by an older version is restricted as well.
+pub fn write_privately(file_path: &str, content: &[u8]) -> Result<(), io::Error> {
+    write_file_atomically(file_path, content, true)?;
+    restrict_permissions(backup_path_of(file_path).as_str());
+    Ok(())
+}
+
+fn write_file_atomically(file_path: &str, content: &[u8], private: bool) -> Result<(), io::Error> {
+    let temp_path = format!("{}.tmp", file_path);
+    // The mode only applies to a new file, not to one left over by a crash.
+    let _ = fs::remove_file(temp_path.as_str());
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    if private {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+    }
+    let mut file = options.open(temp_path.as_str())?;
+    file.write_all(content)?;
+    file.sync_all()?;
+    if path_exists(file_path) {
+        let backup_path = backup_path_of(file_path);
+        let _ = fs::remove_file(backup_path.as_str());
+        // Linked rather than moved, so that the file does not go missing until the new one is renamed in.
+        if fs::hard_link(file_path, backup_path.as_str()).is_err() {
+            fs::copy(file_path, backup_path.as_str())?;
+        }
+    }
+    fs::rename(temp_path, file_path)?;
+    sync_parent_directory(file_path)
+}
+
+// Makes the renames durable, which are kept by the directory rather than by the file.
+#[cfg(unix)]
+fn sync_parent_directory(file_path: &str) -> Result<(), io::Error> {
+    let parent = Path::new(file_path).parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    File::open(parent)?.sync_all()
+}
+
+// Directories cannot be opened as files elsewhere.
+#[cfg(not(unix))]
+fn sync_parent_directory(_file_path: &str) -> Result<(), io::Error> {
+    Ok(())
+}
+
 pub fn dump_event_id_wrapper(identifier: &str, ids: &HashSet<String>) {
     ensure_directory("dump");
     let file_path = format!("dump/{}.json", identifier);
@@ -200,5 +521,24 @@ pub fn dump_event_id_wrapper(identifier: &str, ids: &HashSet<String>) {
     }
 }
 
+fn state_path_of(identifier: &str) -> String {
+    format!("dump/{}_state.json", identifier)
+}
+
+// State of a module besides its event ids, e.g. the position it synced up to; the default state is taken
+// when nothing has been dumped yet.
+pub fn read_state<T: de::DeserializeOwned + Default>(identifier: &str) -> T {
+    read_dumped_json::<T>(state_path_of(identifier).as_str()).unwrap_or_default()
+}
+
+pub fn dump_state<T: Serialize>(identifier: &str, state: &T) {
+    ensure_directory("dump");
+    let file_path = state_path_of(identifier);
+    match write_json(file_path.as_str(), state) {
+        Ok(_) => debug!("Dumped json to file {}.", file_path.as_str()),
+        Err(e) => warn!("Cannot dump json to file {} where the error {} is raised!", file_path.as_str(), e),
+    }
+}
+
 #[cfg(test)]
 mod tests;
\ No newline at end of file
@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::sync::Mutex;
+
+use age::secrecy::SecretString;
+use lazy_static::lazy_static;
+use reqwest::header::{HeaderName, HeaderValue};
+use reqwest::Url;
+
+// Secrets encrypted by `age --passphrase`, a json object from names to values.
+pub const SECRETS_FILE: &str = "config/secrets.json.age";
+// Environment variable holding the passphrase of the secrets file.
+pub const PASSPHRASE_VARIABLE: &str = "CALENDAR_AS_DIARY_PASSPHRASE";
+
+const REDACTED: &str = "[redacted]";
+const SENSITIVE_HEADERS: [&str; 6] = ["authorization", "cookie", "proxy-authorization", "set-cookie", "x-api-key", "x-riot-token"];
+const SENSITIVE_QUERY_KEYS: [&str; 5] = ["auth", "key", "password", "secret", "token"];
+
+lazy_static! {
+    // Decrypted once, as scrypt is deliberately slow.
+    static ref STORED_SECRETS: Mutex<Option<HashMap<String, String>>> = Mutex::new(None);
+}
+
+// Content of a file holding a single secret, e.g. `{file:/home/me/.wakatime_key}`; it should only be
+// readable by its owner.
+pub fn read_secret_file(path: &str) -> Result<String, Box<dyn Error>> {
+    ensure_private(path)?;
+    Ok(fs::read_to_string(path)?.trim_end_matches(['\r', '\n']).to_string())
+}
+
+#[cfg(unix)]
+fn ensure_private(path: &str) -> Result<(), Box<dyn Error>> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = fs::metadata(path)?.permissions().mode();
+    if mode & 0o077 != 0 {
+        return Err(format!("Secret file {} is accessible by other users (mode {:o}); run `chmod 600 {}`.", path, mode & 0o777, path).into());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn ensure_private(_path: &str) -> Result<(), Box<dyn Error>> {
+    Ok(())
+}
+
+// Makes an existing file readable by its owner only, e.g. the OAuth token storage.
+#[cfg(unix)]
+pub fn restrict_permissions(path: &str) {
+    use std::os::unix::fs::PermissionsExt;
+
+    if let Ok(metadata) = fs::metadata(path) {
+        let mode = metadata.permissions().mode();
+        if mode & 0o077 != 0 {
+            match fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o700)) {
+                Ok(_) => warn!("File {} was accessible by other users; restricted to its owner.", path),
+                Err(e) => warn!("File {} is accessible by other users and cannot be restricted where the error {} is raised.", path, e),
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub fn restrict_permissions(_path: &str) {}
+
+// Value named `name` in the secrets file, e.g. `{secret:bilibili_cookie}`.
+pub fn read_stored_secret(name: &str) -> Result<String, Box<dyn Error>> {
+    let mut stored_secrets = STORED_SECRETS.lock().unwrap();
+    if stored_secrets.is_none() {
+        let passphrase = std::env::var(PASSPHRASE_VARIABLE)
+            .map_err(|_| format!("{} must be set to read {}.", PASSPHRASE_VARIABLE, SECRETS_FILE))?;
+        *stored_secrets = Some(decrypt_secrets(fs::read(SECRETS_FILE)?.as_slice(), passphrase)?);
+    }
+    match stored_secrets.as_ref().and_then(|secrets| secrets.get(name)) {
+        Some(value) => Ok(value.clone()),
+        None => Err(format!("Secret {} is not found in {}.", name, SECRETS_FILE).into()),
+    }
+}
+
+// Makes the secrets file be read again, e.g. after the config is reloaded.
+pub fn forget_stored_secrets() {
+    *STORED_SECRETS.lock().unwrap() = None;
+}
+
+fn decrypt_secrets(encrypted: &[u8], passphrase: String) -> Result<HashMap<String, String>, Box<dyn Error>> {
+    let identity = age::scrypt::Identity::new(SecretString::from(passphrase));
+    let decrypted = age::decrypt(&identity, encrypted)?;
+    Ok(serde_json::from_slice(decrypted.as_slice())?)
+}
+
+pub fn is_sensitive_header(name: &HeaderName) -> bool {
+    SENSITIVE_HEADERS.contains(&name.as_str())
+}
+
+// Keeps credentials out of debug output and out of the header compression tables of HTTP/2.
+pub fn mark_if_sensitive(name: &HeaderName, value: &mut HeaderValue) {
+    if is_sensitive_header(name) {
+        value.set_sensitive(true);
+    }
+}
+
+// Header value as it may show up in logs.
+pub fn redact_header<'a>(name: &HeaderName, value: &'a str) -> &'a str {
+    if is_sensitive_header(name) { REDACTED } else { value }
+}
+
+// Url as it may show up in logs, with the values of query parameters like `api_key` hidden.
+pub fn redact_url(url: &str) -> String {
+    let mut parsed = match Url::parse(url) {
+        Ok(parsed) => parsed,
+        Err(_) => return url.to_string(),
+    };
+    if parsed.query().is_none() {
+        return url.to_string();
+    }
+    let pairs: Vec<(String, String)> = parsed.query_pairs()
+        .map(|(key, value)| {
+            let lowercase = key.to_lowercase();
+            match SENSITIVE_QUERY_KEYS.iter().any(|sensitive| lowercase.contains(sensitive)) {
+                true => (key.into_owned(), REDACTED.to_string()),
+                false => (key.into_owned(), value.into_owned()),
+            }
+        })
+        .collect();
+    parsed.query_pairs_mut().clear().extend_pairs(pairs);
+    parsed.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::iter;
+
+    use age::secrecy::SecretString;
+    use reqwest::header::{AUTHORIZATION, HeaderValue, USER_AGENT};
+
+    use crate::common::secrets::{decrypt_secrets, mark_if_sensitive, redact_header, redact_url};
+
+    #[test]
+    fn test_decrypt_secrets() {
+        let mut recipient = age::scrypt::Recipient::new(SecretString::from("passphrase".to_string()));
+        recipient.set_work_factor(2);
+        let encryptor = age::Encryptor::with_recipients(iter::once(&recipient as _)).unwrap();
+        let mut encrypted = vec![];
+        let mut writer = encryptor.wrap_output(&mut encrypted).unwrap();
+        std::io::Write::write_all(&mut writer, br#"{"wakatime_key": "Basic abc"}"#).unwrap();
+        writer.finish().unwrap();
+
+        let secrets = decrypt_secrets(encrypted.as_slice(), "passphrase".to_string()).unwrap();
+        assert_eq!(secrets["wakatime_key"], "Basic abc");
+        assert!(decrypt_secrets(encrypted.as_slice(), "wrong".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_redact() {
+        assert_eq!(redact_header(&AUTHORIZATION, "Basic abc"), "[redacted]");
+        assert_eq!(redact_header(&USER_AGENT, "Mozilla/5.0"), "Mozilla/5.0");
+        let mut value = HeaderValue::from_static("Basic abc");
+        mark_if_sensitive(&AUTHORIZATION, &mut value);
+        assert_eq!(format!("{:?}", value), "Sensitive");
+        assert_eq!(
+            redact_url("https://wakatime.com/api/v1/users/current/durations?date=2022-10-01&api_key=abc"),
+            "https://wakatime.com/api/v1/users/current/durations?date=2022-10-01&api_key=%5Bredacted%5D",
+        );
+        assert_eq!(redact_url("https://www.netflix.com/viewingactivity"), "https://www.netflix.com/viewingactivity");
+    }
+}
@@ -1,5 +1,3 @@
-use chrono::TimeZone;
-
 use super::*;
 
 #[test]
@@ -12,10 +10,115 @@ fn test_get_offset_on() {
         local: 0,
     };
 
-    assert_eq!(config.get_offset_on(&Local.ymd(2020, 1, 2)), -6);
-    assert_eq!(config.get_offset_on(&Local.ymd(2020, 3, 1)), -6);
-    assert_eq!(config.get_offset_on(&Local.ymd(2020, 3, 15)), -5);
-    assert_eq!(config.get_offset_on(&Local.ymd(2020, 11, 1)), -5);
-    assert_eq!(config.get_offset_on(&Local.ymd(2020, 11, 12)), -6);
-    assert_eq!(config.get_offset_on(&Local.ymd(2020, 12, 2)), -6);
+    assert_eq!(config.get_offset_on(NaiveDate::from_ymd_opt(2020, 1, 2).unwrap()), -6);
+    assert_eq!(config.get_offset_on(NaiveDate::from_ymd_opt(2020, 3, 1).unwrap()), -6);
+    assert_eq!(config.get_offset_on(NaiveDate::from_ymd_opt(2020, 3, 15).unwrap()), -5);
+    assert_eq!(config.get_offset_on(NaiveDate::from_ymd_opt(2020, 11, 1).unwrap()), -5);
+    assert_eq!(config.get_offset_on(NaiveDate::from_ymd_opt(2020, 11, 12).unwrap()), -6);
+    assert_eq!(config.get_offset_on(NaiveDate::from_ymd_opt(2020, 12, 2).unwrap()), -6);
+}
+
+#[test]
+fn test_instance_of_config_file() {
+    assert_eq!(instance_of_config_file("bilibili", "bilibili.json"), Some(None));
+    assert_eq!(instance_of_config_file("bilibili", "bilibili.alice.json"), Some(Some("alice".to_string())));
+    assert_eq!(instance_of_config_file("bilibili", "bilibili.json.default"), None);
+    assert_eq!(instance_of_config_file("bilibili", "bilibili..json"), None);
+    assert_eq!(instance_of_config_file("league_of_graphs", "league_of_legends.json"), None);
+    assert_eq!(instance_of_config_file("league_of", "league_of_legends.json"), None);
+}
+
+#[test]
+fn test_instance_identifier() {
+    assert_eq!(instance_identifier("bilibili", None), "bilibili");
+    assert_eq!(instance_identifier("bilibili", Some("alice")), "bilibili.alice");
+    assert_eq!(module_of_instance("bilibili.alice"), "bilibili");
+    assert_eq!(module_of_instance("bilibili"), "bilibili");
+}
+
+#[test]
+fn test_render_template() {
+    // Any variable which is set does; the environment of the process is left alone for the other tests.
+    let path = std::env::var("PATH").unwrap();
+    let today = Local::now().format("%Y-%m-%d").to_string();
+    assert_eq!(
+        render_template("key={env:PATH}&day={date}&after={cursor}&region={region}", Some("42")).unwrap(),
+        format!("key={}&day={}&after=42&region={{region}}", path, today),
+    );
+    assert_eq!(render_template("after={cursor}", None).unwrap(), "after=");
+    assert!(render_template("{env:CALENDAR_AS_DIARY_TEST_MISSING}", None).is_err());
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = std::env::temp_dir().join("calendar_as_diary_test_secret");
+        fs::write(&path, "token\n").unwrap();
+        let template = format!("Bearer {{file:{}}}", path.display());
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        assert!(render_template(template.as_str(), None).is_err());
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        assert_eq!(render_template(template.as_str(), None).unwrap(), "Bearer token");
+        fs::remove_file(&path).unwrap();
+    }
+}
+
+#[test]
+fn test_build_request() {
+    let mut headers = HeaderMap::new();
+    headers_modifier(&HashMap::from([("X-Api-Key".to_string(), "{cursor}".to_string())]), &mut headers).unwrap();
+    assert!(headers_modifier(&HashMap::from([("bad header".to_string(), "".to_string())]), &mut HeaderMap::new()).is_err());
+    let config = RequestConfig {
+        headers,
+        payload: RequestPayload {
+            method: Method::POST,
+            query: HashMap::from([("page".to_string(), "{cursor}".to_string())]),
+            body: None,
+            form: HashMap::from([("user".to_string(), "me".to_string())]),
+        },
+        ..RequestConfig::for_test("https://example.com/api")
+    };
+    let client = reqwest::Client::new();
+
+    let request = config.build_request(&client, config.url.as_str(), true, Some("7")).unwrap().build().unwrap();
+    assert_eq!(request.method(), Method::POST);
+    assert_eq!(request.url().as_str(), "https://example.com/api?page=7");
+    assert_eq!(request.headers()["x-api-key"], "7");
+    assert_eq!(request.body().and_then(|body| body.as_bytes()), Some("user=me".as_bytes()));
+
+    let request = config.build_request(&client, "https://example.com/detail", false, Some("7")).unwrap().build().unwrap();
+    assert_eq!(request.method(), Method::GET);
+    assert_eq!(request.url().as_str(), "https://example.com/detail");
+    assert!(request.body().is_none());
+}
+
+#[test]
+fn test_config_problems_are_reported_together() {
+    let value = json::json!({"url": "", "calendar_id": "primary", "headers": {"cookie": "[cookie]"}});
+    let module_problems = vec!["either riot_id or puuid should be set.".to_string()];
+    let error = RequestConfig::from_config("league_of_legends", value, None, module_problems).err().unwrap();
+    assert_eq!(error.downcast_ref::<ConfigError>().unwrap().problems, vec![
+        "headers.cookie still holds the placeholder \"[cookie]\".",
+        "url is empty.",
+        "either riot_id or puuid should be set.",
+    ]);
+}
+
+#[test]
+fn test_write_atomically() {
+    let path = std::env::temp_dir().join("calendar_as_diary_test_state.json");
+    let path = path.to_str().unwrap();
+    write_json(path, &vec!["first"]).unwrap();
+    write_json(path, &vec!["second"]).unwrap();
+    assert_eq!(read_dumped_json::<Vec<String>>(path).unwrap(), vec!["second"]);
+    assert_eq!(read_json::<Vec<String>>(backup_path_of(path).as_str()).unwrap(), vec!["first"]);
+    assert!(!path_exists(format!("{}.tmp", path).as_str()));
+    write_json(path, &vec!["third"]).unwrap();
+    assert_eq!(read_json::<Vec<String>>(backup_path_of(path).as_str()).unwrap(), vec!["second"]);
+
+    // A file cut short by a crash is passed over for its backup.
+    fs::write(path, "[\"thi").unwrap();
+    assert_eq!(read_dumped_json::<Vec<String>>(path).unwrap(), vec!["second"]);
+    fs::remove_file(path).unwrap();
+    fs::remove_file(backup_path_of(path)).unwrap();
 }
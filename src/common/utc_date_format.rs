@@ -1,8 +1,8 @@
-use chrono::{DateTime, TimeZone, Utc};
+use chrono::{DateTime, NaiveDateTime, Utc};
 use serde::{self, Deserialize, Deserializer, Serializer};
 use serde::de;
 
-const FORMAT: &'static str = "%Y-%m-%dT%H:%M:%SZ";
+const FORMAT: &str = "%Y-%m-%dT%H:%M:%SZ";
 
 #[allow(dead_code)]
 pub fn serialize<S>(date: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
@@ -12,5 +12,5 @@ pub fn serialize<S>(date: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Err
 
 pub fn deserialize<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error> where D: Deserializer<'de> {
     let s = String::deserialize(deserializer)?;
-    Utc.datetime_from_str(&s, FORMAT).map_err(de::Error::custom)
+    NaiveDateTime::parse_from_str(&s, FORMAT).map(|date| date.and_utc()).map_err(de::Error::custom)
 }
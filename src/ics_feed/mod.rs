@@ -0,0 +1,780 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+
+use chrono::{DateTime, Days, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::calendar::event::*;
+use crate::calendar::event::Duration::{MultiDay, StartEnd, WholeDay};
+use crate::common::*;
+use crate::common::diagnostic::ParsedEvents;
+use crate::common::error::ModuleError;
+use crate::ics_feed::recurrence::RecurrenceRule;
+
+mod recurrence;
+
+const IDENTIFIER: &str = "ics_feed";
+
+#[derive(Debug, Deserialize)]
+struct IcsFeedConfig {
+    // Only events whose summary matches are mirrored.
+    summary_regex: Option<String>,
+    // Only events with at least one matching category are mirrored.
+    category_regex: Option<String>,
+    // Events starting outside of [now - past_days, now + future_days] are left out.
+    past_days: Option<i64>,
+    future_days: Option<i64>,
+}
+
+#[derive(Debug)]
+struct Property {
+    name: String,
+    params: HashMap<String, String>,
+    value: String,
+}
+
+#[derive(Debug)]
+enum IcsTime {
+    Date(NaiveDate),
+    DateTime(DateTime<Utc>),
+}
+
+// Wall-clock value of a time property before it is put in its zone, so that the occurrences of a
+// recurring event keep their time of day across daylight saving changes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LocalTime {
+    Date(NaiveDate),
+    DateTime(NaiveDateTime),
+}
+
+impl LocalTime {
+    fn naive(&self) -> NaiveDateTime {
+        match self {
+            LocalTime::Date(date) => date.and_time(NaiveTime::MIN),
+            LocalTime::DateTime(naive) => *naive,
+        }
+    }
+
+    fn with_naive(&self, naive: NaiveDateTime) -> LocalTime {
+        match self {
+            LocalTime::Date(_) => LocalTime::Date(naive.date()),
+            LocalTime::DateTime(_) => LocalTime::DateTime(naive),
+        }
+    }
+
+    // Same form as the value of DTSTART, which RECURRENCE-ID of a modified occurrence follows as well.
+    fn format_like(&self, value: &str) -> String {
+        match self {
+            LocalTime::Date(date) => date.format("%Y%m%d").to_string(),
+            LocalTime::DateTime(naive) if value.trim().ends_with('Z') => naive.format("%Y%m%dT%H%M%SZ").to_string(),
+            LocalTime::DateTime(naive) => naive.format("%Y%m%dT%H%M%S").to_string(),
+        }
+    }
+}
+
+// A VEVENT component with its properties in the order they appear in the feed; nested components
+// (e.g. VALARM) are left out.
+#[derive(Debug)]
+struct VEvent {
+    properties: Vec<Property>,
+}
+
+impl VEvent {
+    fn get(&self, name: &str) -> Option<&Property> {
+        self.properties.iter().find(|p| p.name == name)
+    }
+
+    fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item=&'a Property> {
+        self.properties.iter().filter(move |p| p.name == name)
+    }
+
+    fn text(&self, name: &str) -> Option<String> {
+        self.get(name).map(|p| unescape_text(p.value.as_str())).filter(|t| !t.is_empty())
+    }
+
+    fn uid(&self) -> Result<String, ModuleError> {
+        self.get("UID").map(|p| p.value.clone()).ok_or_else(|| ModuleError::parse("event", "missing property UID", ""))
+    }
+
+    // The occurrence a modified instance of a recurring event stands for.
+    fn recurrence_id(&self) -> Option<&str> {
+        self.get("RECURRENCE-ID").map(|p| p.value.as_str())
+    }
+
+    fn categories(&self) -> Vec<String> {
+        self.properties.iter()
+            .filter(|p| p.name == "CATEGORIES")
+            .flat_map(|p| p.value.split(','))
+            .map(|c| unescape_text(c.trim()))
+            .filter(|c| !c.is_empty())
+            .collect()
+    }
+
+    // Changes whenever anything but the generation stamp of the event changes, so that an updated event
+    // gets a new id and passes the dedup.
+    fn revision(&self) -> String {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for property in self.properties.iter().filter(|p| p.name != "DTSTAMP") {
+            for byte in property.name.bytes().chain(property.value.bytes()).chain(std::iter::once(b'\n')) {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x100000001b3);
+            }
+        }
+        format!("{:016x}", hash)
+    }
+
+    // Occurrences of a recurring event are told apart by their start, in the same way as the modified
+    // ones by their RECURRENCE-ID, which then replace them.
+    fn id(self: &VEvent, identifier: &str, occurrence: Option<&str>) -> Result<String, ModuleError> {
+        let occurrence = self.recurrence_id().or(occurrence).unwrap_or("");
+        Ok(format!("{}|{}|{}|{}", identifier, self.uid()?, occurrence, self.revision()))
+    }
+
+    fn ical_uid(&self, occurrence: Option<&str>) -> Result<String, ModuleError> {
+        Ok(match self.recurrence_id().or(occurrence) {
+            Some(recurrence_id) => format!("{}_{}", self.uid()?, recurrence_id),
+            None => self.uid()?,
+        })
+    }
+
+    fn dtstart(&self) -> Result<&Property, ModuleError> {
+        self.get("DTSTART").ok_or_else(|| ModuleError::parse("event", "missing property DTSTART", ""))
+    }
+
+    fn duration(&self) -> Result<crate::calendar::event::Duration, ModuleError> {
+        let start = parse_time(self.dtstart()?)?;
+        let end = self.get("DTEND").map(parse_time).transpose()?;
+        let length = self.get("DURATION").map(|p| parse_duration(p.value.as_str())).transpose()?;
+        Ok(match start {
+            IcsTime::Date(first) => {
+                // The end date is exclusive in iCal as well.
+                let end = match (end, length) {
+                    (Some(IcsTime::Date(end)), _) => end,
+                    (_, Some(length)) => first + length,
+                    _ => first + Days::new(1),
+                };
+                let last = end.checked_sub_days(Days::new(1)).filter(|last| *last > first);
+                match last {
+                    Some(last) => MultiDay(first, last),
+                    None => WholeDay(first),
+                }
+            }
+            IcsTime::DateTime(start) => {
+                let end = match (end, length) {
+                    (Some(IcsTime::DateTime(end)), _) => end,
+                    (_, Some(length)) => start + length,
+                    _ => start,
+                };
+                StartEnd(start, end)
+            }
+        })
+    }
+
+    // Starts of the occurrences of a recurring event (RRULE and RDATE, less EXDATE) within [first_day,
+    // last_day] of their own zone, or None if the event does not recur.
+    fn occurrences(&self, first_day: NaiveDate, last_day: NaiveDate) -> Result<Option<Vec<LocalTime>>, ModuleError> {
+        let rrule = self.get("RRULE");
+        if (rrule.is_none() && self.get("RDATE").is_none()) || self.recurrence_id().is_some() {
+            return Ok(None);
+        }
+        let dtstart = self.dtstart()?;
+        let start = parse_local_time(dtstart, dtstart.value.as_str())?;
+        let mut starts = vec![];
+        match rrule {
+            Some(rrule) => {
+                let rule = RecurrenceRule::parse(rrule.value.as_str())?;
+                let until = rule.until.as_deref().map(|until| parse_until(dtstart, until)).transpose()?;
+                for naive in rule.occurrences(start.naive(), first_day, last_day) {
+                    if let Some(until) = &until {
+                        if !until.includes(dtstart, naive)? {
+                            break;
+                        }
+                    }
+                    starts.push(start.with_naive(naive));
+                }
+            }
+            None => starts.push(start),
+        }
+        for rdate in self.get_all("RDATE") {
+            for value in rdate.value.split(',') {
+                // A period only counts by its start, the length being the one of the event.
+                let value = value.split('/').next().unwrap_or_default();
+                let naive = parse_local_time(rdate, value)?.naive();
+                if first_day <= naive.date() && naive.date() <= last_day {
+                    starts.push(start.with_naive(naive));
+                }
+            }
+        }
+        let mut excluded = vec![];
+        for exdate in self.get_all("EXDATE") {
+            for value in exdate.value.split(',') {
+                excluded.push(parse_local_time(exdate, value)?.naive());
+            }
+        }
+        starts.retain(|start| !excluded.contains(&start.naive()));
+        starts.sort_by_key(LocalTime::naive);
+        starts.dedup();
+        Ok(Some(starts))
+    }
+
+    // The occurrence starting at `start`, lasting as long as the event does.
+    fn duration_at(&self, duration: &crate::calendar::event::Duration, start: LocalTime) -> Result<crate::calendar::event::Duration, ModuleError> {
+        let dtstart = self.dtstart()?;
+        let first = start.naive().date();
+        Ok(match duration {
+            StartEnd(event_start, event_end) => {
+                let start = localize(dtstart, dtstart.value.as_str(), start.naive())?;
+                StartEnd(start, start + (*event_end - *event_start))
+            }
+            WholeDay(_) => WholeDay(first),
+            MultiDay(event_first, event_last) => MultiDay(first, first + (*event_last - *event_first)),
+        })
+    }
+}
+
+// End of a recurrence: a day for all-day events, or else a time in UTC (or floating, like DTSTART).
+enum Until {
+    Date(NaiveDate),
+    Utc(DateTime<Utc>),
+    Floating(NaiveDateTime),
+}
+
+impl Until {
+    fn includes(&self, dtstart: &Property, naive: NaiveDateTime) -> Result<bool, ModuleError> {
+        Ok(match self {
+            Until::Date(date) => naive.date() <= *date,
+            Until::Utc(until) => localize(dtstart, dtstart.value.as_str(), naive)? <= *until,
+            Until::Floating(until) => naive <= *until,
+        })
+    }
+}
+
+fn parse_until(dtstart: &Property, value: &str) -> Result<Until, ModuleError> {
+    let value = value.trim();
+    Ok(match parse_local_time(dtstart, value)? {
+        LocalTime::Date(date) => Until::Date(date),
+        LocalTime::DateTime(naive) if value.ends_with('Z') => Until::Utc(Utc.from_utc_datetime(&naive)),
+        LocalTime::DateTime(naive) => Until::Floating(naive),
+    })
+}
+
+fn start_date(duration: &crate::calendar::event::Duration) -> NaiveDate {
+    match duration {
+        StartEnd(start, _) => start.date_naive(),
+        WholeDay(day) | MultiDay(day, _) => *day,
+    }
+}
+
+// Long lines are folded by a line break followed by a space or a tab.
+fn unfold_lines(content: &str) -> Vec<String> {
+    let mut lines: Vec<String> = vec![];
+    for line in content.lines() {
+        match (line.strip_prefix(' ').or_else(|| line.strip_prefix('\t')), lines.last_mut()) {
+            (Some(continuation), Some(last)) => last.push_str(continuation),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+// e.g. `DTSTART;TZID="America/Chicago":20221104T150000`; colons and semicolons within quoted
+// parameter values do not count as separators.
+fn parse_property(line: &str) -> Option<Property> {
+    let mut in_quotes = false;
+    let mut separator = None;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            ':' if !in_quotes => {
+                separator = Some(i);
+                break;
+            }
+            _ => {}
+        }
+    }
+    let (head, value) = line.split_at(separator?);
+    let mut segments = head.split(';');
+    let name = segments.next()?.trim().to_uppercase();
+    let params = segments
+        .filter_map(|segment| segment.split_once('='))
+        .map(|(key, value)| (key.trim().to_uppercase(), value.trim().trim_matches('"').to_string()))
+        .collect();
+    Some(Property { name, params, value: value[1..].to_string() })
+}
+
+fn parse_vevents(content: &str) -> Vec<VEvent> {
+    let mut vevents = vec![];
+    let mut current: Option<VEvent> = None;
+    let mut nested = 0;
+    for property in unfold_lines(content).iter().filter_map(|line| parse_property(line)) {
+        match (property.name.as_str(), property.value.trim().to_uppercase().as_str()) {
+            ("BEGIN", "VEVENT") if current.is_none() => current = Some(VEvent { properties: vec![] }),
+            ("BEGIN", _) if current.is_some() => nested += 1,
+            ("END", "VEVENT") if nested == 0 => vevents.extend(current.take()),
+            ("END", _) if nested > 0 => nested -= 1,
+            _ => if let (Some(vevent), 0) = (current.as_mut(), nested) {
+                vevent.properties.push(property);
+            }
+        }
+    }
+    vevents
+}
+
+fn unescape_text(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => result.push('\n'),
+            Some(c) => result.push(c),
+            None => {}
+        }
+    }
+    result
+}
+
+// `value` is the one of the property, or one of them for properties listing several, e.g. EXDATE.
+fn parse_local_time(property: &Property, value: &str) -> Result<LocalTime, ModuleError> {
+    let value = value.trim();
+    if property.params.get("VALUE").map(String::as_str) == Some("DATE") || value.len() == 8 {
+        return NaiveDate::parse_from_str(value, "%Y%m%d")
+            .map(LocalTime::Date)
+            .map_err(|e| ModuleError::parse(property.name.as_str(), e, value));
+    }
+    NaiveDateTime::parse_from_str(value.trim_end_matches('Z'), "%Y%m%dT%H%M%S")
+        .map(LocalTime::DateTime)
+        .map_err(|e| ModuleError::parse(property.name.as_str(), e, value))
+}
+
+fn parse_time(property: &Property) -> Result<IcsTime, ModuleError> {
+    Ok(match parse_local_time(property, property.value.as_str())? {
+        LocalTime::Date(date) => IcsTime::Date(date),
+        LocalTime::DateTime(naive) => IcsTime::DateTime(localize(property, property.value.as_str(), naive)?),
+    })
+}
+
+// Times are either in UTC (trailing "Z"), in the zone named by TZID or floating, i.e. in local time.
+fn localize(property: &Property, value: &str, naive: NaiveDateTime) -> Result<DateTime<Utc>, ModuleError> {
+    let value = value.trim();
+    let time = if value.ends_with('Z') {
+        Some(Utc.from_utc_datetime(&naive))
+    } else {
+        match property.params.get("TZID").map(|tzid| tzid.trim_start_matches('/').parse::<Tz>()) {
+            Some(Ok(tz)) => tz.from_local_datetime(&naive).earliest().map(|t| t.with_timezone(&Utc)),
+            Some(Err(_)) => {
+                warn!("Unknown TZID {:?}; {} is taken as local time.", property.params.get("TZID"), value);
+                Local.from_local_datetime(&naive).earliest().map(|t| t.with_timezone(&Utc))
+            }
+            None => Local.from_local_datetime(&naive).earliest().map(|t| t.with_timezone(&Utc)),
+        }
+    };
+    time.ok_or_else(|| ModuleError::parse(property.name.as_str(), "not a local time", value))
+}
+
+// e.g. "PT1H30M", "P1D" or "P2W".
+fn parse_duration(s: &str) -> Result<Duration, ModuleError> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"^([+-])?P(?:(\d+)W)?(?:(\d+)D)?(?:T(?:(\d+)H)?(?:(\d+)M)?(?:(\d+)S)?)?$").unwrap();
+    }
+    let mat = RE.captures(s.trim()).ok_or_else(|| ModuleError::parse("DURATION", "not an iCal duration", s))?;
+    let part = |i: usize| mat.get(i).map_or(0, |m| m.as_str().parse::<i64>().unwrap_or(0));
+    let seconds = (((part(2) * 7 + part(3)) * 24 + part(4)) * 60 + part(5)) * 60 + part(6);
+    Ok(Duration::seconds(if mat.get(1).map(|m| m.as_str()) == Some("-") { -seconds } else { seconds }))
+}
+
+pub struct IcsFeed {
+    identifier: String,
+    request_config: RequestConfig,
+    event_ids: HashSet<String>,
+    summary_regex: Option<Regex>,
+    category_regex: Option<Regex>,
+    past_days: i64,
+    future_days: i64,
+}
+
+impl IcsFeed {
+    fn is_wanted(&self, vevent: &VEvent, summary: &str) -> bool {
+        if let Some(re) = &self.summary_regex {
+            if !re.is_match(summary) {
+                return false;
+            }
+        }
+        if let Some(re) = &self.category_regex {
+            if !vevent.categories().iter().any(|c| re.is_match(c)) {
+                return false;
+            }
+        }
+        true
+    }
+
+    // Events starting outside of these days are left out.
+    fn window(&self) -> (NaiveDate, NaiveDate) {
+        let today = Utc::now().date_naive();
+        (today - Duration::days(self.past_days), today + Duration::days(self.future_days))
+    }
+
+    // A recurring event gives one event per occurrence within the window, except for the occurrences
+    // replaced by a modified one, i.e. those in `overridden` by UID and RECURRENCE-ID.
+    fn vevent_into_event_with_id(&self, vevent: &VEvent, overridden: &HashSet<(String, NaiveDateTime)>) -> Result<Vec<EventWithId>, ModuleError> {
+        let summary = vevent.text("SUMMARY").unwrap_or_else(|| "[No summary]".to_string());
+        if !self.is_wanted(vevent, summary.as_str()) {
+            return Ok(vec![]);
+        }
+        let duration = vevent.duration()?;
+        let (first_day, last_day) = self.window();
+        // Occurrences are expanded in the zone of the event, so a day of margin covers those moved
+        // into the window by the conversion to UTC.
+        let occurrences = match vevent.occurrences(first_day - Days::new(1), last_day + Days::new(1))? {
+            None => vec![(None, duration)],
+            Some(starts) => {
+                let uid = vevent.uid()?;
+                let dtstart = vevent.dtstart()?;
+                let mut occurrences = vec![];
+                for start in starts.into_iter().filter(|start| !overridden.contains(&(uid.clone(), start.naive()))) {
+                    occurrences.push((Some(start.format_like(dtstart.value.as_str())), vevent.duration_at(&duration, start)?));
+                }
+                occurrences
+            }
+        };
+        let mut events = vec![];
+        for (occurrence, duration) in occurrences {
+            let start = start_date(&duration);
+            if first_day <= start && start <= last_day {
+                events.push(self.occurrence_into_event_with_id(vevent, summary.as_str(), occurrence.as_deref(), duration)?);
+            }
+        }
+        Ok(events)
+    }
+
+    fn occurrence_into_event_with_id(&self, vevent: &VEvent, summary: &str, occurrence: Option<&str>, duration: crate::calendar::event::Duration) -> Result<EventWithId, ModuleError> {
+        let summary = match vevent.get("STATUS").map(|p| p.value.as_str()) {
+            Some("CANCELLED") => format!("[Cancelled] {}", summary),
+            _ => summary.to_string(),
+        };
+        let id = vevent.id(self.identifier.as_str(), occurrence)?;
+        let mut description = vevent.text("DESCRIPTION").map(|d| d + "\n").unwrap_or_default();
+        let categories = vevent.categories();
+        if !categories.is_empty() {
+            description += format!("[categories] {}\n", categories.join(", ")).as_str();
+        }
+        if let Some(url) = vevent.text("URL") {
+            description += format!("[link] {}\n", url).as_str();
+        }
+        description += format!("[hash] {}", id).as_str();
+        Ok(EventWithId {
+            summary,
+            description,
+            duration,
+            id,
+            location: vevent.text("LOCATION"),
+            url: vevent.text("URL").filter(|url| url.starts_with("http")),
+            ical_uid: Some(vevent.ical_uid(occurrence)?),
+        })
+    }
+
+    // Every VEVENT is a row, reported by its UID when it cannot be converted.
+    fn vevents_into_event_with_id(&self, vevents: &[VEvent]) -> ParsedEvents {
+        let overridden: HashSet<(String, NaiveDateTime)> = vevents.iter()
+            .filter_map(|vevent| {
+                let recurrence_id = vevent.get("RECURRENCE-ID")?;
+                let occurrence = parse_local_time(recurrence_id, recurrence_id.value.as_str()).ok()?;
+                Some((vevent.uid().ok()?, occurrence.naive()))
+            })
+            .collect();
+        let mut parsed = ParsedEvents::default();
+        for (row, vevent) in vevents.iter().enumerate() {
+            match self.vevent_into_event_with_id(vevent, &overridden) {
+                Ok(events) => parsed.events.extend(events),
+                Err(e) => parsed.skip(row, &e, format!("UID:{}", vevent.uid().unwrap_or_default()).as_str()),
+            }
+        }
+        parsed
+    }
+
+    fn feed_into_event_with_id(&self, content: &str) -> ParsedEvents {
+        self.vevents_into_event_with_id(&parse_vevents(content))
+    }
+}
+
+impl Module for IcsFeed {
+    fn new(instance: Option<&str>, calendar_id: Option<String>) -> Result<Box<dyn Module>, Box<dyn Error>> {
+        let identifier = instance_identifier(IDENTIFIER, instance);
+        let (request_config, ics_feed_config) = read_configs::<IcsFeedConfig>(identifier.as_str(), calendar_id, |_| vec![])?;
+        let event_ids = read_dumped_event_id(identifier.as_str()).unwrap_or_default();
+        Ok(Box::new(IcsFeed {
+            identifier,
+            request_config,
+            event_ids,
+            summary_regex: ics_feed_config.summary_regex.as_deref().map(Regex::new).transpose()?,
+            category_regex: ics_feed_config.category_regex.as_deref().map(Regex::new).transpose()?,
+            past_days: ics_feed_config.past_days.unwrap_or(30),
+            future_days: ics_feed_config.future_days.unwrap_or(180),
+        }))
+    }
+
+    fn dump(&self) {
+        dump_event_id_wrapper(self.identifier.as_str(), &self.event_ids);
+    }
+
+    fn get_config(&self) -> &RequestConfig {
+        &(self.request_config)
+    }
+
+    fn get_event_ids(&mut self) -> &mut HashSet<String> {
+        &mut self.event_ids
+    }
+
+    fn get_identifier(&self) -> &str {
+        self.identifier.as_str()
+    }
+
+    // webcal:// is merely a hint for calendar apps to subscribe; the feed itself is served over https.
+    fn get_request_url(&self) -> String {
+        match self.request_config.url.strip_prefix("webcal://") {
+            Some(rest) => format!("https://{}", rest),
+            None => self.request_config.url.clone(),
+        }
+    }
+
+    fn need_for_detail(&self, _response: &String) -> Option<Vec<String>> {
+        None
+    }
+
+    fn process_response_into_event_with_id(&self, responses: Vec<String>) -> Result<ParsedEvents, ModuleError> {
+        let vevents: Vec<VEvent> = responses.iter().flat_map(|response| parse_vevents(response.as_str())).collect();
+        Ok(self.vevents_into_event_with_id(&vevents))
+    }
+
+    fn process_import_into_event_with_id(&self, content: String) -> Result<Vec<EventWithId>, Box<dyn Error>> {
+        let parsed = self.feed_into_event_with_id(content.as_str());
+        for diagnostic in &parsed.diagnostics {
+            warn!("Event in feed {} is skipped at {}.", self.identifier, diagnostic);
+        }
+        Ok(parsed.events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use chrono::{Duration, NaiveDate, TimeZone, Utc};
+    use regex::Regex;
+
+    use crate::calendar::event::Duration::{MultiDay, StartEnd, WholeDay};
+    use crate::common::{Module, RequestConfig};
+    use crate::ics_feed::{IcsFeed, parse_duration, parse_vevents};
+    use crate::test_support::{assert_golden, fetch_events, StubServer};
+
+    fn feed(summary_regex: Option<&str>, category_regex: Option<&str>) -> IcsFeed {
+        IcsFeed {
+            identifier: "ics_feed".to_string(),
+            request_config: RequestConfig::for_test(""),
+            event_ids: HashSet::new(),
+            summary_regex: summary_regex.map(|re| Regex::new(re).unwrap()),
+            category_regex: category_regex.map(|re| Regex::new(re).unwrap()),
+            past_days: 36500,
+            future_days: 36500,
+        }
+    }
+
+    const FEED: &str = "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+BEGIN:VEVENT\r
+UID:seminar-1@example.edu\r
+DTSTAMP:20221101T120000Z\r
+DTSTART;TZID=America/Chicago:20221104T150000\r
+DTEND;TZID=America/Chicago:20221104T160000\r
+SUMMARY:Seminar: Numerical methods\\, revisited\r
+DESCRIPTION:Speaker: Jane Doe\\nAbstract follows.\r
+LOCATION:POB 6.304\r
+CATEGORIES:Seminar,Math\r
+BEGIN:VALARM\r
+TRIGGER:-PT15M\r
+DESCRIPTION:Reminder\r
+END:VALARM\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:game-2@example.com\r
+DTSTART:20221105T230000Z\r
+DURATION:PT1H30M\r
+SUMMARY:Home game vs. Rivals with a summary folded\r
+  over two lines\r
+CATEGORIES:Sports\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:holiday-3@example.com\r
+DTSTART;VALUE=DATE:20221124\r
+DTEND;VALUE=DATE:20221126\r
+SUMMARY:Thanksgiving break\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:offsite-4@example.com\r
+DTSTART;VALUE=DATE:20221201\r
+SUMMARY:Team offsite\r
+STATUS:CANCELLED\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+
+    #[test]
+    fn test_parse_feed() {
+        let vevents = parse_vevents(FEED);
+        assert_eq!(vevents.len(), 4);
+        assert_eq!(vevents[0].get("DESCRIPTION").unwrap().value, "Speaker: Jane Doe\\nAbstract follows.");
+        assert_eq!(vevents[1].text("SUMMARY").unwrap(), "Home game vs. Rivals with a summary folded over two lines");
+
+        let events = feed(None, None).feed_into_event_with_id(FEED).events;
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[0].summary, "Seminar: Numerical methods, revisited");
+        assert!(events[0].description.starts_with("Speaker: Jane Doe\nAbstract follows.\n[categories] Seminar, Math\n"));
+        assert_eq!(events[0].location.as_deref(), Some("POB 6.304"));
+        assert_eq!(events[0].ical_uid.as_deref(), Some("seminar-1@example.edu"));
+        match events[0].duration {
+            StartEnd(start, end) => {
+                assert_eq!(start, Utc.with_ymd_and_hms(2022, 11, 4, 20, 0, 0).unwrap());
+                assert_eq!(end - start, Duration::hours(1));
+            }
+            _ => panic!(),
+        }
+        match events[1].duration {
+            StartEnd(start, end) => assert_eq!(end - start, Duration::minutes(90)),
+            _ => panic!(),
+        }
+        match events[2].duration {
+            MultiDay(first, last) => {
+                assert_eq!(first, NaiveDate::from_ymd_opt(2022, 11, 24).unwrap());
+                assert_eq!(last, NaiveDate::from_ymd_opt(2022, 11, 25).unwrap());
+            }
+            _ => panic!(),
+        }
+        assert!(matches!(events[3].duration, WholeDay(_)));
+        assert_eq!(events[3].summary, "[Cancelled] Team offsite");
+    }
+
+    #[test]
+    fn test_filter_feed() {
+        let events = feed(Some("(?i)seminar"), None).feed_into_event_with_id(FEED).events;
+        assert_eq!(events.len(), 1);
+        let events = feed(None, Some("^Sports$")).feed_into_event_with_id(FEED).events;
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].ical_uid.as_deref(), Some("game-2@example.com"));
+
+        let mut recent = feed(None, None);
+        recent.past_days = 0;
+        recent.future_days = 0;
+        assert!(recent.feed_into_event_with_id(FEED).events.is_empty());
+    }
+
+    #[test]
+    fn test_revision() {
+        let events = feed(None, None).feed_into_event_with_id(FEED).events;
+        let restamped = feed(None, None).feed_into_event_with_id(FEED.replace("DTSTAMP:20221101", "DTSTAMP:20221102").as_str()).events;
+        let moved = feed(None, None).feed_into_event_with_id(FEED.replace("20221104T160000", "20221104T170000").as_str()).events;
+        assert_eq!(events[0].id, restamped[0].id);
+        assert_ne!(events[0].id, moved[0].id);
+        assert_eq!(events[0].ical_uid, moved[0].ical_uid);
+        assert!(events[0].id.starts_with("ics_feed|seminar-1@example.edu||"));
+    }
+
+    const RECURRING: &str = "BEGIN:VCALENDAR
+BEGIN:VEVENT
+UID:office-5@example.edu
+DTSTART;TZID=America/Chicago:20221101T090000
+DTEND;TZID=America/Chicago:20221101T100000
+RRULE:FREQ=WEEKLY;BYDAY=TU,TH;COUNT=5
+EXDATE;TZID=America/Chicago:20221110T090000
+RDATE;TZID=America/Chicago:20221118T090000
+SUMMARY:Office hours
+END:VEVENT
+BEGIN:VEVENT
+UID:office-5@example.edu
+RECURRENCE-ID;TZID=America/Chicago:20221108T090000
+DTSTART;TZID=America/Chicago:20221108T130000
+DTEND;TZID=America/Chicago:20221108T140000
+SUMMARY:Office hours (moved)
+END:VEVENT
+BEGIN:VEVENT
+UID:standup-6@example.com
+DTSTART:20221101T140000Z
+DURATION:PT15M
+RRULE:FREQ=DAILY;UNTIL=20221103T140000Z
+SUMMARY:Standup
+END:VEVENT
+END:VCALENDAR
+";
+
+    #[test]
+    fn test_recurring_event() {
+        let events = feed(None, None).feed_into_event_with_id(RECURRING).events;
+        let starts: Vec<_> = events.iter()
+            .map(|event| match event.duration {
+                StartEnd(start, _) => (event.summary.as_str(), start),
+                _ => panic!(),
+            })
+            .collect();
+        assert_eq!(starts, vec![
+            // Time of day is kept across the end of daylight saving time on Nov 6.
+            ("Office hours", Utc.with_ymd_and_hms(2022, 11, 1, 14, 0, 0).unwrap()),
+            ("Office hours", Utc.with_ymd_and_hms(2022, 11, 3, 14, 0, 0).unwrap()),
+            ("Office hours", Utc.with_ymd_and_hms(2022, 11, 15, 15, 0, 0).unwrap()),
+            ("Office hours", Utc.with_ymd_and_hms(2022, 11, 18, 15, 0, 0).unwrap()),
+            ("Office hours (moved)", Utc.with_ymd_and_hms(2022, 11, 8, 19, 0, 0).unwrap()),
+            ("Standup", Utc.with_ymd_and_hms(2022, 11, 1, 14, 0, 0).unwrap()),
+            ("Standup", Utc.with_ymd_and_hms(2022, 11, 2, 14, 0, 0).unwrap()),
+            ("Standup", Utc.with_ymd_and_hms(2022, 11, 3, 14, 0, 0).unwrap()),
+        ]);
+        assert!(events[0].id.starts_with("ics_feed|office-5@example.edu|20221101T090000|"));
+        assert_eq!(events[0].ical_uid.as_deref(), Some("office-5@example.edu_20221101T090000"));
+        // The modified occurrence takes the place of the one it stands for.
+        assert!(events[4].id.starts_with("ics_feed|office-5@example.edu|20221108T090000|"));
+        assert_eq!(events[5].ical_uid.as_deref(), Some("standup-6@example.com_20221101T140000Z"));
+        match events[0].duration {
+            StartEnd(start, end) => assert_eq!(end - start, Duration::hours(1)),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn test_recurring_event_window() {
+        let endless = "BEGIN:VEVENT\nUID:daily-7@example.com\nDTSTART;VALUE=DATE:20200101\nRRULE:FREQ=DAILY\nSUMMARY:Journal\nEND:VEVENT\n";
+        let mut recent = feed(None, None);
+        recent.past_days = 1;
+        recent.future_days = 1;
+        let events = recent.feed_into_event_with_id(endless).events;
+        let today = Utc::now().date_naive();
+        let days: Vec<_> = events.iter()
+            .map(|event| match event.duration {
+                WholeDay(day) => day,
+                _ => panic!(),
+            })
+            .collect();
+        assert_eq!(days, vec![today - Duration::days(1), today, today + Duration::days(1)]);
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("PT1H30M").unwrap(), Duration::minutes(90));
+        assert_eq!(parse_duration("P1W2D").unwrap(), Duration::days(9));
+        assert_eq!(parse_duration("-PT15M").unwrap(), Duration::minutes(-15));
+        assert!(parse_duration("1 hour").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_fixture() {
+        let server = StubServer::start(&[("/calendar.ics", "ics_feed/feed.ics")]);
+        let mut module = feed(None, None);
+        module.request_config.url = server.url("/calendar.ics");
+        let mut module: Box<dyn Module> = Box::new(module);
+
+        let events = fetch_events(&mut module).await;
+        assert_golden("ics_feed/expected.txt", &events, &Utc);
+    }
+}
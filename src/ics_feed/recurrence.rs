@@ -0,0 +1,263 @@
+use chrono::{Datelike, Days, Months, NaiveDate, NaiveDateTime, Weekday};
+
+use crate::common::error::ModuleError;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+// A weekday of BYDAY, along with its position within the month if given, e.g. -1 for "-1FR".
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ByDay {
+    ordinal: Option<i32>,
+    weekday: Weekday,
+}
+
+// An RRULE with the parts feeds commonly use; other BY* parts are refused rather than expanded wrongly.
+#[derive(Debug, PartialEq)]
+pub struct RecurrenceRule {
+    frequency: Frequency,
+    interval: u32,
+    count: Option<usize>,
+    // Raw UNTIL value, whose zone is only known along with DTSTART.
+    pub until: Option<String>,
+    by_day: Vec<ByDay>,
+    by_month_day: Vec<i32>,
+    by_month: Vec<u32>,
+}
+
+fn parse_weekday(s: &str) -> Option<Weekday> {
+    Some(match s {
+        "MO" => Weekday::Mon,
+        "TU" => Weekday::Tue,
+        "WE" => Weekday::Wed,
+        "TH" => Weekday::Thu,
+        "FR" => Weekday::Fri,
+        "SA" => Weekday::Sat,
+        "SU" => Weekday::Sun,
+        _ => return None,
+    })
+}
+
+fn parse_by_day(s: &str) -> Option<ByDay> {
+    let split = s.len().checked_sub(2)?;
+    let (ordinal, weekday) = (s.get(..split)?, s.get(split..)?);
+    Some(ByDay {
+        ordinal: if ordinal.is_empty() { None } else { Some(ordinal.trim_start_matches('+').parse::<i32>().ok()?) },
+        weekday: parse_weekday(weekday)?,
+    })
+}
+
+fn parse_list<T>(value: &str, parse: impl Fn(&str) -> Option<T>) -> Option<Vec<T>> {
+    value.split(',').map(|item| parse(item.trim())).collect()
+}
+
+// Day `day` of the month starting on `first`, counted from the end if negative.
+fn month_day(first: NaiveDate, day: i32) -> Option<NaiveDate> {
+    if day > 0 {
+        first.with_day(day as u32)
+    } else {
+        let last = first.checked_add_months(Months::new(1))?.pred_opt()?;
+        last.checked_sub_days(Days::new((-day - 1) as u64)).filter(|date| date.month() == first.month())
+    }
+}
+
+// Days of the month starting on `first` which match one of `by_day`, e.g. the second Tuesday for "2TU".
+fn month_weekdays(first: NaiveDate, by_day: &[ByDay]) -> Vec<NaiveDate> {
+    let days: Vec<NaiveDate> = first.iter_days().take_while(|date| date.month() == first.month()).collect();
+    let mut dates = vec![];
+    for entry in by_day {
+        let matching: Vec<NaiveDate> = days.iter().copied().filter(|date| date.weekday() == entry.weekday).collect();
+        match entry.ordinal {
+            None => dates.extend(matching),
+            Some(n) if n > 0 => dates.extend(matching.get(n as usize - 1)),
+            Some(n) => dates.extend(matching.len().checked_sub((-n) as usize).and_then(|i| matching.get(i))),
+        }
+    }
+    dates
+}
+
+impl RecurrenceRule {
+    // e.g. "FREQ=WEEKLY;INTERVAL=2;BYDAY=TU,TH;UNTIL=20221215T000000Z".
+    pub fn parse(value: &str) -> Result<RecurrenceRule, ModuleError> {
+        let invalid = |reason: String| ModuleError::parse("RRULE", reason, value);
+        let mut rule = RecurrenceRule {
+            frequency: Frequency::Daily,
+            interval: 1,
+            count: None,
+            until: None,
+            by_day: vec![],
+            by_month_day: vec![],
+            by_month: vec![],
+        };
+        let mut frequency = None;
+        for part in value.split(';').filter(|part| !part.is_empty()) {
+            let (name, part_value) = part.split_once('=').ok_or_else(|| invalid(format!("{} is not a NAME=VALUE pair", part)))?;
+            let bad_value = || invalid(format!("invalid {}", name));
+            match name.to_uppercase().as_str() {
+                "FREQ" => frequency = Some(match part_value.to_uppercase().as_str() {
+                    "DAILY" => Frequency::Daily,
+                    "WEEKLY" => Frequency::Weekly,
+                    "MONTHLY" => Frequency::Monthly,
+                    "YEARLY" => Frequency::Yearly,
+                    other => return Err(invalid(format!("frequency {} is not supported", other))),
+                }),
+                "INTERVAL" => rule.interval = part_value.parse::<u32>().ok().filter(|interval| *interval > 0).ok_or_else(bad_value)?,
+                "COUNT" => rule.count = Some(part_value.parse::<usize>().map_err(|_| bad_value())?),
+                "UNTIL" => rule.until = Some(part_value.to_string()),
+                "BYDAY" => rule.by_day = parse_list(part_value.to_uppercase().as_str(), parse_by_day).ok_or_else(bad_value)?,
+                "BYMONTHDAY" => rule.by_month_day = parse_list(part_value, |day| day.parse::<i32>().ok().filter(|day| *day != 0 && day.abs() <= 31))
+                    .ok_or_else(bad_value)?,
+                "BYMONTH" => rule.by_month = parse_list(part_value, |month| month.parse::<u32>().ok().filter(|month| (1..=12).contains(month)))
+                    .ok_or_else(bad_value)?,
+                // Weeks starting on another day only matter for weekly rules with an interval, and are rare.
+                "WKST" => {}
+                other => return Err(invalid(format!("{} is not supported", other))),
+            }
+        }
+        rule.frequency = frequency.ok_or_else(|| invalid("FREQ is missing".to_string()))?;
+        if rule.frequency == Frequency::Yearly && rule.by_month.is_empty() && rule.by_day.iter().any(|entry| entry.ordinal.is_some()) {
+            return Err(invalid("BYDAY with a position within the year is not supported".to_string()));
+        }
+        Ok(rule)
+    }
+
+    // First day of the period `index` periods after the one of `start`, and the days of that period
+    // matching the rule, in order.
+    fn period(&self, start: NaiveDate, index: u32) -> Option<(NaiveDate, Vec<NaiveDate>)> {
+        let step = index.checked_mul(self.interval)?;
+        let month_dates = |first: NaiveDate, default_day: u32| -> Vec<NaiveDate> {
+            if !self.by_month_day.is_empty() {
+                let weekdays: Vec<Weekday> = self.by_day.iter().map(|entry| entry.weekday).collect();
+                self.by_month_day.iter()
+                    .filter_map(|day| month_day(first, *day))
+                    .filter(|date| weekdays.is_empty() || weekdays.contains(&date.weekday()))
+                    .collect()
+            } else if !self.by_day.is_empty() {
+                month_weekdays(first, &self.by_day)
+            } else {
+                first.with_day(default_day).into_iter().collect()
+            }
+        };
+        let (first, mut dates) = match self.frequency {
+            Frequency::Daily => {
+                let date = start.checked_add_days(Days::new(step as u64))?;
+                let matches = (self.by_day.is_empty() || self.by_day.iter().any(|entry| entry.weekday == date.weekday()))
+                    && (self.by_month_day.is_empty() || self.by_month_day.iter().any(|day| date.with_day(1).and_then(|first| month_day(first, *day)) == Some(date)));
+                (date, if matches { vec![date] } else { vec![] })
+            }
+            Frequency::Weekly => {
+                let week = start.checked_sub_days(Days::new(start.weekday().num_days_from_monday() as u64))?
+                    .checked_add_days(Days::new(step as u64 * 7))?;
+                let weekdays: Vec<Weekday> = match self.by_day.is_empty() {
+                    true => vec![start.weekday()],
+                    false => self.by_day.iter().map(|entry| entry.weekday).collect(),
+                };
+                (week, weekdays.iter().filter_map(|weekday| week.checked_add_days(Days::new(weekday.num_days_from_monday() as u64))).collect())
+            }
+            Frequency::Monthly => {
+                let first = start.with_day(1)?.checked_add_months(Months::new(step))?;
+                (first, month_dates(first, start.day()))
+            }
+            Frequency::Yearly => {
+                let first = NaiveDate::from_ymd_opt(start.year().checked_add(step as i32)?, 1, 1)?;
+                let months = match self.by_month.is_empty() {
+                    true => vec![start.month()],
+                    false => self.by_month.clone(),
+                };
+                let dates = months.iter()
+                    .filter_map(|month| first.with_month(*month))
+                    .flat_map(|first_of_month| month_dates(first_of_month, start.day()))
+                    .collect();
+                (first, dates)
+            }
+        };
+        if !self.by_month.is_empty() && self.frequency != Frequency::Yearly {
+            dates.retain(|date| self.by_month.contains(&date.month()));
+        }
+        dates.sort();
+        dates.dedup();
+        Some((first, dates))
+    }
+
+    // Starts of the occurrences which fall within [first_day, last_day], in order; `start` (DTSTART) is
+    // always the first occurrence, and COUNT is counted from it even before `first_day`. UNTIL is left to
+    // the caller.
+    pub fn occurrences(&self, start: NaiveDateTime, first_day: NaiveDate, last_day: NaiveDate) -> Vec<NaiveDateTime> {
+        let mut occurrences = vec![];
+        let mut count = 0;
+        let mut add = |date: NaiveDate| -> bool {
+            count += 1;
+            if self.count.is_some_and(|max| count > max) {
+                return false;
+            }
+            if first_day <= date && date <= last_day {
+                occurrences.push(date.and_time(start.time()));
+            }
+            true
+        };
+        if !add(start.date()) {
+            return occurrences;
+        }
+        for index in 0.. {
+            let (first, dates) = match self.period(start.date(), index) {
+                Some(period) if period.0 <= last_day => period,
+                _ => break,
+            };
+            for date in dates.into_iter().filter(|date| *date > start.date() && *date >= first) {
+                if date > last_day || !add(date) {
+                    return occurrences;
+                }
+            }
+        }
+        occurrences
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, NaiveDateTime};
+
+    use crate::ics_feed::recurrence::RecurrenceRule;
+
+    fn day(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn expand(rule: &str, start: NaiveDate, first_day: NaiveDate, last_day: NaiveDate) -> Vec<NaiveDate> {
+        let start = start.and_hms_opt(15, 0, 0).unwrap();
+        RecurrenceRule::parse(rule).unwrap().occurrences(start, first_day, last_day).iter().map(NaiveDateTime::date).collect()
+    }
+
+    #[test]
+    fn test_occurrences() {
+        // A weekly seminar on Fridays, of which only the ones within the window are kept.
+        assert_eq!(expand("FREQ=WEEKLY", day(2022, 11, 4), day(2022, 11, 10), day(2022, 11, 30)),
+                   vec![day(2022, 11, 11), day(2022, 11, 18), day(2022, 11, 25)]);
+        assert_eq!(expand("FREQ=WEEKLY;INTERVAL=2;BYDAY=TU,TH;COUNT=5", day(2022, 11, 1), day(2022, 1, 1), day(2023, 1, 1)),
+                   vec![day(2022, 11, 1), day(2022, 11, 3), day(2022, 11, 15), day(2022, 11, 17), day(2022, 11, 29)]);
+        // COUNT is counted from DTSTART, even before the window.
+        assert_eq!(expand("FREQ=DAILY;COUNT=3", day(2022, 11, 1), day(2022, 11, 3), day(2022, 11, 30)), vec![day(2022, 11, 3)]);
+        assert_eq!(expand("FREQ=MONTHLY;BYDAY=-1FR;COUNT=3", day(2022, 11, 25), day(2022, 1, 1), day(2023, 12, 31)),
+                   vec![day(2022, 11, 25), day(2022, 12, 30), day(2023, 1, 27)]);
+        assert_eq!(expand("FREQ=MONTHLY;COUNT=3", day(2023, 1, 31), day(2023, 1, 1), day(2023, 12, 31)),
+                   vec![day(2023, 1, 31), day(2023, 3, 31), day(2023, 5, 31)]);
+        assert_eq!(expand("FREQ=YEARLY;BYMONTH=11;BYDAY=4TH;COUNT=2", day(2022, 11, 24), day(2022, 1, 1), day(2030, 1, 1)),
+                   vec![day(2022, 11, 24), day(2023, 11, 23)]);
+        // Without COUNT or UNTIL, the expansion stops at the end of the window.
+        assert_eq!(expand("FREQ=DAILY;INTERVAL=10", day(2022, 11, 1), day(2022, 11, 1), day(2022, 11, 30)).len(), 3);
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(RecurrenceRule::parse("FREQ=WEEKLY;UNTIL=20221215T000000Z").unwrap().until.as_deref(), Some("20221215T000000Z"));
+        assert!(RecurrenceRule::parse("INTERVAL=2").is_err());
+        assert!(RecurrenceRule::parse("FREQ=HOURLY").is_err());
+        assert!(RecurrenceRule::parse("FREQ=MONTHLY;BYSETPOS=-1;BYDAY=MO,TU,WE,TH,FR").is_err());
+        assert!(RecurrenceRule::parse("FREQ=YEARLY;BYDAY=20MO").is_err());
+    }
+}
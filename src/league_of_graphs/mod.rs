@@ -1,16 +1,17 @@
 use std::collections::HashSet;
 use std::error::Error;
-use std::fmt;
 
 use chrono::{DateTime, Duration, TimeZone, Utc};
 use lazy_static::lazy_static;
 use regex::Regex;
 use scraper::{ElementRef, Html, Selector};
+use serde::Deserialize;
 
 use crate::calendar::event::*;
 use crate::calendar::event::Duration::StartEnd;
 use crate::common::*;
-use crate::league_of_graphs::ParseError::*;
+use crate::common::diagnostic::ParsedEvents;
+use crate::common::error::ModuleError;
 
 const IDENTIFIER: &str = "league_of_graphs";
 
@@ -20,111 +21,178 @@ struct GameObject {
     creation: DateTime<Utc>,
     duration: i64,
     mode: String,
+    champion: Option<String>,
+    result: Option<String>,
+    kda: Option<String>,
+    items: Vec<String>,
+    lp_change: Option<String>,
 }
 
 impl GameObject {
-    fn id(self: &GameObject) -> String {
-        format!("{}|{}", IDENTIFIER, self.id)
+    fn id(self: &GameObject, identifier: &str) -> String {
+        format!("{}|{}", identifier, self.id)
+    }
+
+    // e.g. "ARAM – Jinx – Victory 12/3/20", leaving out what is missing in the row.
+    fn title(self: &GameObject) -> String {
+        let outcome = [&self.result, &self.kda].iter().filter_map(|s| s.as_deref()).collect::<Vec<&str>>().join(" ");
+        let mut parts = vec![self.mode.as_str()];
+        parts.extend(self.champion.as_deref());
+        if !outcome.is_empty() {
+            parts.push(outcome.as_str());
+        }
+        parts.join(" – ")
     }
 }
 
+#[derive(Debug, Deserialize)]
+struct LeagueOfGraphsConfig {
+    // Region as it appears in League of Graphs links, e.g. "na", "euw" or "kr".
+    region: Option<String>,
+}
 
-#[derive(Debug, Clone)]
-enum ParseError {
-    DurationError(String),
-    ParseFails(String),
-    IdError(String),
-    DateError(String),
+fn select_helper<'a>(element: &ElementRef<'a>, selector: &Selector, name: &str) -> Result<ElementRef<'a>, ModuleError> {
+    element.select(selector).next().ok_or_else(|| ModuleError::parse("game row", format!("{} is missing", name), element.html().as_str()))
 }
 
-impl fmt::Display for ParseError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            DurationError(t) => write!(f, "Duration error: {}", t),
-            ParseFails(t) => write!(f, "Parse fails for {}", t),
-            IdError(t) => write!(f, "Id error: {}", t),
-            DateError(t) => write!(f, "Date error: {}", t),
-        }
-    }
+fn parse_number<T: std::str::FromStr>(s: &str, context: &str) -> Result<T, ModuleError> where T::Err: std::fmt::Display {
+    s.parse::<T>().map_err(|e| ModuleError::parse(context, e, s))
 }
 
-impl Error for ParseError {}
+fn parse_duration(s: &str) -> Result<i64, ModuleError> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"(\d+)min (\d+)s").unwrap();
+    }
+    let mat = RE.captures_iter(s).next().ok_or_else(|| ModuleError::parse("game duration", "no minutes and seconds", s))?;
 
-fn parse_selector(selection: &str) -> Result<Selector, ParseError> {
-    Selector::parse(selection).map_err(|_e| ParseFails(format!("selector {}", selection)))
+    let minute = parse_number::<i64>(&mat[1], "game duration")?;
+    let second = parse_number::<i64>(&mat[2], "game duration")?;
+    Ok(minute * 60 + second)
 }
 
-fn select_helper<'a, 'b>(element: &ElementRef<'a>, selector: &'b Selector) -> Result<ElementRef<'a>, ParseError> {
-    element.select(selector).next().ok_or_else(|| ParseFails(format!("fails to select {:?}", selector)))
+fn select_text(element: &ElementRef, selector: &Selector) -> Option<String> {
+    element.select(selector).next()
+        .map(|e| e.text().collect::<String>().trim().to_string())
+        .filter(|t| !t.is_empty())
 }
 
-fn parse_duration(s: &str) -> Result<i64, Box<dyn Error>> {
+// Details shown in a row besides the ones needed to place the game in the calendar; all of them are
+// optional since League of Graphs leaves some columns out depending on the game mode.
+fn parse_game_details(row: &ElementRef, game: &mut GameObject) {
     lazy_static! {
-        static ref RE: Regex = Regex::new(r"(\d+)min (\d+)s").unwrap();
+        static ref CHAMPION_SELECTOR: Selector = Selector::parse("td.championCellLight img").unwrap();
+        static ref RESULT_SELECTOR: Selector = Selector::parse("div.victoryDefeatText").unwrap();
+        static ref KILLS_SELECTOR: Selector = Selector::parse("td.kdaColumn span.kills").unwrap();
+        static ref DEATHS_SELECTOR: Selector = Selector::parse("td.kdaColumn span.deaths").unwrap();
+        static ref ASSISTS_SELECTOR: Selector = Selector::parse("td.kdaColumn span.assists").unwrap();
+        static ref ITEMS_SELECTOR: Selector = Selector::parse("td.itemsColumnLight img, td.itemsColumnDark img").unwrap();
+        static ref LP_CHANGE_SELECTOR: Selector = Selector::parse("div.lpChange").unwrap();
     }
-    let mat = RE.captures_iter(s).next().ok_or(Box::new(DurationError(s.to_string())))?;
 
-    let minute = mat[1].parse::<i64>()?;
-    let second = mat[2].parse::<i64>()?;
-    return Ok(minute * 60 + second);
+    game.champion = row.select(&CHAMPION_SELECTOR).next()
+        .and_then(|e| e.value().attr("alt").or_else(|| e.value().attr("title")))
+        .map(|s| s.trim().to_string());
+    game.result = select_text(row, &RESULT_SELECTOR);
+    game.kda = match (select_text(row, &KILLS_SELECTOR), select_text(row, &DEATHS_SELECTOR), select_text(row, &ASSISTS_SELECTOR)) {
+        (Some(k), Some(d), Some(a)) => Some(format!("{}/{}/{}", k, d, a)),
+        _ => None,
+    };
+    game.items = row.select(&ITEMS_SELECTOR).filter_map(|e| e.value().attr("alt")).map(|s| s.trim().to_string()).collect();
+    game.lp_change = select_text(row, &LP_CHANGE_SELECTOR);
 }
 
-fn parse_individual_game(script: &str, mode: &str, duration: &str) -> Result<GameObject, Box<dyn Error>> {
+fn parse_individual_game(script: &str, mode: &str, duration: &str) -> Result<GameObject, ModuleError> {
     lazy_static! {
         static ref ID_RE: Regex = Regex::new(r"match-(\d+)").unwrap();
         static ref DATE_RE: Regex = Regex::new(r"new Date\((\d+)").unwrap();
     }
 
-    let game_id = ID_RE.captures_iter(script).next().ok_or(Box::new(IdError(script.to_string())))?;
-    let game_date = DATE_RE.captures_iter(script).next().ok_or(Box::new(DateError(script.to_string())))?;
+    let game_id = ID_RE.captures_iter(script).next().ok_or_else(|| ModuleError::parse("game script", "no match id", script))?;
+    let game_date = DATE_RE.captures_iter(script).next().ok_or_else(|| ModuleError::parse("game script", "no date", script))?;
+    let creation = Utc.timestamp_millis_opt(parse_number::<i64>(&game_date[1], "game date")?).single()
+        .ok_or_else(|| ModuleError::parse("game date", "out of range", &game_date[1]))?;
 
     Ok(GameObject {
-        id: game_id[1].parse::<u64>()?,
-        creation: Utc.timestamp_millis(game_date[1].parse::<i64>()?),
+        id: parse_number::<u64>(&game_id[1], "match id")?,
+        creation,
         duration: parse_duration(duration.trim())?,
         mode: mode.trim().to_string(),
+        champion: None,
+        result: None,
+        kda: None,
+        items: vec![],
+        lp_change: None,
     })
 }
 
-fn parse_games(response: &str) -> Result<Vec<GameObject>, Box<dyn Error>> {
+// One result per row of the table, so that a broken row does not cost the others.
+fn parse_games(response: &str) -> Vec<Result<GameObject, ModuleError>> {
+    lazy_static! {
+        static ref TR_SELECTOR: Selector = Selector::parse("tr[class=\"\"]").unwrap();
+        static ref SCRIPT_SELECTOR: Selector = Selector::parse("script").unwrap();
+        static ref GAME_MODE_SELECTOR: Selector = Selector::parse("div.gameMode").unwrap();
+        static ref GAME_DURATION_SELECTOR: Selector = Selector::parse("div.gameDuration").unwrap();
+    }
     let document = Html::parse_document(response);
-    let tr_selector = parse_selector("tr[class=\"\"]")?;
-    let script_selector = parse_selector("script")?;
-    let div_game_mode_selector = parse_selector("div.gameMode")?;
-    let div_game_duration_selector = parse_selector("div.gameDuration")?;
-
-    Ok(document.select(&tr_selector).into_iter().map(|row| {
-        // println!("{}", row.inner_html());
-        let script_content = select_helper(&row, &script_selector)?.inner_html();
-        let game_mode = select_helper(&row, &div_game_mode_selector)?.inner_html();
-        let game_duration = select_helper(&row, &div_game_duration_selector)?.inner_html();
-        parse_individual_game(&script_content, &game_mode, &game_duration)
-    }).filter_map(|r| match r {
-        Ok(r) => Some(r),
-        Err(e) => {
-            println!("{:?}", e);
-            None
-        }
-    }).collect())
+
+    document.select(&TR_SELECTOR).map(|row| -> Result<GameObject, ModuleError> {
+        let script_content = select_helper(&row, &SCRIPT_SELECTOR, "script")?.inner_html();
+        let game_mode = select_helper(&row, &GAME_MODE_SELECTOR, "div.gameMode")?.inner_html();
+        let game_duration = select_helper(&row, &GAME_DURATION_SELECTOR, "div.gameDuration")?.inner_html();
+        let mut game = parse_individual_game(&script_content, &game_mode, &game_duration)?;
+        parse_game_details(&row, &mut game);
+        Ok(game)
+    }).collect()
 }
 
 pub struct LeagueOfGraphs {
+    identifier: String,
     request_config: RequestConfig,
     event_ids: HashSet<String>,
+    region: String,
+}
+
+impl LeagueOfGraphs {
+    fn game_into_event_with_id(&self, game: GameObject) -> EventWithId {
+        let link = format!("https://www.leagueofgraphs.com/match/{}/{}", self.region, game.id);
+        let mut description = format!("[link] {}\n[mode] {}", link, game.mode);
+        let details = [("champion", &game.champion), ("result", &game.result), ("kda", &game.kda), ("lp", &game.lp_change)];
+        for (key, value) in details.iter() {
+            if let Some(value) = value {
+                description += format!("\n[{}] {}", key, value).as_str();
+            }
+        }
+        if !game.items.is_empty() {
+            description += format!("\n[items] {}", game.items.join(", ")).as_str();
+        }
+        description += format!("\n[hash] {}", game.id(self.identifier.as_str())).as_str();
+        EventWithId {
+            summary: format!("[LoL] {}", game.title()),
+            description,
+            duration: StartEnd(game.creation, game.creation + Duration::seconds(game.duration)),
+            id: game.id(self.identifier.as_str()),
+            location: None,
+            url: Some(link),
+            ical_uid: None,
+        }
+    }
 }
 
 impl Module for LeagueOfGraphs {
-    fn new(calendar_id: Option<String>) -> Result<Box<dyn Module>, Box<dyn Error>> {
-        let request_config = RequestConfig::new(IDENTIFIER, calendar_id)?;
-        let event_ids = read_dumped_event_id(IDENTIFIER).unwrap_or(HashSet::new());
+    fn new(instance: Option<&str>, calendar_id: Option<String>) -> Result<Box<dyn Module>, Box<dyn Error>> {
+        let identifier = instance_identifier(IDENTIFIER, instance);
+        let (request_config, league_of_graphs_config) = read_configs::<LeagueOfGraphsConfig>(identifier.as_str(), calendar_id, |_| vec![])?;
+        let event_ids = read_dumped_event_id(identifier.as_str()).unwrap_or_default();
         Ok(Box::new(LeagueOfGraphs {
+            identifier,
             request_config,
             event_ids,
+            region: league_of_graphs_config.region.unwrap_or_else(|| "na".to_string()),
         }))
     }
 
     fn dump(&self) {
-        dump_event_id_wrapper(IDENTIFIER, &self.event_ids);
+        dump_event_id_wrapper(self.identifier.as_str(), &self.event_ids);
     }
 
     fn get_config(&self) -> &RequestConfig {
@@ -136,44 +204,43 @@ impl Module for LeagueOfGraphs {
     }
 
     fn get_identifier(&self) -> &str {
-        IDENTIFIER
+        self.identifier.as_str()
     }
 
     fn get_request_url(&self) -> String {
-        self.request_config.url.to_string()
+        self.request_config.url.replace("{region}", self.region.as_str())
     }
 
     fn need_for_detail(&self, _response: &String) -> Option<Vec<String>> {
         None
     }
 
-    fn process_response_into_event_with_id(&self, responses: Vec<String>) -> Result<Vec<EventWithId>, Box<dyn Error>> {
-        match parse_games(responses[0].as_str()) {
-            Ok(v) => Ok(v.into_iter().map(|r| {
-                EventWithId {
-                    summary: format!("[League of Legends] {}", r.mode),
-                    description: format!("[link] https://www.leagueofgraphs.com/match/na/{}\n[mode] {}\n[hash] {}", r.id, r.mode, r.id()),
-                    duration: StartEnd(r.creation, r.creation + Duration::seconds(r.duration)),
-                    id: r.id(),
-                }
-            }).collect()),
-            Err(e) => {
-                info!("{}", e);
-                Err(e)
+    fn process_response_into_event_with_id(&self, responses: Vec<String>) -> Result<ParsedEvents, ModuleError> {
+        let mut parsed = ParsedEvents::default();
+        let games = responses.iter().flat_map(|response| parse_games(response.as_str()));
+        for (row, game) in games.enumerate() {
+            match game {
+                Ok(game) => parsed.events.push(self.game_into_event_with_id(game)),
+                Err(e) => parsed.skip(row, &e, ""),
             }
         }
+        Ok(parsed)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashSet;
     use std::error::Error;
 
+    use chrono::Utc;
     use lazy_static::lazy_static;
     use regex::Regex;
-    use scraper::Html;
+    use scraper::{Html, Selector};
 
-    use crate::league_of_graphs::{parse_duration, parse_games, parse_selector, select_helper};
+    use crate::common::{Module, RequestConfig};
+    use crate::league_of_graphs::{LeagueOfGraphs, parse_duration, parse_games, select_helper};
+    use crate::test_support::{assert_golden, fetch_events, StubServer};
 
     #[test]
     fn test_regex() {
@@ -194,10 +261,10 @@ mod tests {
         fn worker() -> Result<String, Box<dyn Error>> {
             let response = "<div><span></span></div>";
             let document = Html::parse_document(response);
-            let div_selector = parse_selector("div")?;
-            let div_element = select_helper(&document.root_element(), &div_selector)?;
-            let a_selector = parse_selector("a")?;
-            let a_element = select_helper(&div_element, &a_selector)?;
+            let div_selector = Selector::parse("div").unwrap();
+            let div_element = select_helper(&document.root_element(), &div_selector, "div")?;
+            let a_selector = Selector::parse("a").unwrap();
+            let a_element = select_helper(&div_element, &a_selector, "a")?;
             Ok(a_element.inner_html())
         }
 
@@ -229,7 +296,7 @@ mod tests {
         <tr class=\"\">
 			<td class=\"championCellLight\">
 			    <a href=\"/match/na/4471269577#participant9\">
-			        <div></div>
+			        <div><img class=\"champion-222-48\" alt=\"Jinx\" title=\"Jinx\"></div>
 			        <div class=\"spells\"></div>
 			    </a>
 			</td>
@@ -262,8 +329,10 @@ mod tests {
 			</td>
 
 			<td class=\"resultCellDark nopadding\"></td>
-			<td class=\"text-center nopadding kdaColumn \"></td>
-    		<td class=\"itemsColumnLight\"></td>
+			<td class=\"text-center nopadding kdaColumn \">
+			    <div class=\"kda\"><span class=\"kills\">12</span>/<span class=\"deaths\">3</span>/<span class=\"assists\">20</span></div>
+			</td>
+    		<td class=\"itemsColumnLight\"><img class=\"item-3031\" alt=\"Infinity Edge\"><img class=\"item-3006\" alt=\"Berserker's Greaves\"></td>
 			<td class=\"itemsColumnDark\"></td>
 			<td class=\"summonersTdLight\"></td>
 			<td class=\"summonersTdDark\"></td>
@@ -295,7 +364,7 @@ mod tests {
 			        <div class=\"victoryDefeatText defeat\">Defeat</div>        <div class=\"gameMode requireTooltip\" tooltip-vertical-offset=\"0\" tooltip=\"ARAM\">ARAM        </div>
 			        <div class=\"gameDate requireTooltip\" tooltip-vertical-offset=\"0\" tooltip-var=\"match-4471295235\">16 hours ago        </div>
 			        <div class=\"gameDuration\">18min 31s        </div>
-			        <div class=\"lpChange\"></div>
+			        <div class=\"lpChange\">-15 LP</div>
 			    </a>
 			</td>
 
@@ -309,9 +378,44 @@ mod tests {
     </tbody>
 </table>
 ";
-        match parse_games(response) {
-            Ok(item) => println!("{:#?}", item),
-            Err(e) => println!("{:#?}", e),
-        }
+        let games = parse_games(response).into_iter().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].title(), "ARAM – Jinx – Victory 12/3/20");
+        assert_eq!(games[0].items, vec!["Infinity Edge", "Berserker's Greaves"]);
+        assert_eq!(games[0].lp_change, None);
+        assert_eq!(games[1].title(), "ARAM – Defeat");
+        assert_eq!(games[1].lp_change, Some("-15 LP".to_string()));
+
+        let module = LeagueOfGraphs {
+            request_config: RequestConfig::for_test("https://www.leagueofgraphs.com/partial/summoner/{region}/player_id"),
+            identifier: "league_of_graphs".to_string(),
+            event_ids: HashSet::new(),
+            region: "euw".to_string(),
+        };
+        assert_eq!(module.get_request_url(), "https://www.leagueofgraphs.com/partial/summoner/euw/player_id");
+        let event = module.game_into_event_with_id(games.into_iter().next().unwrap());
+        assert_eq!(event.summary, "[LoL] ARAM – Jinx – Victory 12/3/20");
+        assert_eq!(event.description, "[link] https://www.leagueofgraphs.com/match/euw/4471269577\n[mode] ARAM\n[champion] Jinx\n[result] Victory\n[kda] 12/3/20\n[items] Infinity Edge, Berserker's Greaves\n[hash] league_of_graphs|4471269577");
+
+        // A broken row is reported while the other one is kept.
+        let parsed = module.process_response_into_event_with_id(vec![response.replace("18min 31s", "18 minutes")]).unwrap();
+        assert_eq!(parsed.events.len(), 1);
+        assert_eq!(parsed.diagnostics.len(), 1);
+        assert_eq!(parsed.diagnostics[0].row, 1);
+        assert_eq!(parsed.diagnostics[0].reason, "cannot parse game duration: no minutes and seconds");
+    }
+
+    #[tokio::test]
+    async fn test_fixture() {
+        let server = StubServer::start(&[("/partial/summoner/euw/player_id", "league_of_graphs/recent_games.html")]);
+        let mut module: Box<dyn Module> = Box::new(LeagueOfGraphs {
+            request_config: RequestConfig::for_test(&server.url("/partial/summoner/{region}/player_id")),
+            identifier: "league_of_graphs".to_string(),
+            event_ids: HashSet::new(),
+            region: "euw".to_string(),
+        });
+
+        let events = fetch_events(&mut module).await;
+        assert_golden("league_of_graphs/expected.txt", &events, &Utc);
     }
 }
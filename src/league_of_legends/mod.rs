@@ -1,78 +1,213 @@
 use std::collections::HashSet;
 use std::error::Error;
 
-use chrono::{Duration, TimeZone, Utc};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use reqwest::header::HeaderValue;
+use reqwest::Url;
 use serde::Deserialize;
 use serde_json::Number;
 
 use crate::calendar::event::*;
 use crate::calendar::event::Duration::StartEnd;
 use crate::common::*;
+use crate::common::diagnostic::ParsedEvents;
+use crate::common::error::ModuleError;
 
 const IDENTIFIER: &str = "league_of_legends";
+// Matches listed per request at most, as allowed by match-v5.
+const MAX_MATCH_COUNT: u32 = 100;
+
+#[derive(Debug, Deserialize)]
+struct Account {
+    puuid: String,
+}
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct Response {
-    platform_id: String,
-    account_id: Number,
-    games: GamesObject,
+struct MatchObject {
+    metadata: MatchMetadata,
+    info: MatchInfo,
 }
 
 #[derive(Debug, Deserialize)]
-struct GamesObject {
-    games: Vec<GameObject>,
+#[serde(rename_all = "camelCase")]
+struct MatchMetadata {
+    match_id: String,
 }
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct GameObject {
+struct MatchInfo {
     game_id: Number,
     platform_id: String,
     game_creation: Number,
     game_duration: Number,
-    queue_id: Number,
+    game_start_timestamp: Option<Number>,
+    game_end_timestamp: Option<Number>,
     game_mode: String,
-    game_type: String,
-    participant_identities: Vec<ParticipantIdentity>,
+    queue_id: Number,
+    participants: Vec<Participant>,
 }
 
 #[derive(Debug, Deserialize)]
-struct ParticipantIdentity {
-    player: Player,
+#[serde(rename_all = "camelCase")]
+struct Participant {
+    puuid: String,
+    champion_name: String,
+    win: bool,
+    kills: Number,
+    deaths: Number,
+    assists: Number,
 }
 
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct Player {
-    summoner_name: String,
-    match_history_uri: String,
-    account_id: Number,
+impl MatchObject {
+    fn id(self: &MatchObject, identifier: &str, puuid: &str) -> String {
+        format!("{}|{}|{}|{}", identifier, self.info.platform_id, self.info.game_id, puuid)
+    }
+
+    fn context(self: &MatchObject) -> String {
+        format!("match {}", self.metadata.match_id)
+    }
+
+    fn start_end(self: &MatchObject) -> Result<(DateTime<Utc>, DateTime<Utc>), ModuleError> {
+        let millis = |n: &Number| n.as_i64().and_then(DateTime::from_timestamp_millis);
+        let start = self.info.game_start_timestamp.as_ref().and_then(millis)
+            .or_else(|| millis(&self.info.game_creation))
+            .ok_or_else(|| ModuleError::parse(self.context(), format!("invalid creation time {}", self.info.game_creation), ""))?;
+        let end = match self.info.game_end_timestamp.as_ref().and_then(millis) {
+            Some(end) => end,
+            None => start + Duration::seconds(self.info.game_duration.as_i64().unwrap_or(0)),
+        };
+        Ok((start, end))
+    }
 }
 
-impl GameObject {
-    fn id(self: &GameObject) -> String {
-        format!("{}|{}|{}|{}", IDENTIFIER, self.platform_id, self.game_id, self.participant_identities[0].player.account_id)
+fn queue_name(queue_id: i64, game_mode: &str) -> String {
+    match queue_id {
+        400 => "Normal Draft",
+        420 => "Ranked Solo/Duo",
+        430 => "Normal Blind",
+        440 => "Ranked Flex",
+        450 => "ARAM",
+        490 => "Quickplay",
+        700 => "Clash",
+        830 | 840 | 850 => "Co-op vs. AI",
+        900 | 1900 => "URF",
+        1700 => "Arena",
+        _ => game_mode,
+    }.to_string()
+}
+
+// Platform ids (e.g. "NA1") as they appear in League of Graphs links (e.g. "na").
+fn league_of_graphs_region(platform_id: &str) -> String {
+    match platform_id.to_uppercase().as_str() {
+        "EUN1" => "eune".to_string(),
+        "OC1" => "oce".to_string(),
+        "LA1" => "lan".to_string(),
+        "LA2" => "las".to_string(),
+        p => p.trim_end_matches(char::is_numeric).to_lowercase(),
     }
 }
 
+#[derive(Debug, Deserialize)]
+struct LeagueOfLegendsConfig {
+    // Regional routing value for account-v1 and match-v5, e.g. "americas", "europe" or "asia".
+    routing: String,
+    // Platform routing value of the account, e.g. "na1" or "euw1".
+    platform: String,
+    api_key: String,
+    // Either the Riot ID ("name#tag") to look up, or the PUUID directly.
+    riot_id: Option<String>,
+    puuid: Option<String>,
+    // How far back matches are listed in a regular cycle.
+    lookback_hours: Option<i64>,
+}
+
 pub struct LeagueOfLegends {
+    identifier: String,
     request_config: RequestConfig,
     event_ids: HashSet<String>,
+    lol_config: LeagueOfLegendsConfig,
+    puuid: Option<String>,
+    backfill_range: Option<(NaiveDate, NaiveDate)>,
+}
+
+impl LeagueOfLegends {
+    fn base_url(&self) -> String {
+        self.request_config.url.replace("{routing}", self.lol_config.routing.as_str())
+    }
+
+    fn get_match_url(&self, match_id: &str) -> String {
+        format!("{}/lol/match/v5/matches/{}", self.base_url(), match_id)
+    }
+
+    fn time_range(&self) -> (i64, i64) {
+        match self.backfill_range {
+            Some((from, to)) => (
+                from.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp(),
+                (to + Duration::days(1)).and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp(),
+            ),
+            None => {
+                let now = Utc::now();
+                (now.timestamp() - self.lol_config.lookback_hours.unwrap_or(48) * 3600, now.timestamp())
+            }
+        }
+    }
+
+    fn match_into_event_with_id(&self, game: &MatchObject, puuid: &str) -> Result<EventWithId, ModuleError> {
+        let participant = game.info.participants.iter().find(|p| p.puuid == puuid)
+            .ok_or_else(|| ModuleError::parse(game.context(), "the player is not among the participants", ""))?;
+        let (start_time, end_time) = game.start_end()?;
+        let queue = queue_name(game.info.queue_id.as_i64().unwrap_or(0), game.info.game_mode.as_str());
+        let result = if participant.win { "Victory" } else { "Defeat" };
+        let kda = format!("{}/{}/{}", participant.kills, participant.deaths, participant.assists);
+        let game_link = format!("https://www.leagueofgraphs.com/match/{}/{}",
+                                league_of_graphs_region(self.lol_config.platform.as_str()), game.info.game_id);
+        Ok(EventWithId {
+            summary: format!("[LoL] {} – {} – {} {}", queue, participant.champion_name, result, kda),
+            description: format!("[link] {}\n[queue] {}\n[champion] {}\n[result] {}\n[kda] {}\n[hash] {}",
+                                 game_link, queue, participant.champion_name, result, kda, game.id(self.identifier.as_str(), puuid)),
+            duration: StartEnd(start_time, end_time),
+            id: game.id(self.identifier.as_str(), puuid),
+            location: None,
+            url: None,
+            ical_uid: None,
+        })
+    }
+}
+
+fn config_problems(lol_config: &LeagueOfLegendsConfig) -> Vec<String> {
+    let mut problems = vec![];
+    if lol_config.riot_id.is_none() && lol_config.puuid.is_none() {
+        problems.push("either riot_id or puuid should be set.".to_string());
+    }
+    if let Some(riot_id) = &lol_config.riot_id {
+        if !matches!(riot_id.split_once('#'), Some((game_name, tag_line)) if !game_name.is_empty() && !tag_line.is_empty()) {
+            problems.push(format!("riot_id {:?} should look like \"name#tag\".", riot_id));
+        }
+    }
+    problems
 }
 
 impl Module for LeagueOfLegends {
-    fn new(calendar_id: Option<String>) -> Result<Box<dyn Module>, Box<dyn Error>> {
-        let request_config = RequestConfig::new(IDENTIFIER, calendar_id)?;
-        let event_ids = read_dumped_event_id(IDENTIFIER).unwrap_or(HashSet::new());
+    fn new(instance: Option<&str>, calendar_id: Option<String>) -> Result<Box<dyn Module>, Box<dyn Error>> {
+        let identifier = instance_identifier(IDENTIFIER, instance);
+        let (mut request_config, lol_config) = read_configs::<LeagueOfLegendsConfig>(identifier.as_str(), calendar_id, config_problems)?;
+        let event_ids = read_dumped_event_id(identifier.as_str()).unwrap_or_default();
+        request_config.headers.insert("x-riot-token", HeaderValue::from_str(lol_config.api_key.as_str())?);
+        let puuid = lol_config.puuid.clone();
         Ok(Box::new(LeagueOfLegends {
+            identifier,
             request_config,
             event_ids,
+            lol_config,
+            puuid,
+            backfill_range: None,
         }))
     }
 
     fn dump(&self) {
-        dump_event_id_wrapper(IDENTIFIER, &self.event_ids);
+        dump_event_id_wrapper(self.identifier.as_str(), &self.event_ids);
     }
 
     fn get_config(&self) -> &RequestConfig {
@@ -84,34 +219,174 @@ impl Module for LeagueOfLegends {
     }
 
     fn get_identifier(&self) -> &str {
-        IDENTIFIER
+        self.identifier.as_str()
     }
 
     fn get_request_url(&self) -> String {
-        self.request_config.url.to_string()
+        let (start_time, end_time) = self.time_range();
+        format!("{}/lol/match/v5/matches/by-puuid/{}/ids?startTime={}&endTime={}&count={}",
+                self.base_url(), self.puuid.as_deref().unwrap_or(""), start_time, end_time, MAX_MATCH_COUNT)
     }
 
-    fn need_for_detail(&self, _response: &String) -> Option<Vec<String>> {
-        None
+    // A match list which cannot be parsed is handed on as it is, so that it fails as a match instead of
+    // passing for a list without new games.
+    fn need_for_detail(&self, response: &String) -> Option<Vec<String>> {
+        let match_ids = serde_json::from_str::<Vec<String>>(response.as_str()).ok()?;
+        Some(match_ids.iter().map(|id| self.get_match_url(id)).collect())
     }
 
-    fn process_response_into_event_with_id(&self, responses: Vec<String>) -> Result<Vec<EventWithId>, Box<dyn Error>> {
-        let response = responses[0].clone();
-        let (account_id, items) = match serde_json::from_str::<Response>(response.as_str()) {
-            Ok(json) => (json.account_id, json.games.games),
-            Err(e) => panic!("Cannot parse {} response!, {:#?}. The original response reads:\n{}", IDENTIFIER, e, response),
-        };
+    // Every match is a row.
+    fn process_response_into_event_with_id(&self, responses: Vec<String>) -> Result<ParsedEvents, ModuleError> {
+        let puuid = self.puuid.as_deref().unwrap_or("");
+        let mut parsed = ParsedEvents::default();
+        for (row, response) in responses.iter().enumerate() {
+            let event = serde_json::from_str::<MatchObject>(response.as_str())
+                .map_err(|e| ModuleError::parse("match", e, response.as_str()))
+                .and_then(|game| self.match_into_event_with_id(&game, puuid));
+            match event {
+                Ok(event) => parsed.events.push(event),
+                Err(e) => parsed.skip(row, &e, ""),
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn set_backfill_range(&mut self, from: NaiveDate, to: NaiveDate) -> Result<(), Box<dyn Error>> {
+        self.backfill_range = Some((from, to));
+        Ok(())
+    }
+
+    // Development keys allow 100 requests every two minutes.
+    fn get_request_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(1200)
+    }
+
+    fn need_for_setup(&self) -> Option<String> {
+        if self.puuid.is_some() {
+            return None;
+        }
+        let (game_name, tag_line) = self.lol_config.riot_id.as_deref()?.split_once('#')?;
+        let mut url = Url::parse(self.base_url().as_str()).ok()?;
+        url.path_segments_mut().ok()?.extend(&["riot", "account", "v1", "accounts", "by-riot-id", game_name, tag_line]);
+        Some(url.to_string())
+    }
+
+    fn setup(&mut self, response: String) -> Result<(), ModuleError> {
+        let account = serde_json::from_str::<Account>(response.as_str())
+            .map_err(|e| ModuleError::parse("account", e, response.as_str()))?;
+        info!("Resolved the PUUID of {:?} on {}.", self.lol_config.riot_id, self.lol_config.platform);
+        self.puuid = Some(account.puuid);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
 
-        Ok(items.iter().map(|item| {
-            let game_link = format!("https://matchhistory.na.leagueoflegends.com/en/#match-details/NA1/{}/{}", item.game_id, account_id);
-            let start_time = Utc.ymd(1970, 1, 1).and_hms(0, 0, 0) + Duration::seconds(item.game_creation.as_i64().unwrap() / 1000);
-            let end_time = Utc.ymd(1970, 1, 1).and_hms(0, 0, 0) + Duration::seconds(item.game_creation.as_i64().unwrap() / 1000 + item.game_duration.as_i64().unwrap());
-            EventWithId {
-                summary: format!("[League of Legends] {}", item.game_mode),
-                description: format!("[link] {}\n[mode] {} {}\n[hash] {}", game_link, item.game_mode, item.game_type, item.id()),
-                duration: StartEnd(start_time, end_time),
-                id: item.id(),
+    use chrono::{TimeZone, Utc};
+
+    use crate::calendar::event::Duration::StartEnd;
+    use crate::common::{Module, RequestConfig};
+    use crate::league_of_legends::{config_problems, league_of_graphs_region, LeagueOfLegends, LeagueOfLegendsConfig};
+    use crate::test_support::{assert_golden, fetch_events, StubServer};
+
+    fn league_of_legends(puuid: Option<&str>) -> LeagueOfLegends {
+        LeagueOfLegends {
+            identifier: "league_of_legends".to_string(),
+            request_config: RequestConfig::for_test("https://{routing}.api.riotgames.com"),
+            event_ids: HashSet::new(),
+            lol_config: LeagueOfLegendsConfig {
+                routing: "americas".to_string(),
+                platform: "na1".to_string(),
+                api_key: "".to_string(),
+                riot_id: Some("Some One#NA1".to_string()),
+                puuid: None,
+                lookback_hours: None,
+            },
+            puuid: puuid.map(str::to_string),
+            backfill_range: None,
+        }
+    }
+
+    #[test]
+    fn test_setup() {
+        let mut module = league_of_legends(None);
+        assert_eq!(module.need_for_setup().unwrap(), "https://americas.api.riotgames.com/riot/account/v1/accounts/by-riot-id/Some%20One/NA1");
+        module.setup(r#"{"puuid": "abc", "gameName": "Some One", "tagLine": "NA1"}"#.to_string()).unwrap();
+        assert_eq!(module.need_for_setup(), None);
+        assert!(module.get_request_url().starts_with("https://americas.api.riotgames.com/lol/match/v5/matches/by-puuid/abc/ids?startTime="));
+    }
+
+    #[test]
+    fn test_config_problems() {
+        let mut lol_config = league_of_legends(None).lol_config;
+        assert!(config_problems(&lol_config).is_empty());
+        lol_config.riot_id = Some("Some One".to_string());
+        assert_eq!(config_problems(&lol_config), vec!["riot_id \"Some One\" should look like \"name#tag\"."]);
+        lol_config.riot_id = None;
+        assert_eq!(config_problems(&lol_config), vec!["either riot_id or puuid should be set."]);
+    }
+
+    #[test]
+    fn test_need_for_detail() {
+        let module = league_of_legends(Some("abc"));
+        assert_eq!(module.need_for_detail(&r#"["NA1_4471269577"]"#.to_string()).unwrap(),
+                   vec!["https://americas.api.riotgames.com/lol/match/v5/matches/NA1_4471269577"]);
+        assert_eq!(module.need_for_detail(&r#"{"matches": []}"#.to_string()), None);
+    }
+
+    #[test]
+    fn test_process_match() {
+        let response = r#"{
+  "metadata": {"dataVersion": "2", "matchId": "NA1_4471269577", "participants": ["xyz", "abc"]},
+  "info": {
+    "gameCreation": 1666411890000, "gameDuration": 620, "gameEndTimestamp": 1666412535909, "gameId": 4471269577,
+    "gameMode": "ARAM", "gameStartTimestamp": 1666411915909, "gameType": "MATCHED_GAME", "platformId": "NA1", "queueId": 450,
+    "participants": [
+      {"puuid": "xyz", "championName": "Lux", "win": false, "kills": 3, "deaths": 12, "assists": 8},
+      {"puuid": "abc", "championName": "Jinx", "win": true, "kills": 12, "deaths": 3, "assists": 20}
+    ]
+  }
+}"#;
+        let events = league_of_legends(Some("abc")).process_response_into_event_with_id(vec![response.to_string()]).unwrap().events;
+        assert_eq!(events[0].summary, "[LoL] ARAM – Jinx – Victory 12/3/20");
+        assert_eq!(events[0].id, "league_of_legends|NA1|4471269577|abc");
+        assert!(events[0].description.starts_with("[link] https://www.leagueofgraphs.com/match/na/4471269577\n"));
+        match events[0].duration {
+            StartEnd(start, end) => {
+                assert_eq!(start, Utc.timestamp_millis_opt(1666411915909).unwrap());
+                assert_eq!(end, Utc.timestamp_millis_opt(1666412535909).unwrap());
             }
-        }).collect())
+            _ => panic!("Matches should be timed."),
+        }
+        let parsed = league_of_legends(Some("nobody")).process_response_into_event_with_id(vec![response.to_string()]).unwrap();
+        assert!(parsed.events.is_empty());
+        assert_eq!(parsed.diagnostics[0].reason, "cannot parse match NA1_4471269577: the player is not among the participants");
+    }
+
+    #[test]
+    fn test_league_of_graphs_region() {
+        assert_eq!(league_of_graphs_region("NA1"), "na");
+        assert_eq!(league_of_graphs_region("EUW1"), "euw");
+        assert_eq!(league_of_graphs_region("EUN1"), "eune");
+        assert_eq!(league_of_graphs_region("KR"), "kr");
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_fixture() {
+        let server = StubServer::start(&[
+            ("/riot/account/v1/accounts/by-riot-id/Some%20One/NA1", "league_of_legends/account.json"),
+            ("/lol/match/v5/matches/by-puuid/abc/ids", "league_of_legends/match_ids.json"),
+            ("/lol/match/v5/matches/NA1_4471269577", "league_of_legends/match_4471269577.json"),
+            ("/lol/match/v5/matches/NA1_4471295235", "league_of_legends/match_4471295235.json"),
+        ]);
+        let mut module = league_of_legends(None);
+        module.request_config.url = server.url("");
+        let mut module: Box<dyn Module> = Box::new(module);
+
+        let events = fetch_events(&mut module).await;
+        assert_eq!(server.requests().len(), 4);
+        assert_golden("league_of_legends/expected.txt", &events, &Utc);
+    }
+}
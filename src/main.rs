@@ -9,17 +9,33 @@ extern crate reqwest;
 extern crate serde_derive;
 extern crate tokio;
 
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
-use std::time::SystemTime;
+use std::{fs, io};
+use std::time::{Instant, SystemTime};
 
-use chrono::{Duration, Utc};
+use chrono::{Days, Duration, NaiveDate, Utc};
+use reqwest::header::RETRY_AFTER;
+use reqwest::StatusCode;
+#[cfg(unix)]
+use tokio::signal::unix::{signal, Signal, SignalKind};
+use tokio::sync::{mpsc, watch};
 use tokio::time;
 
 use crate::bilibili::*;
 use crate::calendar::*;
-use crate::calendar::event::Duration::{StartEnd, WholeDay};
+use crate::calendar::event::Duration::{MultiDay, StartEnd, WholeDay};
 use crate::calendar::event::EventWithId;
 use crate::common::*;
+use crate::common::archive::*;
+use crate::common::auth_health::*;
+use crate::common::config::watch_config;
+use crate::common::cookie_jar::keep_cookie_jars_read_only;
+use crate::common::diagnostic::Diagnostic;
+use crate::common::error::ModuleError;
+use crate::common::metrics::{start_metrics_server, update_metrics};
+use crate::common::secrets::{forget_stored_secrets, redact_url};
+use crate::ics_feed::*;
 use crate::league_of_graphs::*;
 use crate::league_of_legends::*;
 use crate::netflix::*;
@@ -30,88 +46,541 @@ use crate::youtube::*;
 mod bilibili;
 mod common;
 mod calendar;
+mod ics_feed;
 mod league_of_legends;
 mod league_of_graphs;
 mod netflix;
 mod ut_oden_seminar;
 mod youtube;
 mod wakatime;
+#[cfg(test)]
+mod test_support;
+
+type ModuleConstructor = fn(Option<&str>, Option<String>) -> Result<Box<dyn Module>, Box<dyn Error>>;
+
+// Every module by its identifier, along with how to create an instance of it.
+const MODULES: [(&str, ModuleConstructor); 8] = [
+    ("bilibili", Bilibili::new),
+    ("ics_feed", IcsFeed::new),
+    ("league_of_graphs", LeagueOfGraphs::new),
+    ("league_of_legends", LeagueOfLegends::new),
+    ("netflix", Netflix::new),
+    ("ut_oden_seminar", UTOdenSeminar::new),
+    ("wakatime", Wakatime::new),
+    ("youtube", Youtube::new),
+];
+const MAX_RATE_LIMITED_RETRIES: u32 = 3;
+const MAX_PAGES_PER_CYCLE: usize = 20;
+// Days backfilled by one cycle, so that a module fetching a page or two per day stays within the pages a
+// cycle may fetch.
+const BACKFILL_DAYS_PER_CYCLE: u64 = 10;
+const CONFIG_SETTLE_TIME: std::time::Duration = std::time::Duration::from_millis(500);
 
 #[tokio::main]
 #[allow(dead_code)]
 async fn main() -> Result<(), Box<dyn Error>> {
     env_logger::init();
 
+    let args: Vec<String> = std::env::args().collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        [_, "status"] => {
+            print_status();
+            return Ok(());
+        }
+        [_, "replay", identifier, path] => return replay(identifier, path),
+        _ => {}
+    }
+
+    match args.as_slice() {
+        [_, "import", identifier, path] => return import_file(&mut init_hub().await, identifier, path).await,
+        [_, "backfill", identifier, from, to] => return backfill(&mut init_hub().await, identifier, from, to).await,
+        _ => {}
+    }
+
+    let enabled_modules = vec![
+        // "bilibili",
+        // "ics_feed",
+        "league_of_graphs",
+        // "league_of_legends",
+        // "netflix",
+        // "wakatime",
+        "ut_oden_seminar",
+        // "youtube",
+    ];
+    let mut modules: Vec<Box<dyn Module>> = load_modules(enabled_modules.iter()
+        .flat_map(|module| new_module_instances(module, None)).collect())?;
     let mut hub = init_hub().await;
-    let mut modules: Vec<Box<dyn Module>> = filter_loaded_modules(vec![
-        // Bilibili::new(None),
-        LeagueOfGraphs::new(None),
-        // LeagueOfLegends::new(None),
-        // Netflix::new(None),
-        // Wakatime::new(None),
-        UTOdenSeminar::new(None),
-        // Youtube::new(None),
-    ]);
+    start_metrics_server();
     let mut interval = time::interval(std::time::Duration::from_millis(60 * 60 * 1000));
+    // Config changes are picked up between cycles, either from the watcher or from SIGHUP.
+    let (reload_sender, mut reload_receiver) = mpsc::unbounded_channel();
+    let _watcher = watch_config(reload_sender);
+    let mut hangup = ReloadSignal::new()?;
+    let (shutdown_sender, mut shutdown_receiver) = watch::channel(None);
+    tokio::spawn(wait_for_shutdown(ShutdownSignals::new()?, shutdown_sender));
+    // Module instances whose failure has been notified, until they succeed again.
+    let mut alerted = HashSet::new();
 
     loop {
-        interval.tick().await;
-        info!("Timer picked up at {:#?}", SystemTime::now());
-        for mut module in &mut modules {
-            let response = fetch_data(&mut module).await?;
-            let detail_response = make_detail(&mut module, response).await?;
-            let events = filter_events_to_be_posted(&mut module, detail_response);
-            match events {
-                Ok(events) => {
-                    for event in events {
-                        calendar_post(&mut hub, module.get_config(), event.into()).await;
+        tokio::select! {
+            _ = interval.tick() => {
+                info!("Timer picked up at {:#?}", SystemTime::now());
+                for module in &mut modules {
+                    // The module being synced finishes its posts, while the rest wait for the next start.
+                    if shutdown_receiver.borrow().is_some() {
+                        break;
+                    }
+                    match sync_module(&mut hub, module).await {
+                        Ok(_) => {
+                            alerted.remove(module.get_identifier());
+                        }
+                        Err(e) => report_failure(module.get_identifier(), &e, &mut alerted),
                     }
-                    module.dump()
                 }
-                Err(e) => error!("{}", e),
+                prune_archive();
+                info!("Waiting for timer to pick up...")
+            }
+            Some(_) = reload_receiver.recv() => {
+                // Editors tend to write a file in several steps, so the changes are let to settle.
+                time::sleep(CONFIG_SETTLE_TIME).await;
+                while reload_receiver.try_recv().is_ok() {}
+                info!("Config directory changed; reloading modules.");
+                modules = reload_modules(&enabled_modules, modules);
             }
+            _ = hangup.recv() => {
+                info!("SIGHUP received; reloading modules.");
+                modules = reload_modules(&enabled_modules, modules);
+            }
+            _ = shutdown_receiver.changed() => break,
+        }
+    }
+
+    for module in &modules {
+        module.dump();
+    }
+    info!("Module state flushed; bye.");
+    let status = *shutdown_receiver.borrow();
+    std::process::exit(status.unwrap_or(0));
+}
+
+// SIGHUP, which asks for a reload; there is no such signal but on Unix.
+#[cfg(unix)]
+struct ReloadSignal(Signal);
+
+#[cfg(unix)]
+impl ReloadSignal {
+    fn new() -> io::Result<ReloadSignal> {
+        Ok(ReloadSignal(signal(SignalKind::hangup())?))
+    }
+
+    async fn recv(&mut self) {
+        self.0.recv().await;
+    }
+}
+
+#[cfg(not(unix))]
+struct ReloadSignal;
+
+#[cfg(not(unix))]
+impl ReloadSignal {
+    fn new() -> io::Result<ReloadSignal> {
+        Ok(ReloadSignal)
+    }
+
+    async fn recv(&mut self) {
+        std::future::pending::<()>().await;
+    }
+}
+
+// SIGINT and SIGTERM on Unix, Ctrl-C elsewhere.
+#[cfg(unix)]
+struct ShutdownSignals {
+    interrupt: Signal,
+    terminate: Signal,
+}
+
+#[cfg(unix)]
+impl ShutdownSignals {
+    fn new() -> io::Result<ShutdownSignals> {
+        Ok(ShutdownSignals { interrupt: signal(SignalKind::interrupt())?, terminate: signal(SignalKind::terminate())? })
+    }
+
+    // Exit status for quitting on the signal received, i.e. 128 and its number.
+    async fn recv(&mut self) -> i32 {
+        tokio::select! {
+            _ = self.interrupt.recv() => 130,
+            _ = self.terminate.recv() => 143,
         }
-        info!("Waiting for timer to pick up...")
     }
+}
+
+#[cfg(not(unix))]
+struct ShutdownSignals;
+
+#[cfg(not(unix))]
+impl ShutdownSignals {
+    fn new() -> io::Result<ShutdownSignals> {
+        Ok(ShutdownSignals)
+    }
+
+    async fn recv(&mut self) -> i32 {
+        let _ = tokio::signal::ctrl_c().await;
+        130
+    }
+}
+
+// Asks the main loop to stop after the current module on the first SIGINT or SIGTERM, and quits at once
+// on the second one.
+// The exit status for the signal is sent along, so that the daemon exits with it after a clean shutdown too.
+async fn wait_for_shutdown(mut signals: ShutdownSignals, sender: watch::Sender<Option<i32>>) {
+    let status = signals.recv().await;
+    info!("Shutting down once the posts in flight are done; signal again to quit at once.");
+    let _ = sender.send(Some(status));
+    let status = signals.recv().await;
+    warn!("Quitting without flushing module state.");
+    std::process::exit(status);
+}
+
+// Failures which may go away by themselves are left to the next cycle; the others are notified once
+// until the module succeeds again, except for expired logins which `mark_needs_reauth` notifies.
+fn report_failure(identifier: &str, error: &ModuleError, alerted: &mut HashSet<String>) {
+    if error.is_transient() {
+        warn!("Module {} fails in this cycle and is retried in the next one: {}", identifier, error);
+        return;
+    }
+    error!("Module {} fails in this cycle: {}", identifier, error);
+    update_metrics(identifier, |metrics| metrics.failure = Some(error.to_string()));
+    if !matches!(error, ModuleError::Auth(_)) && alerted.insert(identifier.to_string()) {
+        notify(format!("Module {} needs attention: {}.", identifier, error).as_str());
+    }
+}
 
+async fn sync_module<S: CalendarSink>(calendar: &mut S, module: &mut Box<dyn Module>) -> Result<(), ModuleError> {
+    let mut archive = ResponseArchive::new(module.get_identifier());
+    let started = Instant::now();
+    let detail_response = fetch_responses(module, &mut archive).await?;
+    let fetch_seconds = started.elapsed().as_secs_f64();
+    update_metrics(module.get_identifier(), |metrics| {
+        metrics.fetch_seconds += fetch_seconds;
+        metrics.fetches += 1;
+    });
+    let events = filter_events_to_be_posted(module, detail_response, &mut archive)?;
+    let (posted, failed) = post_events(calendar, module, events).await;
+    update_metrics(module.get_identifier(), |metrics| {
+        metrics.events_posted += posted;
+        metrics.post_failures += failed;
+    });
+    // Nothing made it to the calendar, so the cursor of the module stays where it was and the cycle is
+    // retried as a whole.
+    if posted == 0 && failed > 0 {
+        module.dump();
+        return Err(ModuleError::Network(format!("none of the {} events can be posted", failed)));
+    }
+    module.commit_cycle();
+    module.dump();
+    update_metrics(module.get_identifier(), |metrics| {
+        metrics.last_success = Some(SystemTime::now());
+        metrics.failure = None;
+    });
     Ok(())
 }
 
-async fn fetch_data(module: &mut Box<dyn Module>) -> Result<String, Box<dyn Error>> {
-    let response = reqwest::Client::new()
-        .get(&module.get_request_url())
-        .headers(module.get_config().headers.clone())
-        .send()
-        .await?
-        .text()
-        .await?;
-
-    Ok(response)
-}
-
-async fn make_detail(module: &mut Box<dyn Module>, response: String) -> Result<Vec<String>, Box<dyn std::error::Error>> {
-    if let Some(further_request_urls) = module.need_for_detail(&response) {
-        let mut responses = vec![];
-        for url in further_request_urls {
-            let response = reqwest::Client::new()
-                .get(&url)
-                .headers(module.get_config().headers.clone())
-                .send()
-                .await?
-                .text()
-                .await?;
-            responses.push(response);
+// Returns how many events are posted and how many fail to be; the ones which fail are taken as new again
+// next time, as `dedup_events` has already counted them as seen.
+async fn post_events<S: CalendarSink>(calendar: &mut S, module: &mut Box<dyn Module>, events: Vec<EventWithId>) -> (u64, u64) {
+    let (mut posted, mut failed) = (0, 0);
+    for event in events {
+        let id = event.id.clone();
+        if calendar.post(module.get_config(), event.into()).await {
+            posted += 1;
+        } else {
+            module.get_event_ids().remove(id.as_str());
+            failed += 1;
+        }
+    }
+    (posted, failed)
+}
+
+// Everything a cycle fetches: the setup request if any, then the pages and their details.
+async fn fetch_responses(module: &mut Box<dyn Module>, archive: &mut ResponseArchive) -> Result<Vec<String>, ModuleError> {
+    if let Some(url) = module.need_for_setup() {
+        let response = fetch_url(module, archive, url.as_str(), ResponseKind::Setup).await?;
+        module.setup(response)?;
+    }
+    let response = fetch_data(module, archive).await?;
+    make_detail(module, archive, response).await
+}
+
+// The configured method, query, body and form only apply to pages; every response is archived, even
+// the ones telling that the login expired.
+async fn fetch_url(module: &mut Box<dyn Module>, archive: &mut ResponseArchive, url: &str, kind: ResponseKind) -> Result<String, ModuleError> {
+    // Local sources, e.g. an exported `.ics` file, are read from disk.
+    if let Some(path) = url.strip_prefix("file://") {
+        let text = fs::read_to_string(path).map_err(|e| ModuleError::Network(format!("cannot read {}: {}", path, e)))?;
+        archive.record(kind, url, text.as_str());
+        return Ok(text);
+    }
+    // Urls may carry api keys, so they are redacted in the errors.
+    let redacted_url = redact_url(url);
+    let mut retries = 0;
+    loop {
+        let cursor = module.get_cursor();
+        // Requests fail to build when a secret they refer to cannot be read.
+        let response = module.get_config()
+            .build_request(&reqwest::Client::new(), url, kind == ResponseKind::Page, cursor.as_deref())
+            .map_err(|e| ModuleError::Config(format!("cannot build the request to {}: {}", redacted_url, e)))?
+            .send()
+            .await
+            .map_err(|e| ModuleError::Network(format!("request to {} fails: {}", redacted_url, e.without_url())))?;
+        if let Some(cookie_jar) = &module.get_config().cookie_jar {
+            cookie_jar.store_response_cookies(response.url(), response.headers());
+        }
+        let status = response.status();
+        let wait = response.headers().get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok());
+        if status != StatusCode::TOO_MANY_REQUESTS {
+            let final_url = response.url().to_string();
+            let text = response.text().await
+                .map_err(|e| ModuleError::Network(format!("response of {} cannot be read: {}", redacted_url, e.without_url())))?;
+            archive.record(kind, url, text.as_str());
+            if let Some(reason) = module.check_auth_health(status, final_url.as_str(), text.as_str()) {
+                mark_needs_reauth(module.get_identifier(), reason.as_str());
+                return Err(ModuleError::Auth(format!("login of module {} expired ({})", module.get_identifier(), reason)));
+            }
+            mark_authenticated(module.get_identifier());
+            // A detail page which is gone only costs its own event, which the module skips.
+            return match status {
+                _ if kind == ResponseKind::Detail => Ok(text),
+                _ if status.is_server_error() => Err(ModuleError::Network(format!("{} answers with HTTP status {}", redacted_url, status))),
+                _ if status.is_client_error() => Err(ModuleError::SourceChanged(format!("{} answers with HTTP status {}", redacted_url, status))),
+                _ => Ok(text),
+            };
+        }
+        if retries >= MAX_RATE_LIMITED_RETRIES {
+            return Err(ModuleError::RateLimited(wait.map(std::time::Duration::from_secs)));
+        }
+        let wait = wait.unwrap_or(60);
+        warn!("Module {} is rate limited; retrying in {} seconds.", module.get_identifier(), wait);
+        time::sleep(std::time::Duration::from_secs(wait)).await;
+        retries += 1;
+    }
+}
+
+// Fetches the request url along with the pages following it, if the module is paged.
+async fn fetch_data(module: &mut Box<dyn Module>, archive: &mut ResponseArchive) -> Result<Vec<String>, ModuleError> {
+    let mut url = module.get_request_url();
+    let mut responses = vec![];
+    loop {
+        let response = fetch_url(module, archive, url.as_str(), ResponseKind::Page).await?;
+        let next_url = module.next_page_url(&response);
+        responses.push(response);
+        match next_url {
+            Some(_) if responses.len() >= MAX_PAGES_PER_CYCLE => {
+                warn!("Module {} has more than {} pages to fetch; the rest is skipped.", module.get_identifier(), MAX_PAGES_PER_CYCLE);
+                break;
+            }
+            Some(next_url) => {
+                time::sleep(module.get_request_interval()).await;
+                url = next_url;
+            }
+            None => break,
+        }
+    }
+    Ok(responses)
+}
+
+async fn make_detail(module: &mut Box<dyn Module>, archive: &mut ResponseArchive, responses: Vec<String>) -> Result<Vec<String>, ModuleError> {
+    let mut detail_responses = vec![];
+    for (page, response) in responses.into_iter().enumerate() {
+        if let Some(further_request_urls) = module.need_for_detail(&response) {
+            archive.record_details(page, further_request_urls.len());
+            for (i, url) in further_request_urls.iter().enumerate() {
+                if i > 0 {
+                    time::sleep(module.get_request_interval()).await;
+                }
+                detail_responses.push(fetch_url(module, archive, url, ResponseKind::Detail).await?);
+            }
+        } else {
+            detail_responses.push(response);
+        }
+    }
+    Ok(detail_responses)
+}
+
+// Lists the configured module instances along with the ones which need to be signed in again.
+fn print_status() {
+    let auth_statuses = read_auth_statuses();
+    for (module, _) in MODULES.iter() {
+        for instance in find_instances(module) {
+            let identifier = instance_identifier(module, instance.as_deref());
+            if !path_exists(format!("config/{}.json", identifier).as_str()) {
+                continue;
+            }
+            match auth_statuses.get(&identifier) {
+                Some(status) => println!("{}: needs to be signed in again since {} ({})", identifier, status.since, status.reason),
+                None => println!("{}: ok", identifier),
+            }
+        }
+    }
+}
+
+// Creates the module instance of the given identifier, e.g. "bilibili" or "bilibili.alice".
+fn new_module(identifier: &str, calendar_id: Option<String>) -> Result<Box<dyn Module>, Box<dyn Error>> {
+    let instance = identifier.split_once('.').map(|(_, instance)| instance);
+    let module = module_of_instance(identifier);
+    match MODULES.iter().find(|(name, _)| *name == module) {
+        Some((_, new)) => new(instance, calendar_id),
+        None => Err(format!("Unknown module {}.", identifier).into()),
+    }
+}
+
+fn new_module_instances(module: &str, calendar_id: Option<String>) -> Vec<Result<Box<dyn Module>, Box<dyn Error>>> {
+    find_instances(module).into_iter()
+        .map(|instance| {
+            let identifier = instance_identifier(module, instance.as_deref());
+            new_module(identifier.as_str(), calendar_id.clone())
+                .map_err(|e| format!("Module {} cannot be loaded: {}", identifier, e).into())
+        })
+        .collect()
+}
+
+// Rebuilds the enabled module instances from the current config. The state of every module is dumped
+// first and its dedup ids are carried over, so nothing is posted twice; an instance whose new config is
+// broken keeps running with the previous one.
+fn reload_modules(enabled_modules: &[&str], modules: Vec<Box<dyn Module>>) -> Vec<Box<dyn Module>> {
+    forget_stored_secrets();
+    let mut previous_modules: HashMap<String, Box<dyn Module>> = modules.into_iter()
+        .map(|module| {
+            module.dump();
+            (module.get_identifier().to_string(), module)
+        })
+        .collect();
+    let mut reloaded = vec![];
+    for module in enabled_modules {
+        for instance in find_instances(module) {
+            let identifier = instance_identifier(module, instance.as_deref());
+            let previous = previous_modules.remove(&identifier);
+            match new_module(identifier.as_str(), None) {
+                Ok(mut module) => {
+                    if let Some(mut previous) = previous {
+                        module.get_event_ids().extend(previous.get_event_ids().drain());
+                    }
+                    info!("Reloaded module {}.", identifier);
+                    reloaded.push(module);
+                }
+                Err(e) => match previous {
+                    Some(previous) => {
+                        error!("Module {} keeps its previous config as the new one cannot be loaded: {}", identifier, e);
+                        reloaded.push(previous);
+                    }
+                    None => error!("Module {} cannot be loaded: {}", identifier, e),
+                },
+            }
         }
-        Ok(responses)
-    } else {
-        Ok(vec![response])
     }
+    for identifier in previous_modules.keys() {
+        info!("Module {} is no longer configured; stopped.", identifier);
+    }
+    reloaded
+}
+
+// Runs archived responses through the parser of the module and prints the events, without posting them
+// or touching any state, e.g. to fix a parser after the page layout changed.
+fn replay(identifier: &str, path: &str) -> Result<(), Box<dyn Error>> {
+    keep_cookie_jars_read_only();
+    let mut module = new_module(identifier, None)?;
+    let responses = read_archived_responses(&mut module, path)?;
+    let parsed = module.process_response_into_event_with_id(responses)?;
+    for event in &parsed.events {
+        println!("{:#?}", event);
+    }
+    for diagnostic in &parsed.diagnostics {
+        println!("Skipped {}.", diagnostic);
+    }
+    println!("{} events parsed and {} rows skipped from {}.", parsed.events.len(), parsed.diagnostics.len(), path);
+    // Rows are counted the same way on every run of the same responses, so the ones skipped when the cycle
+    // was archived can be told apart by their position.
+    let recorded = read_archived_diagnostics(path)?;
+    let (still_skipped, now_parsed): (Vec<&Diagnostic>, Vec<&Diagnostic>) = recorded.iter()
+        .partition(|diagnostic| parsed.diagnostics.iter().any(|skipped| skipped.row == diagnostic.row));
+    for diagnostic in &now_parsed {
+        println!("Now parses the row skipped in the cycle at {}.", diagnostic);
+    }
+    for diagnostic in &still_skipped {
+        println!("Still skips the row skipped in the cycle at {}.", diagnostic);
+    }
+    if !recorded.is_empty() {
+        println!("{} of the {} rows skipped in the cycle now parse.", now_parsed.len(), recorded.len());
+    }
+    Ok(())
+}
+
+async fn import_file(hub: &mut CalHub, identifier: &str, path: &str) -> Result<(), Box<dyn Error>> {
+    let mut module = new_module(identifier, None)?;
+    let content = fs::read_to_string(path)?;
+    let imported_events = module.process_import_into_event_with_id(content)?;
+    let events = dedup_events(&mut module, filter_event(imported_events));
+    info!("Importing {} events from {} into module {}.", events.len(), path, identifier);
+    let (_, failed) = post_events(hub, &mut module, events).await;
+    if failed > 0 {
+        warn!("{} events of {} fail to be posted and are imported again next time.", failed, path);
+    }
+    module.dump();
+    Ok(())
+}
+
+async fn backfill(hub: &mut CalHub, identifier: &str, from: &str, to: &str) -> Result<(), Box<dyn Error>> {
+    let mut module = new_module(identifier, None)?;
+    let from = NaiveDate::parse_from_str(from, "%Y-%m-%d")?;
+    let to = NaiveDate::parse_from_str(to, "%Y-%m-%d")?;
+    if from > to {
+        return Err(format!("Backfill starts on {} after it ends on {}.", from, to).into());
+    }
+    let mut first_day = from;
+    while first_day <= to {
+        let last_day = (first_day + Days::new(BACKFILL_DAYS_PER_CYCLE - 1)).min(to);
+        module.set_backfill_range(first_day, last_day)?;
+        info!("Backfilling module {} from {} to {}.", identifier, first_day, last_day);
+        sync_module(hub, &mut module).await?;
+        first_day = last_day + Days::new(1);
+        time::sleep(module.get_request_interval()).await;
+    }
+    Ok(())
+}
+
+fn filter_events_to_be_posted(module: &mut Box<dyn Module>, responses: Vec<String>, archive: &mut ResponseArchive) -> Result<Vec<EventWithId>, ModuleError> {
+    let parsed = module.process_response_into_event_with_id(responses)?;
+    report_diagnostics(module.get_identifier(), &parsed.diagnostics, archive);
+    parsed.check_usable()?;
+    let parsed_count = parsed.events.len();
+    let filtered_events = filter_event(parsed.events);
+    let filtered_count = filtered_events.len();
+    let events = dedup_events(module, filtered_events);
+    update_metrics(module.get_identifier(), |metrics| {
+        metrics.events_parsed += parsed_count as u64;
+        metrics.events_filtered += (parsed_count - filtered_count) as u64;
+        metrics.events_deduped += (filtered_count - events.len()) as u64;
+    });
+    Ok(events)
+}
+
+// Skipped rows leave the rest of the cycle going, so they are only logged, counted and kept with the
+// archived responses to be looked into with `replay`.
+fn report_diagnostics(identifier: &str, diagnostics: &[Diagnostic], archive: &mut ResponseArchive) {
+    if diagnostics.is_empty() {
+        return;
+    }
+    for diagnostic in diagnostics {
+        warn!("Module {} skips {}.", identifier, diagnostic);
+    }
+    update_metrics(identifier, |metrics| metrics.parse_diagnostics += diagnostics.len() as u64);
+    archive.record_diagnostics(diagnostics);
 }
 
-fn filter_events_to_be_posted(module: &mut Box<dyn Module>, responses: Vec<String>) -> Result<Vec<EventWithId>, Box<dyn Error>> {
-    let fetched_events = module.process_response_into_event_with_id(responses)?;
-    let filtered_events = filter_event(fetched_events);
-    Ok(filtered_events.into_iter().filter(|event| {
+fn dedup_events(module: &mut Box<dyn Module>, events: Vec<EventWithId>) -> Vec<EventWithId> {
+    events.into_iter().filter(|event| {
         if module.get_event_ids().contains(event.id.as_str()) {
             debug!("Event with id \"{}\" already exists; skipped.", event.id);
             false
@@ -120,18 +589,19 @@ fn filter_events_to_be_posted(module: &mut Box<dyn Module>, responses: Vec<Strin
             debug!("Event with id \"{}\" shows for the first time; inserting.", event.id);
             true
         }
-    }).collect())
+    }).collect()
 }
 
 fn filter_event(events: Vec<EventWithId>) -> Vec<EventWithId> {
     events.into_iter()
         .filter(|event| {
-            if event.id.contains("ut_oden_seminar") {
+            if event.id.contains("ut_oden_seminar") || event.id.starts_with("ics_feed") {
                 return true;
             }
             if match &event.duration {
                 StartEnd(_, end) => *end < Utc::now() - Duration::hours(1),
-                WholeDay(w) => *w <= Utc::today() - Duration::days(1),
+                WholeDay(w) => *w < Utc::now().date_naive(),
+                MultiDay(_, last) => *last < Utc::now().date_naive(),
             } {
                 true
             } else {
@@ -140,12 +610,12 @@ fn filter_event(events: Vec<EventWithId>) -> Vec<EventWithId> {
             }
         })
         .filter(|event| {
-            if event.id.contains("bilibili") {
+            if event.id.contains("bilibili") || event.id.starts_with("ics_feed") {
                 return true;
             }
             if match &event.duration {
                 StartEnd(start, end) => *end - *start > Duration::minutes(5),
-                WholeDay(_) => true,
+                WholeDay(_) | MultiDay(_, _) => true,
             } {
                 true
             } else {
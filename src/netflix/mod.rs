@@ -1,48 +1,213 @@
 use std::collections::HashSet;
 use std::error::Error;
 
-use chrono::{TimeZone, Utc};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use lazy_static::lazy_static;
+use regex::Regex;
+use reqwest::header::ACCEPT_LANGUAGE;
+use reqwest::StatusCode;
 use scraper::{ElementRef, Html, Selector};
 use serde::Deserialize;
 
 use crate::calendar::event::*;
-use crate::calendar::event::Duration::WholeDay;
+use crate::calendar::event::Duration::{StartEnd, WholeDay};
 use crate::common::*;
+use crate::common::diagnostic::ParsedEvents;
+use crate::common::error::ModuleError;
 
 const IDENTIFIER: &str = "netflix";
 
 #[derive(Debug, Deserialize)]
 struct Item {
     link: String,
+    // Last part of the link, e.g. "80025678" of "/title/80025678".
+    title_id: String,
     title: String,
     date: String,
 }
 
 impl Item {
-    fn id(self: &Item) -> String {
-        let paths = self.link.split("/").collect::<Vec<&str>>();
-        let id = paths[2].parse::<u32>().unwrap();
-        format!("{}|{}|{}", IDENTIFIER, id, self.date)
+    fn id(self: &Item, identifier: &str) -> String {
+        format!("{}|{}|{}", identifier, self.title_id, self.date)
     }
 }
 
+// One row of the `ViewingActivity.csv` file shipped in Netflix's "download your personal information".
+#[derive(Debug, Deserialize)]
+struct ActivityRecord {
+    #[serde(rename = "Profile Name")]
+    profile_name: String,
+    #[serde(rename = "Start Time")]
+    start_time: String,
+    #[serde(rename = "Duration")]
+    duration: String,
+    #[serde(rename = "Title")]
+    title: String,
+    #[serde(rename = "Supplemental Video Type", default)]
+    supplemental_video_type: String,
+    #[serde(rename = "Device Type", default)]
+    device_type: String,
+}
+
+impl ActivityRecord {
+    fn id(self: &ActivityRecord, identifier: &str) -> String {
+        format!("{}|{}|{}", identifier, self.profile_name, self.start_time)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum DateOrder {
+    MonthDayYear,
+    DayMonthYear,
+    YearMonthDay,
+}
+
+impl DateOrder {
+    // The viewing activity page prints dates in the convention of the account language, which we
+    // guess from the first tag of the `accept-language` header sent along.
+    fn from_language(language: &str) -> DateOrder {
+        let tag = language.split(',').next().unwrap_or("").split(';').next().unwrap_or("").trim().to_lowercase();
+        if tag.is_empty() || tag == "en" || tag == "en-us" || tag == "en-ph" {
+            DateOrder::MonthDayYear
+        } else if ["zh", "ja", "ko", "hu", "lt"].iter().any(|prefix| tag.starts_with(prefix)) {
+            DateOrder::YearMonthDay
+        } else {
+            DateOrder::DayMonthYear
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct NetflixConfig {
+    // Profiles to keep when importing; all profiles are kept if empty.
+    #[serde(default)]
+    profiles: Vec<String>,
+    // Overrides the date order guessed from `accept-language`.
+    date_order: Option<DateOrder>,
+}
+
+fn parse_view_date(date_str: &str, order: DateOrder) -> Result<NaiveDate, ModuleError> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"(\d+)\D+(\d+)\D+(\d+)").unwrap();
+    }
+    let cap = RE.captures(date_str).ok_or_else(|| ModuleError::parse("view date", "no three numbers", date_str))?;
+    let fields: Vec<u32> = (1..4).map(|i| cap[i].parse::<u32>().unwrap_or(0)).collect();
+    let (year, month, day) = match order {
+        DateOrder::MonthDayYear => (fields[2], fields[0], fields[1]),
+        DateOrder::DayMonthYear => (fields[2], fields[1], fields[0]),
+        DateOrder::YearMonthDay => (fields[0], fields[1], fields[2]),
+    };
+    // Two-digit years follow the same pivot as `%y` in chrono.
+    let year = match year {
+        0..=68 => 2000 + year,
+        69..=99 => 1900 + year,
+        _ => year,
+    };
+    NaiveDate::from_ymd_opt(year as i32, month, day).ok_or_else(|| ModuleError::parse("view date", format!("no such date in {:?} order", order), date_str))
+}
+
+fn parse_row(row: &ElementRef) -> Result<Item, ModuleError> {
+    lazy_static! {
+        static ref TITLE_SELECTOR: Selector = Selector::parse("div.title").unwrap();
+        static ref DATE_SELECTOR: Selector = Selector::parse("div.date").unwrap();
+        static ref LINK_SELECTOR: Selector = Selector::parse("a").unwrap();
+    }
+    let link_element = row.select(&TITLE_SELECTOR).next()
+        .and_then(|e| e.select(&LINK_SELECTOR).next())
+        .ok_or_else(|| ModuleError::parse("viewing activity row", "div.title a is missing", row.html().as_str()))?;
+    let link = link_element.value().attr("href")
+        .ok_or_else(|| ModuleError::parse("viewing activity row", "div.title a has no href", row.html().as_str()))?;
+    let title_id = link.split('/').nth(2).filter(|id| !id.is_empty())
+        .ok_or_else(|| ModuleError::parse("title link", "no title id", link))?;
+    let date = row.select(&DATE_SELECTOR).next()
+        .ok_or_else(|| ModuleError::parse("viewing activity row", "div.date is missing", row.html().as_str()))?;
+    Ok(Item {
+        link: link.to_string(),
+        title_id: title_id.to_string(),
+        title: link_element.inner_html(),
+        date: date.inner_html(),
+    })
+}
+
+fn parse_activity_csv(content: &str, identifier: &str, profiles: &[String]) -> Result<Vec<EventWithId>, ModuleError> {
+    let mut reader = csv::Reader::from_reader(content.as_bytes());
+    let mut events = vec![];
+    for record in reader.deserialize::<ActivityRecord>() {
+        let record = record.map_err(|e| ModuleError::parse("viewing activity csv", e, ""))?;
+        if !profiles.is_empty() && !profiles.contains(&record.profile_name) {
+            continue;
+        }
+        if !record.supplemental_video_type.is_empty() {
+            debug!("Skipped {} \"{}\".", record.supplemental_video_type, record.title);
+            continue;
+        }
+        let start = NaiveDateTime::parse_from_str(record.start_time.as_str(), "%Y-%m-%d %H:%M:%S")
+            .map_err(|e| ModuleError::parse("start time", e, record.start_time.as_str()))?
+            .and_utc();
+        let length = NaiveTime::parse_from_str(record.duration.as_str(), "%H:%M:%S")
+            .map_err(|e| ModuleError::parse("duration", e, record.duration.as_str()))?
+            .signed_duration_since(NaiveTime::MIN);
+        events.push(EventWithId {
+            summary: format!("[Netflix] {}", record.title),
+            description: format!("[profile] {}\n[device] {}\n[hash] {}", record.profile_name, record.device_type, record.id(identifier)),
+            duration: StartEnd(start, start + length),
+            id: record.id(identifier),
+            location: None,
+            url: None,
+            ical_uid: None,
+        });
+    }
+    Ok(events)
+}
+
 pub struct Netflix {
+    identifier: String,
     request_config: RequestConfig,
     event_ids: HashSet<String>,
+    netflix_config: NetflixConfig,
+}
+
+impl Netflix {
+    fn date_order(&self) -> DateOrder {
+        self.netflix_config.date_order.unwrap_or_else(|| {
+            let language = self.request_config.headers.get(ACCEPT_LANGUAGE)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or("");
+            DateOrder::from_language(language)
+        })
+    }
+
+    fn row_into_event_with_id(&self, row: &ElementRef, order: DateOrder) -> Result<EventWithId, ModuleError> {
+        let item = parse_row(row)?;
+        let date = parse_view_date(item.date.as_str(), order)?;
+        Ok(EventWithId {
+            summary: format!("[Netflix] {}", item.title),
+            description: format!("[link] https://www.netflix.com{}\n[hash] {}", item.link, item.id(self.identifier.as_str())),
+            duration: WholeDay(date),
+            id: item.id(self.identifier.as_str()),
+            location: None,
+            url: None,
+            ical_uid: None,
+        })
+    }
 }
 
 impl Module for Netflix {
-    fn new(calendar_id: Option<String>) -> Result<Box<dyn Module>, Box<dyn Error>> {
-        let request_config = RequestConfig::new(IDENTIFIER, calendar_id)?;
-        let event_ids = read_dumped_event_id(IDENTIFIER).unwrap_or(HashSet::new());
+    fn new(instance: Option<&str>, calendar_id: Option<String>) -> Result<Box<dyn Module>, Box<dyn Error>> {
+        let identifier = instance_identifier(IDENTIFIER, instance);
+        let (request_config, netflix_config) = read_configs::<NetflixConfig>(identifier.as_str(), calendar_id, |_| vec![])?;
+        let event_ids = read_dumped_event_id(identifier.as_str()).unwrap_or_default();
         Ok(Box::new(Netflix {
+            identifier,
             request_config,
             event_ids,
+            netflix_config,
         }))
     }
 
     fn dump(&self) {
-        dump_event_id_wrapper(IDENTIFIER, &self.event_ids);
+        dump_event_id_wrapper(self.identifier.as_str(), &self.event_ids);
     }
 
     fn get_config(&self) -> &RequestConfig {
@@ -54,42 +219,130 @@ impl Module for Netflix {
     }
 
     fn get_identifier(&self) -> &str {
-        IDENTIFIER
+        self.identifier.as_str()
     }
 
     fn get_request_url(&self) -> String {
         self.request_config.url.to_string()
     }
 
+    // Netflix redirects to its sign-in page once the cookie expires.
+    fn check_auth_health(&self, status: StatusCode, final_url: &str, _response: &str) -> Option<String> {
+        if final_url.contains("/login") {
+            Some(format!("redirected to {}", final_url))
+        } else if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+            Some(format!("HTTP status {}", status))
+        } else {
+            None
+        }
+    }
+
     fn need_for_detail(&self, _response: &String) -> Option<Vec<String>> {
         None
     }
 
-    fn process_response_into_event_with_id(&self, responses: Vec<String>) -> Result<Vec<EventWithId>, Box<dyn Error>> {
-        let response = responses[0].clone();
-        let document = Html::parse_document(response.as_str());
-        let selector = Selector::parse("li.retableRow").unwrap();
-        let title_selector = Selector::parse("div.title").unwrap();
-        let date_selector = Selector::parse("div.date").unwrap();
-        let link_selector = Selector::parse("a").unwrap();
-
-        let items: Vec<Item> = document.select(&selector).map(|e: ElementRef| {
-            let link_element = e.select(&title_selector).next().unwrap().select(&link_selector).next().unwrap();
-            Item {
-                link: String::from(link_element.value().attr("href").unwrap()),
-                title: link_element.inner_html(),
-                date: e.select(&date_selector).next().unwrap().inner_html(),
+    fn process_response_into_event_with_id(&self, responses: Vec<String>) -> Result<ParsedEvents, ModuleError> {
+        lazy_static! {
+            static ref ROW_SELECTOR: Selector = Selector::parse("li.retableRow").unwrap();
+        }
+        let documents: Vec<Html> = responses.iter().map(|response| Html::parse_document(response.as_str())).collect();
+        let order = self.date_order();
+
+        let mut parsed = ParsedEvents::default();
+        for (i, row) in documents.iter().flat_map(|document| document.select(&ROW_SELECTOR)).enumerate() {
+            match self.row_into_event_with_id(&row, order) {
+                Ok(event) => parsed.events.push(event),
+                Err(e) => parsed.skip(i, &e, row.html().as_str()),
             }
-        }).collect();
-
-        Ok(items.iter().map(|item| {
-            let date_info: Vec<u32> = item.date.split("/").collect::<Vec<&str>>().iter().map(|s: &&str| s.parse::<u32>().unwrap()).collect();
-            EventWithId {
-                summary: format!("[Netflix] {}", item.title),
-                description: format!("[link] https://www.netflix.com{}\n[hash] {}", item.link, item.id()),
-                duration: WholeDay(Utc.ymd((2000 + date_info[2]) as i32, date_info[0], date_info[1])),
-                id: item.id(),
+        }
+        Ok(parsed)
+    }
+
+    fn process_import_into_event_with_id(&self, content: String) -> Result<Vec<EventWithId>, Box<dyn Error>> {
+        Ok(parse_activity_csv(content.as_str(), self.identifier.as_str(), &self.netflix_config.profiles)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use chrono::{NaiveDate, TimeZone, Utc};
+    use reqwest::header::{ACCEPT_LANGUAGE, HeaderMap, HeaderValue};
+    use scraper::{Html, Selector};
+
+    use crate::calendar::event::Duration::StartEnd;
+    use crate::common::{Module, RequestConfig};
+    use crate::netflix::{DateOrder, Netflix, NetflixConfig, parse_activity_csv, parse_row, parse_view_date};
+    use crate::test_support::{assert_golden, fetch_events, StubServer};
+
+    #[test]
+    fn test_date_order_from_language() {
+        assert_eq!(DateOrder::from_language("en-US,en;q=0.5"), DateOrder::MonthDayYear);
+        assert_eq!(DateOrder::from_language("zh-CN,en-US;q=0.7,en;q=0.3"), DateOrder::YearMonthDay);
+        assert_eq!(DateOrder::from_language("en-GB"), DateOrder::DayMonthYear);
+        assert_eq!(DateOrder::from_language("de-DE"), DateOrder::DayMonthYear);
+        assert_eq!(DateOrder::from_language(""), DateOrder::MonthDayYear);
+    }
+
+    #[test]
+    fn test_parse_view_date() {
+        let date = NaiveDate::from_ymd_opt(2022, 10, 21).unwrap();
+        assert_eq!(parse_view_date("10/21/22", DateOrder::MonthDayYear).unwrap(), date);
+        assert_eq!(parse_view_date("21/10/2022", DateOrder::DayMonthYear).unwrap(), date);
+        assert_eq!(parse_view_date("21.10.22", DateOrder::DayMonthYear).unwrap(), date);
+        assert_eq!(parse_view_date("2022/10/21", DateOrder::YearMonthDay).unwrap(), date);
+        assert_eq!(parse_view_date("12/31/99", DateOrder::MonthDayYear).unwrap(), NaiveDate::from_ymd_opt(1999, 12, 31).unwrap());
+        assert!(parse_view_date("21/10/22", DateOrder::MonthDayYear).is_err());
+    }
+
+    #[test]
+    fn test_parse_row() {
+        let document = Html::parse_fragment(r#"<ul>
+<li class="retableRow"><div class="date">10/21/22</div><div class="title"><a href="/title/80025678">The Crown</a></div></li>
+<li class="retableRow"><div class="date">10/21/22</div><div class="title"><a href="/search">Search</a></div></li>
+</ul>"#);
+        let selector = Selector::parse("li.retableRow").unwrap();
+        let rows: Vec<_> = document.select(&selector).collect();
+        assert_eq!(parse_row(&rows[0]).unwrap().id("netflix"), "netflix|80025678|10/21/22");
+        assert!(parse_row(&rows[1]).is_err());
+    }
+
+    #[test]
+    fn test_parse_activity_csv() {
+        let content = "Profile Name,Start Time,Duration,Attributes,Title,Supplemental Video Type,Device Type,Bookmark,Latest Bookmark,Country
+Owen,2022-10-21 03:12:45,00:45:12,,\"The Crown: Season 1: Wolferton Splash (Episode 1)\",,Chrome PC (Cadmium),00:45:12,00:45:12,US (United States)
+Owen,2022-10-21 03:10:00,00:00:31,Autoplayed: user action: None; ,Season 2 Trailer: The Crown,TRAILER,Chrome PC (Cadmium),00:00:31,00:00:31,US (United States)
+Guest,2022-10-20 20:00:00,01:30:00,,Glass Onion,,Netflix Windows App,01:30:00,01:30:00,US (United States)
+";
+        let events = parse_activity_csv(content, "netflix", &["Owen".to_string()]).unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].summary, "[Netflix] The Crown: Season 1: Wolferton Splash (Episode 1)");
+        assert_eq!(events[0].id, "netflix|Owen|2022-10-21 03:12:45");
+        match events[0].duration {
+            StartEnd(start, end) => {
+                assert_eq!(start, Utc.with_ymd_and_hms(2022, 10, 21, 3, 12, 45).unwrap());
+                assert_eq!(end, Utc.with_ymd_and_hms(2022, 10, 21, 3, 57, 57).unwrap());
             }
-        }).collect())
+            _ => panic!("Imported events should be timed."),
+        }
+
+        assert_eq!(parse_activity_csv(content, "netflix", &[]).unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_fixture() {
+        let server = StubServer::start(&[("/viewingactivity", "netflix/viewing_activity.html")]);
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT_LANGUAGE, HeaderValue::from_static("en-US,en;q=0.5"));
+        let mut module: Box<dyn Module> = Box::new(Netflix {
+            identifier: "netflix".to_string(),
+            request_config: RequestConfig { headers, ..RequestConfig::for_test(&server.url("/viewingactivity")) },
+            event_ids: HashSet::new(),
+            netflix_config: NetflixConfig { profiles: vec![], date_order: None },
+        });
+
+        let events = fetch_events(&mut module).await;
+        assert_golden("netflix/expected.txt", &events, &Utc);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,227 @@
+// Offline stand-ins for the outside world: recorded responses under `fixtures/`, a local HTTP server
+// serving them, and a calendar which merely records what is posted.
+
+use std::collections::HashMap;
+use std::env;
+use std::fmt::{Display, Write};
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use calendar3::api::Event;
+use chrono::TimeZone;
+use reqwest::header::HeaderMap;
+
+use crate::calendar::CalendarSink;
+use crate::calendar::event::Duration::{MultiDay, StartEnd, WholeDay};
+use crate::calendar::event::EventWithId;
+use crate::common::{Module, RequestConfig, RequestPayload};
+use crate::common::config::{clear_test_overrides, set_test_override};
+use crate::common::archive::ResponseArchive;
+use crate::ics_feed::IcsFeed;
+
+const FIXTURE_DIRECTORY: &str = "fixtures";
+// Set to rewrite the expected outputs instead of comparing against them, after a deliberate change.
+const UPDATE_VARIABLE: &str = "UPDATE_FIXTURES";
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+pub fn read_fixture(path: &str) -> String {
+    fs::read_to_string(Path::new(FIXTURE_DIRECTORY).join(path))
+        .unwrap_or_else(|e| panic!("Cannot read fixture {}: {}", path, e))
+}
+
+// Runs a module through a sync cycle up to the events to be posted, without archiving or posting.
+pub async fn fetch_events(module: &mut Box<dyn Module>) -> Vec<EventWithId> {
+    let mut archive = ResponseArchive::disabled();
+    let responses = crate::fetch_responses(module, &mut archive).await.unwrap();
+    crate::filter_events_to_be_posted(module, responses, &mut archive).unwrap()
+}
+
+// Compares the events with the expected output in `fixtures/<path>`. Times are shown in `zone`, so that
+// modules reading wall-clock times in the local zone of the machine give the same output everywhere.
+pub fn assert_golden<Tz: TimeZone>(path: &str, events: &[EventWithId], zone: &Tz) where Tz::Offset: Display {
+    let rendered = render_events(events, zone);
+    let file = Path::new(FIXTURE_DIRECTORY).join(path);
+    if env::var(UPDATE_VARIABLE).is_ok() {
+        fs::write(&file, rendered).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&file)
+        .unwrap_or_else(|e| panic!("Cannot read {}: {}; run with {}=1 to create it.", path, e, UPDATE_VARIABLE));
+    assert!(rendered == expected, "Events differ from {}; run with {}=1 to update it if intended. Got:\n{}", path, UPDATE_VARIABLE, rendered);
+}
+
+fn render_events<Tz: TimeZone>(events: &[EventWithId], zone: &Tz) -> String where Tz::Offset: Display {
+    let mut rendered = String::new();
+    for event in events {
+        let time = match &event.duration {
+            StartEnd(start, end) => format!("{} – {}", start.with_timezone(zone).format(TIME_FORMAT), end.with_timezone(zone).format(TIME_FORMAT)),
+            WholeDay(day) => format!("{} (all day)", day),
+            MultiDay(first, last) => format!("{} – {} (all day)", first, last),
+        };
+        writeln!(rendered, "{}", event.summary).unwrap();
+        writeln!(rendered, "  id: {}", event.id).unwrap();
+        writeln!(rendered, "  time: {}", time).unwrap();
+        for (name, value) in [("location", &event.location), ("url", &event.url), ("ical_uid", &event.ical_uid)] {
+            if let Some(value) = value {
+                writeln!(rendered, "  {}: {}", name, value).unwrap();
+            }
+        }
+        writeln!(rendered, "  description:").unwrap();
+        for line in event.description.lines() {
+            writeln!(rendered, "    {}", line).unwrap();
+        }
+        rendered.push('\n');
+    }
+    rendered
+}
+
+// Module instance configured by a test rather than by a config file: the overrides (e.g. `URL`) apply to
+// the modules created on the thread of the test, and the state left by their cycles under `dump/` and
+// `archive/` is removed once dropped.
+pub struct TestInstance {
+    identifier: String,
+}
+
+impl TestInstance {
+    pub fn new(identifier: &str, overrides: &[(&str, String)]) -> TestInstance {
+        let instance = TestInstance { identifier: identifier.to_string() };
+        for (key, value) in overrides {
+            instance.set(key, value.clone());
+        }
+        instance
+    }
+
+    pub fn set(&self, key: &str, value: String) {
+        set_test_override(self.identifier.as_str(), key, value);
+    }
+}
+
+impl Drop for TestInstance {
+    fn drop(&mut self) {
+        clear_test_overrides(self.identifier.as_str());
+        for entry in fs::read_dir("dump").into_iter().flatten().flatten() {
+            if entry.file_name().to_string_lossy().starts_with(self.identifier.as_str()) {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+        let _ = fs::remove_dir_all(Path::new("archive").join(self.identifier.as_str()));
+        let _ = fs::remove_dir("archive");
+    }
+}
+
+// Instance of `ics_feed` syncing the whole span of `ics_feed/feed.ics` into the given calendar, served at
+// `/calendar.ics` along with its later version at `/changed.ics`.
+pub struct FeedInstance {
+    pub identifier: String,
+    pub server: StubServer,
+    pub instance: TestInstance,
+}
+
+impl FeedInstance {
+    pub fn new(name: &str, calendar_id: &str) -> FeedInstance {
+        let identifier = format!("ics_feed.{}", name);
+        let server = StubServer::start(&[("/calendar.ics", "ics_feed/feed.ics"), ("/changed.ics", "ics_feed/feed_changed.ics")]);
+        let instance = TestInstance::new(identifier.as_str(), &[
+            ("URL", server.url("/calendar.ics")),
+            ("CALENDAR_ID", calendar_id.to_string()),
+            ("PAST_DAYS", "36500".to_string()),
+            ("FUTURE_DAYS", "36500".to_string()),
+        ]);
+        FeedInstance { identifier, server, instance }
+    }
+
+    // Points the instance at another path of the server, e.g. `/changed.ics`.
+    pub fn serve(&self, path: &str) {
+        self.instance.set("URL", self.server.url(path));
+    }
+
+    pub fn module(&self) -> Box<dyn Module> {
+        IcsFeed::new(self.identifier.strip_prefix("ics_feed."), None).unwrap()
+    }
+}
+
+// Serves fixtures on a local port until dropped. Routes map a path to a fixture file; a route with a
+// query only matches that very query, while one without matches the path with any query.
+pub struct StubServer {
+    server: Arc<tiny_http::Server>,
+    requests: Arc<Mutex<Vec<String>>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl StubServer {
+    pub fn start(routes: &[(&str, &str)]) -> StubServer {
+        let routes: HashMap<String, String> = routes.iter()
+            .map(|(route, fixture)| (route.to_string(), read_fixture(fixture)))
+            .collect();
+        let server = Arc::new(tiny_http::Server::http("127.0.0.1:0").unwrap());
+        let requests = Arc::new(Mutex::new(vec![]));
+        let thread = {
+            let server = server.clone();
+            let requests = requests.clone();
+            thread::spawn(move || {
+                for request in server.incoming_requests() {
+                    let url = request.url().to_string();
+                    requests.lock().unwrap().push(format!("{} {}", request.method(), url));
+                    let path = url.split('?').next().unwrap_or_default();
+                    let response = match routes.get(url.as_str()).or_else(|| routes.get(path)) {
+                        Some(body) => tiny_http::Response::from_string(body.as_str()),
+                        None => tiny_http::Response::from_string(format!("No fixture for {}", url)).with_status_code(404),
+                    };
+                    let _ = request.respond(response);
+                }
+            })
+        };
+        StubServer { server, requests, thread: Some(thread) }
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.server.server_addr().to_ip().unwrap(), path)
+    }
+
+    // Requests received so far, e.g. "GET /history?ps=20".
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Drop for StubServer {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl RequestConfig {
+    // Config of a module built by hand in a test, with neither headers nor payload.
+    pub fn for_test(url: &str) -> RequestConfig {
+        RequestConfig {
+            url: url.to_string(),
+            calendar_id: "".to_string(),
+            headers: HeaderMap::new(),
+            payload: RequestPayload::default(),
+            cookie_jar: None,
+        }
+    }
+}
+
+// Calendar keeping the posted events along with the calendar id they were posted to.
+#[derive(Default)]
+pub struct FakeCalendar {
+    pub posted: Vec<(String, Event)>,
+    // Refuses every post, like a calendar which cannot be reached.
+    pub failing: bool,
+}
+
+impl CalendarSink for FakeCalendar {
+    async fn post(&mut self, config: &RequestConfig, event: Event) -> bool {
+        if self.failing {
+            return false;
+        }
+        self.posted.push((config.calendar_id.clone(), event));
+        true
+    }
+}
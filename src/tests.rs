@@ -1,10 +1,14 @@
 use calendar3::api::{Calendar, CalendarListEntry};
 
+use crate::calendar::mock::MockCalendarServer;
+use crate::common::metrics::module_metrics;
 use crate::league_of_graphs::LeagueOfGraphs;
+use crate::test_support::{FakeCalendar, FeedInstance};
 
 use super::*;
 
 #[tokio::test]
+#[ignore = "talks to Google Calendar and the live sites; needs config/clientsecret.json and the module configs"]
 async fn list_all_calendar() {
     env_logger::init();
     let mut hub = init_hub().await;
@@ -26,83 +30,71 @@ async fn get_all_calendars(hub: &mut CalHub) -> Option<Vec<CalendarListEntry>> {
     }
 }
 
+// A whole flow against the mock calendar: a test calendar is created, a feed synced into it, synced again
+// after one of its events is deleted by hand and changed at the source, and the calendar deleted again.
 #[tokio::test]
-async fn test_integration() -> Result<(), Box<dyn Error>> {
-    env_logger::init();
-    let mut hub = init_hub().await;
-
-    let test_calendar_name = "Test purpose only";
-
-    // First clear any calendar of the same name
-    match get_all_calendars(&mut hub).await {
-        None => println!("No calendars."),
-        Some(vec) => {
-            for entry in vec {
-                if entry.summary.unwrap() == test_calendar_name {
-                    match hub.calendars().delete(entry.id.unwrap().as_str()).doit().await {
-                        Ok(_) => println!("Previous test calendar deleted."),
-                        Err(e) => panic!("{:#?}", e),
-                    }
-                }
-            }
-        }
-    }
-
-    // Then add our new test calendar
+async fn test_mock_integration() -> Result<(), Box<dyn Error>> {
+    let calendar = MockCalendarServer::start();
+    let mut hub = calendar.hub().await;
     let req = Calendar {
-        summary: Some(test_calendar_name.to_string()),
+        summary: Some("Test purpose only".to_string()),
         ..Calendar::default()
     };
-    let result = hub.calendars().insert(req).doit().await;
-    match result {
-        Ok((_resp, calendar)) => {
-            let calendar_id = calendar.id.unwrap();
-            println!("The test calendar is {}. Please visit https://calendar.google.com/calendar/r.", &calendar_id);
-
-            let modules: Vec<Box<dyn Module>> = filter_loaded_modules(vec![
-                // Bilibili::new(Some(calendar_id.clone())),
-                LeagueOfGraphs::new(Some(calendar_id.clone())),
-                // LeagueOfLegends::new(Some(calendar_id.clone())),
-                // Netflix::new(Some(calendar_id.clone())),
-                // Wakatime::new(Some(calendar_id.clone())),
-                UTOdenSeminar::new(Some(calendar_id.clone())),
-                // Youtube::new(Some(calendar_id.clone())),
-            ]);
-
-            for mut module in modules {
-                let response = fetch_data(&mut module).await?;
-                let detail_response = make_detail(&mut module, response).await?;
-                let events = filter_events_to_be_posted(&mut module, detail_response)?;
-                for event in events {
-                    println!("{:?}", event);
-                    calendar_post(&mut hub, module.get_config(), event.into()).await;
-                }
-            }
-        }
-        Err(e) => panic!("Error occurred when insert the test calendar! {}", e),
-    }
+    let calendar_id = hub.calendars().insert(req).doit().await?.1.id.unwrap();
+
+    let feed = FeedInstance::new("mock_test", calendar_id.as_str());
+    let mut module = feed.module();
+    sync_module(&mut hub, &mut module).await?;
+    sync_module(&mut hub, &mut module).await?;
+
+    let seminar = calendar.events(calendar_id.as_str()).into_iter()
+        .find(|event| event.i_cal_uid.as_deref() == Some("seminar-1@example.edu")).unwrap();
+    hub.events().delete(calendar_id.as_str(), seminar.id.unwrap().as_str()).doit().await?;
+    feed.serve("/changed.ics");
+    let mut changed = feed.module();
+    sync_module(&mut hub, &mut changed).await?;
+
+    let events = calendar.events(calendar_id.as_str());
+    let summaries: Vec<(&str, &str)> = events.iter()
+        .map(|event| (event.summary.as_deref().unwrap(), event.status.as_deref().unwrap()))
+        .collect();
+    assert_eq!(summaries, vec![
+        ("Seminar: Numerical methods, revisited (room changed)", "confirmed"),
+        ("Home game vs. Rivals with a summary folded over two lines", "confirmed"),
+        ("Thanksgiving break", "confirmed"),
+        ("[Cancelled] Team offsite", "confirmed"),
+    ]);
+    assert_eq!(events[0].location.as_deref(), Some("POB 2.402"));
+    let event_requests = format!("/calendar/v3/calendars/{}/events", calendar_id);
+    let requests = calendar.requests();
+    assert_eq!(requests.iter().filter(|request| request.starts_with(format!("POST {}", event_requests).as_str())).count(), 4);
+    assert_eq!(requests.iter().filter(|request| request.starts_with(format!("PATCH {}/", event_requests).as_str())).count(), 1);
 
+    hub.calendars().delete(calendar_id.as_str()).doit().await?;
+    let remaining = get_all_calendars(&mut hub).await.unwrap_or_default();
+    assert!(remaining.iter().all(|entry| entry.id.as_deref() != Some(calendar_id.as_str())));
     Ok(())
 }
 
 #[tokio::test]
+#[ignore = "talks to Google Calendar and the live sites; needs config/clientsecret.json and the module configs"]
 async fn test_fetch() -> Result<(), Box<dyn Error>> {
     env_logger::init();
 
-    let modules: Vec<Box<dyn Module>> = filter_loaded_modules(vec![
-        // Bilibili::new(None),
-        LeagueOfGraphs::new(None),
-        // LeagueOfLegends::new(None),
-        // Netflix::new(None),
-        UTOdenSeminar::new(None),
-        // Wakatime::new(None),
-        // Youtube::new(None),
-    ]);
+    let modules: Vec<Box<dyn Module>> = load_modules(vec![
+        // Bilibili::new(None, None),
+        LeagueOfGraphs::new(None, None),
+        // LeagueOfLegends::new(None, None),
+        // Netflix::new(None, None),
+        UTOdenSeminar::new(None, None),
+        // Wakatime::new(None, None),
+        // Youtube::new(None, None),
+    ])?;
 
     for mut module in modules {
-        let response = fetch_data(&mut module).await?;
-        let detail_response = make_detail(&mut module, response).await?;
-        let events = filter_events_to_be_posted(&mut module, detail_response)?;
+        let response = fetch_data(&mut module, &mut ResponseArchive::disabled()).await?;
+        let detail_response = make_detail(&mut module, &mut ResponseArchive::disabled(), response).await?;
+        let events = filter_events_to_be_posted(&mut module, detail_response, &mut ResponseArchive::disabled())?;
         println!("{:#?}", events);
     }
 
@@ -110,23 +102,24 @@ async fn test_fetch() -> Result<(), Box<dyn Error>> {
 }
 
 #[tokio::test]
+#[ignore = "talks to Google Calendar and the live sites; needs config/clientsecret.json and the module configs"]
 async fn test_dump() -> Result<(), Box<dyn Error>> {
     env_logger::init();
 
-    let modules: Vec<Box<dyn Module>> = filter_loaded_modules(vec![
-        // Bilibili::new(None),
-        LeagueOfGraphs::new(None),
-        // LeagueOfLegends::new(None),
-        // Netflix::new(None),
-        UTOdenSeminar::new(None),
-        // Wakatime::new(None),
-        // Youtube::new(None),
-    ]);
+    let modules: Vec<Box<dyn Module>> = load_modules(vec![
+        // Bilibili::new(None, None),
+        LeagueOfGraphs::new(None, None),
+        // LeagueOfLegends::new(None, None),
+        // Netflix::new(None, None),
+        UTOdenSeminar::new(None, None),
+        // Wakatime::new(None, None),
+        // Youtube::new(None, None),
+    ])?;
 
     for mut module in modules {
-        let response = fetch_data(&mut module).await?;
-        let detail_response = make_detail(&mut module, response).await?;
-        filter_events_to_be_posted(&mut module, detail_response)?;
+        let response = fetch_data(&mut module, &mut ResponseArchive::disabled()).await?;
+        let detail_response = make_detail(&mut module, &mut ResponseArchive::disabled(), response).await?;
+        filter_events_to_be_posted(&mut module, detail_response, &mut ResponseArchive::disabled())?;
         // We skip the posting-to-calendar step
         module.dump()
     }
@@ -135,26 +128,27 @@ async fn test_dump() -> Result<(), Box<dyn Error>> {
 }
 
 #[tokio::test]
+#[ignore = "talks to Google Calendar and the live sites; needs config/clientsecret.json and the module configs"]
 async fn test_interval() -> Result<(), Box<dyn std::error::Error>> {
     env_logger::init();
 
-    let mut modules: Vec<Box<dyn Module>> = filter_loaded_modules(vec![
-        Bilibili::new(None),
-        LeagueOfLegends::new(None),
-        Netflix::new(None),
-        Wakatime::new(None),
-        Youtube::new(None),
-    ]);
+    let mut modules: Vec<Box<dyn Module>> = load_modules(vec![
+        Bilibili::new(None, None),
+        LeagueOfLegends::new(None, None),
+        Netflix::new(None, None),
+        Wakatime::new(None, None),
+        Youtube::new(None, None),
+    ])?;
 
     let mut interval = time::interval(std::time::Duration::from_millis(2 * 1000));
 
     for _ in 0..3 {
         interval.tick().await;
         info!("Timer picked up at {:#?}", SystemTime::now());
-        for mut module in &mut modules {
-            let response = fetch_data(&mut module).await?;
-            let detail_response = make_detail(&mut module, response).await?;
-            let events = filter_events_to_be_posted(&mut module, detail_response)?;
+        for module in &mut modules {
+            let response = fetch_data(module, &mut ResponseArchive::disabled()).await?;
+            let detail_response = make_detail(module, &mut ResponseArchive::disabled(), response).await?;
+            let events = filter_events_to_be_posted(module, detail_response, &mut ResponseArchive::disabled())?;
             println!("{}", events.len());
             // We skip the posting-to-calendar step and the dumping step
         }
@@ -164,6 +158,71 @@ async fn test_interval() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+// A whole cycle without network: the config comes from overrides set by the test, the feed from the stub
+// server and the events go to a fake calendar. Later cycles, even after a restart, post nothing again.
+#[tokio::test]
+async fn test_offline_sync() -> Result<(), Box<dyn Error>> {
+    let feed = FeedInstance::new("offline_test", "offline@group.calendar.google.com");
+    let mut calendar = FakeCalendar::default();
+    let mut module = feed.module();
+    sync_module(&mut calendar, &mut module).await?;
+    sync_module(&mut calendar, &mut module).await?;
+    let mut restarted = feed.module();
+    sync_module(&mut calendar, &mut restarted).await?;
+
+    assert_eq!(feed.server.requests().len(), 3);
+    let summaries: Vec<&str> = calendar.posted.iter().filter_map(|(_, event)| event.summary.as_deref()).collect();
+    assert_eq!(summaries, vec![
+        "Seminar: Numerical methods, revisited",
+        "Home game vs. Rivals with a summary folded over two lines",
+        "Thanksgiving break",
+        "[Cancelled] Team offsite",
+    ]);
+    assert!(calendar.posted.iter().all(|(calendar_id, _)| calendar_id == "offline@group.calendar.google.com"));
+    let metrics = &module_metrics()[feed.identifier.as_str()];
+    assert_eq!((metrics.fetches, metrics.events_parsed, metrics.events_deduped, metrics.events_posted), (3, 12, 8, 4));
+    assert!(metrics.last_success.is_some());
+    Ok(())
+}
+
+// Events which cannot be posted are taken as new again in the next cycle, and a cycle posting none of
+// its events is not counted as a success.
+#[tokio::test]
+async fn test_failed_posts_are_retried() -> Result<(), Box<dyn Error>> {
+    let feed = FeedInstance::new("unreachable_test", "unreachable@group.calendar.google.com");
+    let mut calendar = FakeCalendar { failing: true, ..FakeCalendar::default() };
+    let mut module = feed.module();
+    match sync_module(&mut calendar, &mut module).await {
+        Err(e @ ModuleError::Network(_)) => assert!(e.is_transient()),
+        other => panic!("Expected the cycle to be retried but got {:?}", other),
+    }
+    assert!(module.get_event_ids().is_empty());
+    let metrics = &module_metrics()[feed.identifier.as_str()];
+    assert_eq!((metrics.events_posted, metrics.post_failures), (0, 4));
+    assert!(metrics.last_success.is_none());
+
+    calendar.failing = false;
+    sync_module(&mut calendar, &mut module).await?;
+    assert_eq!(calendar.posted.len(), 4);
+    assert!(module_metrics()[feed.identifier.as_str()].last_success.is_some());
+    Ok(())
+}
+
+// A feed which disappeared fails the cycle as a change of the source, which is alerted instead of retried.
+#[tokio::test]
+async fn test_source_changed() -> Result<(), Box<dyn Error>> {
+    let feed = FeedInstance::new("moved_test", "moved@group.calendar.google.com");
+    feed.serve("/moved.ics");
+    let mut calendar = FakeCalendar::default();
+    let mut module = feed.module();
+    match sync_module(&mut calendar, &mut module).await {
+        Err(e @ ModuleError::SourceChanged(_)) => assert!(!e.is_transient()),
+        other => panic!("Expected a change of the source but got {:?}", other),
+    }
+    assert!(calendar.posted.is_empty());
+    Ok(())
+}
+
 #[test]
 fn test_filter_event() {
     let events: Vec<EventWithId> = vec![
@@ -172,39 +231,75 @@ fn test_filter_event() {
             description: "".to_string(),
             duration: StartEnd(Utc::now() - Duration::hours(2), Utc::now() - Duration::hours(1) - Duration::minutes(30)),
             id: "".to_string(),
+            location: None,
+            url: None,
+            ical_uid: None,
         },
         EventWithId {
             summary: "2".to_string(),
             description: "".to_string(),
             duration: StartEnd(Utc::now() - Duration::hours(3), Utc::now() - Duration::minutes(30)),
             id: "".to_string(),
+            location: None,
+            url: None,
+            ical_uid: None,
         },
         EventWithId {
             summary: "3".to_string(),
             description: "".to_string(),
             duration: StartEnd(Utc::now() - Duration::minutes(40), Utc::now() + Duration::minutes(30)),
             id: "".to_string(),
+            location: None,
+            url: None,
+            ical_uid: None,
         },
         EventWithId {
             summary: "4".to_string(),
             description: "".to_string(),
-            duration: WholeDay(Utc::today()),
+            duration: WholeDay(Utc::now().date_naive()),
             id: "".to_string(),
+            location: None,
+            url: None,
+            ical_uid: None,
         },
         EventWithId {
             summary: "5".to_string(),
             description: "".to_string(),
-            duration: WholeDay(Utc::today() - Duration::days(1)),
+            duration: WholeDay(Utc::now().date_naive() - Duration::days(1)),
             id: "".to_string(),
+            location: None,
+            url: None,
+            ical_uid: None,
         },
         EventWithId {
             summary: "6".to_string(),
             description: "".to_string(),
-            duration: WholeDay(Utc::today() + Duration::days(1)),
+            duration: WholeDay(Utc::now().date_naive() + Duration::days(1)),
+            id: "".to_string(),
+            location: None,
+            url: None,
+            ical_uid: None,
+        },
+        EventWithId {
+            summary: "7".to_string(),
+            description: "".to_string(),
+            duration: MultiDay(Utc::now().date_naive() - Duration::days(3), Utc::now().date_naive() - Duration::days(1)),
+            id: "".to_string(),
+            location: None,
+            url: None,
+            ical_uid: None,
+        },
+        EventWithId {
+            summary: "8".to_string(),
+            description: "".to_string(),
+            duration: MultiDay(Utc::now().date_naive() - Duration::days(1), Utc::now().date_naive()),
             id: "".to_string(),
+            location: None,
+            url: None,
+            ical_uid: None,
         },
     ];
     let filtered_events = filter_event(events);
     let filtered_ids = filtered_events.iter().map(|e| e.summary.parse::<i32>().unwrap()).collect::<Vec<i32>>();
-    assert_eq!(filtered_ids, vec![1, 5])
+    assert_eq!(filtered_ids, vec![1, 5, 7])
 }
\ No newline at end of file
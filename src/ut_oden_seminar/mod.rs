@@ -1,71 +1,157 @@
+use std::cell::RefCell;
 use std::collections::HashSet;
 use std::error::Error;
-use std::fmt;
 
-use chrono::{DateTime, FixedOffset, TimeZone};
+use chrono::{DateTime, NaiveDate, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
 use lazy_static::lazy_static;
 use regex::Regex;
-use scraper::{Html, Selector};
+use scraper::{ElementRef, Html, Selector};
+use serde::{Deserialize, Serialize};
 
 use crate::calendar::event::*;
 use crate::calendar::event::Duration::StartEnd;
 use crate::common::*;
-use crate::ut_oden_seminar::ParseError::*;
+use crate::common::diagnostic::ParsedEvents;
+use crate::common::error::ModuleError;
 
 const IDENTIFIER: &str = "ut_oden_seminar";
 
 #[derive(Debug)]
 struct Item {
     title: String,
-    description: String,
-    seminar_id: u32,
-    start: DateTime<FixedOffset>,
-    end: DateTime<FixedOffset>,
+    speaker: Option<String>,
+    affiliation: Option<String>,
+    abstract_text: Option<String>,
+    // Room of the seminar, e.g. "POB 6.304 & Zoom".
+    location: Option<String>,
+    meeting_url: Option<String>,
+    seminar_id: String,
+    start: DateTime<Tz>,
+    end: DateTime<Tz>,
 }
 
 impl Item {
-    fn id(self: &Item) -> String {
-        let id = self.seminar_id;
-        format!("{}|{}|{}", IDENTIFIER, id, self.start.format("%Y-%m-%d %H:%M").to_string())
+    fn id(self: &Item, identifier: &str) -> String {
+        format!("{}|{}|{}", identifier, self.seminar_id, self.start.format("%Y-%m-%d %H:%M"))
+    }
+
+    fn to_event_with_id(self: Item, identifier: &str) -> EventWithId {
+        let id = self.id(identifier);
+        let mut description = self.abstract_text.map(|a| a + "\n").unwrap_or_default();
+        let details = [("speaker", &self.speaker), ("affiliation", &self.affiliation), ("location", &self.location), ("meeting", &self.meeting_url)];
+        for (key, value) in details.iter() {
+            if let Some(value) = value {
+                description += format!("[{}] {}\n", key, value).as_str();
+            }
+        }
+        description += format!("[hash] {}", id).as_str();
+        EventWithId {
+            summary: self.title,
+            description,
+            duration: StartEnd(self.start.with_timezone(&Utc), self.end.with_timezone(&Utc)),
+            id,
+            location: self.location,
+            url: self.meeting_url,
+            ical_uid: None,
+        }
+    }
+}
+
+// Text of an element with tags dropped, entities decoded and whitespace collapsed.
+fn element_text(element: &ElementRef) -> String {
+    element.text().collect::<String>().split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+// Selectors within the seminar page; the defaults fit the Oden Institute.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+struct SeminarSelectors {
+    // Element holding all the fields below.
+    container: String,
+    title: String,
+    speaker: String,
+    affiliation: String,
+    // Lines (separated by <br>) giving the date and the time range of the seminar.
+    when: String,
+    // A link inside is taken as the online meeting.
+    location: String,
+    #[serde(rename = "abstract")]
+    abstract_text: String,
+}
+
+impl Default for SeminarSelectors {
+    fn default() -> Self {
+        SeminarSelectors {
+            container: "div.cell.large-8".to_string(),
+            title: "h1.event__title".to_string(),
+            speaker: "span.event__speaker".to_string(),
+            affiliation: "span.event__speaker-affiliation".to_string(),
+            when: "div.event__logistics p:nth-of-type(1)".to_string(),
+            location: "div.event__logistics p:nth-of-type(2)".to_string(),
+            abstract_text: "p:nth-of-type(4)".to_string(),
+        }
     }
 }
 
-#[derive(Debug, Clone)]
-enum ParseError {
-    UnwrapNone(String),
-    UnknownMonth(String),
-    ParseFails(String),
-    CaptureFails(String),
-    ComingSoon,
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+struct SeminarConfig {
+    // Links to the seminar pages in the listing, either absolute or relative to the listing site.
+    link_regex: String,
+    // Captures the seminar id in the seminar page.
+    id_regex: String,
+    selectors: SeminarSelectors,
+    // e.g. "%A %b %d, %Y" for "Tuesday Oct 11, 2022".
+    date_format: String,
+    // Separates the start and the end time, e.g. "3:30 – 5PM".
+    time_separator: String,
+    // e.g. "%H:%M"; 12-hour times like "3:30" or "5PM" are understood if not set.
+    time_format: Option<String>,
+    // Zone the times on the page are given in, e.g. "America/Chicago".
+    time_zone: String,
+    // Titles of placeholders which are visited again every cycle until the seminar is announced.
+    coming_soon_regex: String,
 }
 
-impl fmt::Display for ParseError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            UnwrapNone(t) => write!(f, "Try to unwrap None at: {}", t),
-            UnknownMonth(t) => write!(f, "Unknown month: {}", t),
-            ParseFails(t) => write!(f, "Parse fails: {}", t),
-            CaptureFails(t) => write!(f, "Capture fails: {}", t),
-            ComingSoon => write!(f, "Event comes soon, ignored for now.")
+impl Default for SeminarConfig {
+    fn default() -> Self {
+        SeminarConfig {
+            link_regex: r"/news-and-events/events/\d+".to_string(),
+            id_regex: r"news-and-events/events/(\d+)".to_string(),
+            selectors: SeminarSelectors::default(),
+            date_format: "%A %b %d, %Y".to_string(),
+            time_separator: " – ".to_string(),
+            time_format: None,
+            time_zone: "America/Chicago".to_string(),
+            coming_soon_regex: "^Coming soon".to_string(),
         }
     }
 }
 
-impl Error for ParseError {}
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct SeminarState {
+    pending_urls: HashSet<String>,
+}
 
 pub struct UTOdenSeminar {
+    identifier: String,
     request_config: RequestConfig,
     event_ids: HashSet<String>,
+    link_regex: Regex,
+    parser: SeminarParser,
+    // Seminar pages announced as coming soon, fetched every cycle until they can be parsed.
+    pending_urls: RefCell<HashSet<String>>,
+    // Pages requested in the current cycle, in the order of the responses.
+    detail_urls: RefCell<Vec<String>>,
 }
 
-fn parse_time(time_str: &str) -> Result<(i32, i32), Box<dyn Error>> {
+fn parse_time(time_str: &str) -> Result<(i32, i32), ModuleError> {
     let hour_minute_str = time_str.trim_end_matches("AM").trim_end_matches("PM");
-    let (mut hour, minute) = if hour_minute_str.contains(':') {
-        let split: Vec<&str> = hour_minute_str.split(':').collect();
-        (split[0].parse::<i32>()?,
-         split[1].parse::<i32>()?)
-    } else {
-        (hour_minute_str.parse::<i32>()?, 0)
+    let parse_number = |s: &str| s.parse::<i32>().map_err(|e| ModuleError::parse("time", e, time_str));
+    let (mut hour, minute) = match hour_minute_str.split_once(':') {
+        Some((hour, minute)) => (parse_number(hour)?, parse_number(minute)?),
+        None => (parse_number(hour_minute_str)?, 0),
     };
     if hour == 12 {
         if time_str.contains("AM") {
@@ -80,98 +166,165 @@ fn parse_time(time_str: &str) -> Result<(i32, i32), Box<dyn Error>> {
     Ok((hour, minute))
 }
 
-fn parse_selector(selection: &str) -> Result<Selector, ParseError> {
-    Selector::parse(selection).map_err(|_e| ParseFails(format!("selector {}", selection)))
+fn parse_selector(selection: &str) -> Result<Selector, String> {
+    Selector::parse(selection).map_err(|_e| format!("invalid selector {}", selection))
+}
+
+fn select_first<'a>(element: &ElementRef<'a>, selector: &Selector, name: &str) -> Result<ElementRef<'a>, ModuleError> {
+    element.select(selector).next().ok_or_else(|| ModuleError::parse("seminar page", format!("{} is missing", name), element.html().as_str()))
 }
 
-fn parse_seminar(response: &str) -> Result<Item, Box<dyn Error>> {
-    let document = Html::parse_document(response);
-    let div_selector = parse_selector("div.cell.large-8")?;
-    let cell_div = document.select(&div_selector).next().ok_or(UnwrapNone("div.cell.large-8".to_string()))?;
+struct SeminarParser {
+    container: Selector,
+    title: Selector,
+    speaker: Selector,
+    affiliation: Selector,
+    when: Selector,
+    location: Selector,
+    abstract_text: Selector,
+    id_regex: Regex,
+    date_format: String,
+    time_separator: String,
+    time_format: Option<String>,
+    time_zone: Tz,
+    coming_soon_regex: Regex,
+}
+
+impl SeminarParser {
+    fn new(config: &SeminarConfig) -> Result<SeminarParser, Box<dyn Error>> {
+        let selectors = &config.selectors;
+        Ok(SeminarParser {
+            container: parse_selector(selectors.container.as_str())?,
+            title: parse_selector(selectors.title.as_str())?,
+            speaker: parse_selector(selectors.speaker.as_str())?,
+            affiliation: parse_selector(selectors.affiliation.as_str())?,
+            when: parse_selector(selectors.when.as_str())?,
+            location: parse_selector(selectors.location.as_str())?,
+            abstract_text: parse_selector(selectors.abstract_text.as_str())?,
+            id_regex: Regex::new(config.id_regex.as_str())?,
+            date_format: config.date_format.clone(),
+            time_separator: config.time_separator.clone(),
+            time_format: config.time_format.clone(),
+            time_zone: config.time_zone.parse::<Tz>().map_err(|_e| format!("unknown time zone {}", config.time_zone))?,
+            coming_soon_regex: Regex::new(config.coming_soon_regex.as_str())?,
+        })
+    }
 
-    let title_h1_selector = parse_selector("h1.event__title")?;
-    let speaker_span_selector = parse_selector("span.event__speaker")?;
-    let affiliation_span_selector = parse_selector("span.event__speaker-affiliation")?;
+    fn parse_time(&self, time_str: &str) -> Result<NaiveTime, ModuleError> {
+        let time_str = time_str.trim();
+        match &self.time_format {
+            Some(time_format) => NaiveTime::parse_from_str(time_str, time_format.as_str()).map_err(|e| ModuleError::parse("time", e, time_str)),
+            None => {
+                let (hour, minute) = parse_time(time_str)?;
+                NaiveTime::from_hms_opt(hour as u32, minute as u32, 0).ok_or_else(|| ModuleError::parse("time", "out of range", time_str))
+            }
+        }
+    }
 
-    let title = cell_div.select(&title_h1_selector).next().ok_or(UnwrapNone("title".to_string()))?.inner_html();
-    if title.starts_with("Coming soon") {
-        return Err(Box::new(ComingSoon))
+    fn localize(&self, date: NaiveDate, time: NaiveTime) -> Result<DateTime<Tz>, ModuleError> {
+        self.time_zone.from_local_datetime(&date.and_time(time)).earliest()
+            .ok_or_else(|| ModuleError::parse("time", format!("not a local time in {}", self.time_zone), format!("{} {}", date, time).as_str()))
     }
-    let speaker = cell_div.select(&speaker_span_selector).next().ok_or(UnwrapNone("speaker".to_string()))?.inner_html();
-    let affiliation = cell_div.select(&affiliation_span_selector).next().ok_or(UnwrapNone("affiliation".to_string()))?.inner_html();
-
-    let logistics_selector = parse_selector("div.event__logistics")?;
-    let p_selector = parse_selector("p")?;
-    let a_selector = parse_selector("a")?;
-    let span_selector = parse_selector("span")?;
-
-    let logistics_div = cell_div.select(&logistics_selector).next().ok_or(UnwrapNone("div.event__logistics".to_string()))?;
-    let info_paragraph = logistics_div.select(&p_selector).next().ok_or(UnwrapNone("first (info) paragraph".to_string()))?;
-    let info_str = info_paragraph.inner_html().chars().filter(|c| c != &'\t').filter(|c| c != &'\n').collect::<String>();
-    let location_paragraph = logistics_div.select(&p_selector).skip(1).next().ok_or(UnwrapNone("second (location) paragraph".to_string()))?;
-    let location = match location_paragraph.select(&a_selector).next() {
-        Some(zoom_element) => {
-            let href = zoom_element.value().attr("href").unwrap();
-            let place = zoom_element.inner_html();
-            format!("{}: {}", place, href)
+
+    // None while the page is a placeholder announcing the seminar as coming soon.
+    fn parse_seminar(&self, response: &str) -> Result<Option<Item>, ModuleError> {
+        lazy_static! {
+            static ref A_SELECTOR: Selector = Selector::parse("a[href]").unwrap();
         }
-        None => {
-            location_paragraph.select(&span_selector).next().ok_or(UnwrapNone("span or a element for location".to_string()))?.inner_html()
+        let document = Html::parse_document(response);
+        let cell_div = select_first(&document.root_element(), &self.container, "container")?;
+
+        let title = element_text(&select_first(&cell_div, &self.title, "title")?);
+        if self.coming_soon_regex.is_match(title.as_str()) {
+            return Ok(None);
         }
-    };
-    let abs = cell_div.select(&p_selector).skip(5).next().ok_or(UnwrapNone("abstract paragraph".to_string()))?.inner_html();
-    let description = format!("{}, {}\n{}\n{}", speaker, affiliation, abs, location.as_str());
-
-    let info_split: Vec<&str> = info_str.split("<br>").collect();
-    let date_str = info_split[1].trim();
-    let time_str = info_split[0].trim().to_string();
-
-    let date_captures = Regex::new(r"(\w+) (\w+) (\d+), (\d+)")?
-        .captures_iter(date_str).next().ok_or(CaptureFails("date capture".to_string()))?;
-    let day = date_captures.get(3).ok_or(CaptureFails("day".to_string()))?
-        .as_str().parse::<i32>()?;
-    let month_str = date_captures.get(2).ok_or(CaptureFails("month".to_string()))?
-        .as_str();
-    let month_strs = vec!["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
-    let (month, _) = month_strs.iter().enumerate().find(|(_i, m)| *m == &month_str).ok_or(UnknownMonth(month_str.to_string()))?;
-    let year = date_captures.get(4).ok_or(CaptureFails("year".to_string()))?
-        .as_str().parse::<i32>().unwrap();
-
-    let time_split: Vec<&str> = time_str.split(" – ").collect();
-    let (start_hour, start_minute) = parse_time(time_split[0])?;
-    let (end_hour, end_minute) = parse_time(time_split[1])?;
-
-    // let last_breadcrumb_selector = parse_selector("span.breadcrumb__last")?;
-    // let seminar_str = document.select(&last_breadcrumb_selector).next().ok_or("span.breadcrumb__last".to_string())?.inner_html();
-    // let seminar_id = (seminar_str.as_str().trim().split(' ').next().unwrap()).to_string().parse::<u32>()?;
-
-    let seminar_captures = Regex::new(r"news-and-events/events/(\d*)")?
-        .captures_iter(response).next().ok_or(CaptureFails("seminar id".to_string()))?;
-    let seminar_id = seminar_captures.get(1).ok_or(CaptureFails("seminar id 1".to_string()))?.as_str().parse::<u32>()?;
-
-
-    let time_zone: FixedOffset = FixedOffset::west(5 * 60 * 60); // Daylight saving mode
-    Ok(Item {
-        title,
-        description,
-        seminar_id,
-        start: time_zone.ymd(year, month as u32 + 1, day as u32).and_hms(start_hour as u32, start_minute as u32, 0),
-        end: time_zone.ymd(year, month as u32 + 1, day as u32).and_hms(end_hour as u32, end_minute as u32, 0),
-    })
+        // Everything but the title and the time is optional, as not every department lists them.
+        let optional_text = |selector: &Selector| cell_div.select(selector).next().map(|e| element_text(&e)).filter(|t| !t.is_empty());
+        let speaker = optional_text(&self.speaker);
+        let affiliation = optional_text(&self.affiliation);
+        let abstract_text = optional_text(&self.abstract_text);
+        let location = optional_text(&self.location);
+
+        let meeting_url = cell_div.select(&self.location).next()
+            .and_then(|e| e.select(&A_SELECTOR).next())
+            .and_then(|a| a.value().attr("href"))
+            .filter(|href| href.starts_with("http"))
+            .map(|href| href.to_string());
+
+        let when_html = select_first(&cell_div, &self.when, "when")?.inner_html();
+        let when_lines: Vec<String> = when_html.split("<br>")
+            .map(|line| line.chars().filter(|c| c != &'\t').filter(|c| c != &'\n').collect::<String>().trim().to_string())
+            .collect();
+        let date = when_lines.iter()
+            .find_map(|line| NaiveDate::parse_from_str(line, self.date_format.as_str()).ok())
+            .ok_or_else(|| ModuleError::parse("date", format!("nothing matches {}", self.date_format), when_lines.join(" / ").as_str()))?;
+        let (start_str, end_str) = when_lines.iter()
+            .find_map(|line| line.split_once(self.time_separator.as_str()))
+            .ok_or_else(|| ModuleError::parse("time", format!("no {:?} between the start and the end", self.time_separator), when_lines.join(" / ").as_str()))?;
+        let start = self.localize(date, self.parse_time(start_str)?)?;
+        let end = self.localize(date, self.parse_time(end_str)?)?;
+
+        let seminar_id = self.id_regex.captures(response).and_then(|captures| captures.get(1))
+            .ok_or_else(|| ModuleError::parse("seminar id", format!("nothing matches {}", self.id_regex), ""))?
+            .as_str().to_string();
+
+        Ok(Some(Item {
+            title,
+            speaker,
+            affiliation,
+            abstract_text,
+            location,
+            meeting_url,
+            seminar_id,
+            start,
+            end,
+        }))
+    }
+}
+
+impl UTOdenSeminar {
+    // Keeps track of the placeholders so that they are revisited even when the listing drops them, until
+    // they parse as a seminar; a page which fails, e.g. while it is half published, is tried again.
+    fn update_pending(&self, url: Option<&String>, result: &Result<Option<Item>, ModuleError>) {
+        let url = match url {
+            Some(url) => url,
+            None => return,
+        };
+        let mut pending_urls = self.pending_urls.borrow_mut();
+        match result {
+            Ok(None) => {
+                pending_urls.insert(url.clone());
+            }
+            Ok(Some(_)) => {
+                pending_urls.remove(url);
+            }
+            Err(_) => {}
+        }
+    }
 }
 
 impl Module for UTOdenSeminar {
-    fn new(calendar_id: Option<String>) -> Result<Box<dyn Module>, Box<dyn Error>> {
-        let request_config = RequestConfig::new(IDENTIFIER, calendar_id)?;
-        let event_ids = read_dumped_event_id(IDENTIFIER).unwrap_or(HashSet::new());
+    fn new(instance: Option<&str>, calendar_id: Option<String>) -> Result<Box<dyn Module>, Box<dyn Error>> {
+        let identifier = instance_identifier(IDENTIFIER, instance);
+        let (request_config, seminar_config) = read_configs::<SeminarConfig>(identifier.as_str(), calendar_id, |_| vec![])?;
+        let event_ids = read_dumped_event_id(identifier.as_str()).unwrap_or_default();
+        let state = read_state::<SeminarState>(identifier.as_str());
         Ok(Box::new(Self {
+            identifier,
             request_config,
             event_ids,
+            link_regex: Regex::new(seminar_config.link_regex.as_str())?,
+            parser: SeminarParser::new(&seminar_config)?,
+            pending_urls: RefCell::new(state.pending_urls),
+            detail_urls: RefCell::new(vec![]),
         }))
     }
 
     fn dump(&self) {
-        dump_event_id_wrapper(IDENTIFIER, &self.event_ids);
+        dump_event_id_wrapper(self.identifier.as_str(), &self.event_ids);
+        dump_state(self.identifier.as_str(), &SeminarState {
+            pending_urls: self.pending_urls.borrow().clone(),
+        });
     }
 
     fn get_config(&self) -> &RequestConfig {
@@ -183,7 +336,7 @@ impl Module for UTOdenSeminar {
     }
 
     fn get_identifier(&self) -> &str {
-        IDENTIFIER
+        self.identifier.as_str()
     }
 
     fn get_request_url(&self) -> String {
@@ -192,42 +345,66 @@ impl Module for UTOdenSeminar {
 
     fn need_for_detail(&self, response: &String) -> Option<Vec<String>> {
         lazy_static! {
-            static ref RE: Regex = Regex::new(r"/news-and-events/events/\d+").unwrap();
+            static ref BASE_URL_RE: Regex = Regex::new(r"https?://[^/]*").unwrap();
+        }
+        let base_url = BASE_URL_RE.find(self.request_config.url.as_str())?.as_str();
+        let mut urls: Vec<String> = vec![];
+        for mat in self.link_regex.find_iter(response) {
+            let url = if mat.as_str().starts_with("http") { mat.as_str().to_string() } else { base_url.to_string() + mat.as_str() };
+            if !urls.contains(&url) {
+                urls.push(url);
+            }
         }
-        let base_url = Regex::new(r"https://[^/]*").unwrap().find(self.request_config.url.as_str())?.as_str();
-        Some(RE.find_iter(response).map(|mat| base_url.to_string() + mat.as_str()).collect())
+        let mut pending_urls: Vec<String> = self.pending_urls.borrow().iter().filter(|url| !urls.contains(url)).cloned().collect();
+        pending_urls.sort();
+        urls.extend(pending_urls);
+        *self.detail_urls.borrow_mut() = urls.clone();
+        Some(urls)
     }
 
-    fn process_response_into_event_with_id(&self, responses: Vec<String>) -> Result<Vec<EventWithId>, Box<dyn Error>> {
-        Ok(responses.into_iter()
-            .map(|r| parse_seminar(r.as_str()))
-            .filter_map(|r| match r {
-                Ok(t) => Some(t),
-                Err(e) => {
-                    info!("{}", e);
-                    None
-                }
-            })
-            .map(|r: Item| {
-                EventWithId {
-                    id: r.id(),
-                    summary: r.title,
-                    description: r.description,
-                    duration: StartEnd(DateTime::from(r.start), DateTime::from(r.end)),
-                }
-            })
-            .collect())
+    // Every seminar page is a row; a page which cannot be parsed is reported with its url.
+    fn process_response_into_event_with_id(&self, responses: Vec<String>) -> Result<ParsedEvents, ModuleError> {
+        let detail_urls = self.detail_urls.borrow();
+        let mut parsed = ParsedEvents::default();
+        for (i, response) in responses.iter().enumerate() {
+            let result = self.parser.parse_seminar(response.as_str());
+            self.update_pending(detail_urls.get(i), &result);
+            match result {
+                Ok(Some(item)) => parsed.events.push(item.to_event_with_id(self.identifier.as_str())),
+                Ok(None) => debug!("Seminar {} comes soon, to be visited again.", detail_urls.get(i).map_or("", String::as_str)),
+                Err(e) => parsed.skip(i, &e, detail_urls.get(i).map_or("", String::as_str)),
+            }
+        }
+        Ok(parsed)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::error::Error;
+    use std::cell::RefCell;
+    use std::collections::HashSet;
 
+    use chrono::{TimeZone, Utc};
     use lazy_static::lazy_static;
     use regex::Regex;
 
-    use crate::ut_oden_seminar::{parse_seminar, parse_time};
+    use crate::common::{Module, RequestConfig};
+    use crate::common::error::ModuleError;
+    use crate::test_support::{assert_golden, fetch_events, StubServer};
+    use crate::ut_oden_seminar::{parse_time, SeminarConfig, SeminarParser, UTOdenSeminar};
+
+    fn seminar() -> UTOdenSeminar {
+        let config = SeminarConfig::default();
+        UTOdenSeminar {
+            identifier: "ut_oden_seminar".to_string(),
+            request_config: RequestConfig::for_test("https://oden.utexas.edu/news-and-events/events/"),
+            event_ids: HashSet::new(),
+            link_regex: Regex::new(config.link_regex.as_str()).unwrap(),
+            parser: SeminarParser::new(&config).unwrap(),
+            pending_urls: RefCell::new(HashSet::new()),
+            detail_urls: RefCell::new(vec![]),
+        }
+    }
 
     #[test]
     fn test_regex() {
@@ -289,15 +466,62 @@ mod tests {
     <h2>Biography</h2>
     <p>Andrey obtained his&nbsp;Ph.D. in Computer Science from Virginia Tech (VT), and his B.S. in Mathematics from Rensselaer Polytechnic Institute (RPI).&nbsp;&nbsp;During the course of his Ph.D., Andrey has worked on ensemble filtering techniques including work with multifidelity data assimilation and with covariance shrinkage.&nbsp;He has also worked on extending and applying non-linear dimensionality reduction techniques to constructing efficient reduced order models for use in scientific applications.&nbsp;Andrey's other interests include data-driven science, knowledge-guided machine learning, and time integration</p>
 </div>";
-        match parse_seminar(response) {
-            Ok(item) => println!("{:#?}", item),
-            Err(e) => println!("{:#?}", e),
-        }
+        let item = SeminarParser::new(&SeminarConfig::default()).unwrap().parse_seminar(response).unwrap().unwrap();
+        assert_eq!(item.seminar_id, "1708");
+        assert!(item.title.starts_with("Combining collections"));
+        assert_eq!(item.speaker.as_deref(), Some("Andrey Popov"));
+        assert_eq!(item.affiliation.as_deref(), Some("ASE/EM Dept., UT Austin"));
+        assert_eq!(item.location.as_deref(), Some("POB 6.304 & Zoom"));
+        assert_eq!(item.meeting_url.as_deref(), Some("https://utexas.zoom.us/j/965"));
+        let abstract_text = item.abstract_text.as_deref().unwrap();
+        assert!(abstract_text.starts_with("Physics-based"));
+        assert!(abstract_text.contains("counterparts. This reality"));
+        assert!(!abstract_text.contains("&nbsp;"));
+        // Central daylight time.
+        assert_eq!(item.start.with_timezone(&Utc), Utc.with_ymd_and_hms(2022, 10, 11, 20, 30, 0).unwrap());
+        assert_eq!(item.end.with_timezone(&Utc), Utc.with_ymd_and_hms(2022, 10, 11, 22, 0, 0).unwrap());
+
+        let event = item.to_event_with_id("ut_oden_seminar");
+        assert_eq!(event.location.as_deref(), Some("POB 6.304 & Zoom"));
+        assert_eq!(event.url.as_deref(), Some("https://utexas.zoom.us/j/965"));
+        assert!(event.description.ends_with("[speaker] Andrey Popov\n[affiliation] ASE/EM Dept., UT Austin\n[location] POB 6.304 & Zoom\n[meeting] https://utexas.zoom.us/j/965\n[hash] ut_oden_seminar|1708|2022-10-11 15:30"));
+    }
+
+    #[test]
+    fn test_coming_soon_is_revisited() {
+        let coming_soon = "
+<div class=\"cell small-12 medium-12 large-8 \">
+    <h1 class=\"event__title\">Coming soon</h1>
+</div>";
+        let module = seminar();
+        let listing = "<a href=\"/news-and-events/events/1800---C\">Coming soon</a>".to_string();
+        let urls = module.need_for_detail(&listing).unwrap();
+        assert_eq!(urls, vec!["https://oden.utexas.edu/news-and-events/events/1800"]);
+        assert!(module.process_response_into_event_with_id(vec![coming_soon.to_string()]).unwrap().events.is_empty());
+
+        // Still visited once the listing no longer shows it.
+        let urls = module.need_for_detail(&"".to_string()).unwrap();
+        assert_eq!(urls, vec!["https://oden.utexas.edu/news-and-events/events/1800"]);
+
+        // Half published: tried again in the next cycle.
+        let half_published = "<div class=\"cell large-8\"><h1 class=\"event__title\">Announced</h1></div>";
+        assert_eq!(module.process_response_into_event_with_id(vec![half_published.to_string()]).unwrap().diagnostics.len(), 1);
+        assert_eq!(module.need_for_detail(&"".to_string()).unwrap(), vec!["https://oden.utexas.edu/news-and-events/events/1800"]);
+
+        let announced = "
+<link rel=\"canonical\" href=\"https://oden.utexas.edu/news-and-events/events/1800/\" />
+<div class=\"cell large-8\">
+    <h1 class=\"event__title\">Announced</h1>
+    <div class=\"event__logistics\"><p>3:30 – 5PM <br> Tuesday Oct 11, 2022</p></div>
+</div>";
+        let events = module.process_response_into_event_with_id(vec![announced.to_string()]).unwrap().events;
+        assert_eq!(events[0].summary, "Announced");
+        assert!(module.need_for_detail(&"".to_string()).unwrap().is_empty());
     }
 
     #[test]
     fn test_parse_time() {
-        fn h(a: Result<(i32, i32), Box<dyn Error>>, b: (i32, i32)) {
+        fn h(a: Result<(i32, i32), ModuleError>, b: (i32, i32)) {
             match a {
                 Ok(a) => {
                     assert_eq!(a, b);
@@ -314,4 +538,22 @@ mod tests {
         h(parse_time("5PM"), (17, 0));
         h(parse_time("5:30PM"), (17, 30));
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_fixture() {
+        let server = StubServer::start(&[
+            ("/news-and-events/events/", "ut_oden_seminar/listing.html"),
+            ("/news-and-events/events/1708", "ut_oden_seminar/event_1708.html"),
+            ("/news-and-events/events/1800", "ut_oden_seminar/event_1800.html"),
+        ]);
+        let mut module = seminar();
+        module.request_config.url = server.url("/news-and-events/events/");
+        let mut module: Box<dyn Module> = Box::new(module);
+
+        let events = fetch_events(&mut module).await;
+        assert_eq!(server.requests().len(), 3);
+        assert_golden("ut_oden_seminar/expected.txt", &events, &Utc);
+        // The seminar coming soon is visited again in the next cycle.
+        assert_eq!(module.need_for_detail(&"".to_string()).unwrap(), vec![server.url("/news-and-events/events/1800")]);
+    }
+}
@@ -1,26 +1,43 @@
-use std::collections::HashSet;
+use std::cell::Cell;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::error::Error;
 
-use chrono::{DateTime, Duration, TimeZone, Utc};
-use serde::Deserialize;
-use serde_json::Number;
+use chrono::{DateTime, Days, Duration, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use serde_json::{Number, Value};
 
 use crate::calendar::event::*;
-use crate::calendar::event::Duration::StartEnd;
+use crate::calendar::event::Duration::{StartEnd, WholeDay};
 use crate::common::*;
+use crate::common::diagnostic::ParsedEvents;
+use crate::common::error::ModuleError;
 
 const IDENTIFIER: &str = "wakatime";
+// Days caught up in one cycle at most; the rest is left to the following cycles. Each day takes a page of
+// durations and one of summaries, which stays within the pages a cycle may fetch.
+const MAX_DAYS_PER_CYCLE: u64 = 10;
 
 #[derive(Debug, Deserialize)]
 struct Item {
     duration: Number,
     project: String,
     time: Number,
+    // Holds the field named after `slice_by` (e.g. `language`) when durations are sliced.
+    #[serde(flatten)]
+    slices: HashMap<String, Value>,
 }
 
 impl Item {
-    fn id(self: &Item) -> String {
-        format!("{}|{}", IDENTIFIER, self.time)
+    fn id(self: &Item, identifier: &str, slice: Option<&str>) -> String {
+        match slice {
+            None => format!("{}|{}", identifier, self.time),
+            Some(slice) => format!("{}|{}|{}", identifier, self.time, slice),
+        }
+    }
+
+    fn slice(self: &Item, slice_by: &str) -> Option<&str> {
+        self.slices.get(slice_by).and_then(Value::as_str).filter(|s| !s.is_empty())
     }
 }
 
@@ -35,23 +52,271 @@ struct Response {
     timezone: String,
 }
 
+#[derive(Debug, Deserialize)]
+struct SummaryEntry {
+    name: String,
+    text: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SummaryGrandTotal {
+    text: String,
+    total_seconds: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct SummaryRange {
+    date: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Summary {
+    grand_total: SummaryGrandTotal,
+    range: SummaryRange,
+    #[serde(default)]
+    projects: Vec<SummaryEntry>,
+    #[serde(default)]
+    languages: Vec<SummaryEntry>,
+    #[serde(default)]
+    editors: Vec<SummaryEntry>,
+}
+
+impl Summary {
+    fn id(self: &Summary, identifier: &str) -> String {
+        format!("{}|summary|{}", identifier, self.range.date)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SummariesResponse {
+    data: Vec<Summary>,
+}
+
+impl SummariesResponse {
+    fn day(self: &SummariesResponse) -> Option<NaiveDate> {
+        parse_day(self.data.first()?.range.date.as_str()).ok()
+    }
+}
+
+// Durations and summaries responses come in the same batch; the durations one is told apart by its
+// `branches` and `timezone` fields.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum AnyResponse {
+    Durations(Response),
+    Summaries(SummariesResponse),
+}
+
+fn format_summary_entries(entries: &[SummaryEntry]) -> String {
+    entries.iter().map(|e| format!("{} ({})", e.name, e.text)).collect::<Vec<String>>().join(", ")
+}
+
+impl Response {
+    fn timezone(self: &Response) -> Option<Tz> {
+        self.timezone.parse::<Tz>().ok()
+    }
+
+    // `start` and `end` delimit the requested day in the user's time zone. Should the zone be unknown to
+    // chrono-tz, the midpoint is taken in UTC, which is right as long as the offset is within twelve hours.
+    fn day(self: &Response) -> NaiveDate {
+        match self.timezone() {
+            Some(timezone) => self.start.with_timezone(&timezone).date_naive(),
+            None => (self.start + (self.end - self.start) / 2).date_naive(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct WakatimeConfig {
+    // First day to fetch when nothing has been synced yet, e.g. "2022-01-01".
+    backfill_from: Option<String>,
+    // Passed on to the durations endpoint, e.g. "language", "editor", "branch" or "entity".
+    slice_by: Option<String>,
+    // Summaries endpoint with `{date}` placeholders; one all-day digest event is posted per day if set.
+    summaries_url: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct WakatimeState {
+    last_synced_day: Option<String>,
+    // Time zone of the user as last told by the durations, e.g. "America/Chicago".
+    #[serde(default)]
+    timezone: Option<String>,
+}
+
 pub struct Wakatime {
+    identifier: String,
     request_config: RequestConfig,
     event_ids: HashSet<String>,
+    backfill_from: Option<NaiveDate>,
+    backfill_range: Option<(NaiveDate, NaiveDate)>,
+    slice_by: Option<String>,
+    summaries_url: Option<String>,
+    // Last day whose records have all been processed.
+    last_synced_day: Option<NaiveDate>,
+    // What `last_synced_day` becomes once the events of the current cycle are posted.
+    pending_synced_day: Cell<Option<NaiveDate>>,
+    // Time zone of the user, which tells when a day is over; UTC until a response tells it.
+    timezone: Cell<Option<Tz>>,
+}
+
+fn parse_day(day: &str) -> Result<NaiveDate, Box<dyn Error>> {
+    Ok(NaiveDate::parse_from_str(day, "%Y-%m-%d")?)
+}
+
+fn days_between(from: NaiveDate, to: NaiveDate) -> Vec<NaiveDate> {
+    from.iter_days().take_while(|day| *day <= to).collect()
+}
+
+// Advances the last synced day over the consecutive days processed after it, but never over today
+// since records may still come in.
+fn advance_last_synced_day(last_synced_day: NaiveDate, processed_days: &BTreeSet<NaiveDate>, today: NaiveDate) -> NaiveDate {
+    let mut day = last_synced_day;
+    while let Some(next_day) = day.checked_add_days(Days::new(1)) {
+        if next_day >= today || !processed_days.contains(&next_day) {
+            break;
+        }
+        day = next_day;
+    }
+    day
+}
+
+impl Wakatime {
+    fn today(&self) -> NaiveDate {
+        match self.timezone.get() {
+            Some(timezone) => Utc::now().with_timezone(&timezone).date_naive(),
+            None => Utc::now().date_naive(),
+        }
+    }
+
+    fn days_to_sync(&self, today: NaiveDate) -> Vec<NaiveDate> {
+        let (first_day, last_day) = match self.backfill_range {
+            Some(range) => range,
+            None => match self.last_synced_day {
+                Some(day) => (day + Days::new(1), today),
+                None => (self.backfill_from.unwrap_or(today - Days::new(1)), today),
+            },
+        };
+        days_between(first_day, last_day).into_iter().take(MAX_DAYS_PER_CYCLE as usize).collect()
+    }
+
+    fn get_request_url_on(&self, day: NaiveDate) -> String {
+        let url = self.request_config.url.replace("{date}", day.format("%Y-%m-%d").to_string().as_str());
+        match &self.slice_by {
+            None => url,
+            Some(slice_by) => format!("{}&slice_by={}", url, slice_by),
+        }
+    }
+
+    // Durations of the next day to sync after `day`, if any is left in this cycle.
+    fn get_request_url_after(&self, day: NaiveDate) -> Option<String> {
+        let next_day = day.checked_add_days(Days::new(1))?;
+        let last_day = *self.days_to_sync(self.today()).last()?;
+        if next_day > last_day {
+            return None;
+        }
+        Some(self.get_request_url_on(next_day))
+    }
+
+    fn duration_events(&self, response: &Response) -> Vec<Result<EventWithId, ModuleError>> {
+        // The branch is only known per day, so it is shown when there is no ambiguity.
+        let branch = match response.branches.as_slice() {
+            [branch] if self.slice_by.as_deref() != Some("branch") => Some(branch.as_str()),
+            _ => None,
+        };
+        response.data.iter().map(|item| {
+            let slice = self.slice_by.as_deref().and_then(|slice_by| item.slice(slice_by));
+            let tags: Vec<&str> = slice.into_iter().chain(branch).collect();
+            let mut description = format!("[link] https://wakatime.com/projects/{}", item.project);
+            if let (Some(slice_by), Some(slice)) = (&self.slice_by, slice) {
+                description += format!("\n[{}] {}", slice_by, slice).as_str();
+            }
+            if let Some(branch) = branch {
+                description += format!("\n[branch] {}", branch).as_str();
+            }
+            let context = || format!("durations of {} on {}", item.project, response.day());
+            let created_at = item.time.as_f64().and_then(|time| Utc.timestamp_opt(time.floor() as i64, 0).single())
+                .ok_or_else(|| ModuleError::parse(context(), format!("invalid time {}", item.time), ""))?;
+            let length = item.duration.as_f64()
+                .ok_or_else(|| ModuleError::parse(context(), format!("invalid duration {}", item.duration), ""))?;
+            Ok(EventWithId {
+                summary: if tags.is_empty() {
+                    format!("[Wakatime] {}", item.project)
+                } else {
+                    format!("[Wakatime] {} ({})", item.project, tags.join(", "))
+                },
+                description,
+                duration: StartEnd(created_at, created_at + Duration::seconds(length.floor() as i64)),
+                id: item.id(self.identifier.as_str(), slice),
+                location: None,
+                url: None,
+                ical_uid: None,
+            })
+        }).collect()
+    }
+}
+
+fn summary_events(response: &SummariesResponse, identifier: &str) -> Vec<Result<EventWithId, ModuleError>> {
+    let mut events = vec![];
+    for summary in &response.data {
+        if summary.grand_total.total_seconds <= 0.0 {
+            continue;
+        }
+        let day = match parse_day(summary.range.date.as_str()) {
+            Ok(day) => day,
+            Err(e) => {
+                events.push(Err(ModuleError::parse("summary", e, summary.range.date.as_str())));
+                continue;
+            }
+        };
+        events.push(Ok(EventWithId {
+            summary: format!("[Wakatime] {} of coding", summary.grand_total.text),
+            description: format!("[projects] {}\n[languages] {}\n[editors] {}\n[hash] {}",
+                                 format_summary_entries(&summary.projects),
+                                 format_summary_entries(&summary.languages),
+                                 format_summary_entries(&summary.editors),
+                                 summary.id(identifier)),
+            duration: WholeDay(day),
+            id: summary.id(identifier),
+            location: None,
+            url: None,
+            ical_uid: None,
+        }));
+    }
+    events
 }
 
 impl Module for Wakatime {
-    fn new(calendar_id: Option<String>) -> Result<Box<dyn Module>, Box<dyn Error>> {
-        let request_config = RequestConfig::new(IDENTIFIER, calendar_id)?;
-        let event_ids = read_dumped_event_id(IDENTIFIER).unwrap_or(HashSet::new());
+    fn new(instance: Option<&str>, calendar_id: Option<String>) -> Result<Box<dyn Module>, Box<dyn Error>> {
+        let identifier = instance_identifier(IDENTIFIER, instance);
+        let (request_config, wakatime_config) = read_configs::<WakatimeConfig>(identifier.as_str(), calendar_id, |_| vec![])?;
+        let event_ids = read_dumped_event_id(identifier.as_str()).unwrap_or_default();
+        let backfill_from = wakatime_config.backfill_from.as_deref().map(parse_day).transpose()?;
+        let slice_by = wakatime_config.slice_by;
+        let summaries_url = wakatime_config.summaries_url;
+        let state = read_state::<WakatimeState>(identifier.as_str());
+        let last_synced_day = state.last_synced_day.as_deref().map(parse_day).transpose()?;
+        let timezone = state.timezone.and_then(|timezone| timezone.parse::<Tz>().ok());
         Ok(Box::new(Wakatime {
+            identifier,
             request_config,
             event_ids,
+            backfill_from,
+            backfill_range: None,
+            slice_by,
+            summaries_url,
+            last_synced_day,
+            pending_synced_day: Cell::new(None),
+            timezone: Cell::new(timezone),
         }))
     }
 
     fn dump(&self) {
-        dump_event_id_wrapper(IDENTIFIER, &self.event_ids);
+        dump_event_id_wrapper(self.identifier.as_str(), &self.event_ids);
+        dump_state(self.identifier.as_str(), &WakatimeState {
+            last_synced_day: self.last_synced_day.map(|day| day.format("%Y-%m-%d").to_string()),
+            timezone: self.timezone.get().map(|timezone| timezone.name().to_string()),
+        });
     }
 
     fn get_config(&self) -> &RequestConfig {
@@ -63,32 +328,215 @@ impl Module for Wakatime {
     }
 
     fn get_identifier(&self) -> &str {
-        IDENTIFIER
+        self.identifier.as_str()
     }
 
+    // The days to sync are paged through, starting with the first one.
     fn get_request_url(&self) -> String {
-        self.request_config.url.replace("{date}", Utc::now().format("%Y-%m-%d").to_string().as_str())
+        let today = self.today();
+        let days = self.days_to_sync(today);
+        info!("Fetching {} days of durations from {:?} on.", days.len(), days.first());
+        self.get_request_url_on(days.first().copied().unwrap_or(today))
+    }
+
+    // The durations of a day are followed by its summaries if they are configured, and then by the
+    // durations of the next day.
+    fn next_page_url(&self, response: &String) -> Option<String> {
+        match serde_json::from_str::<AnyResponse>(response.as_str()).ok()? {
+            AnyResponse::Durations(json) => match &self.summaries_url {
+                Some(url) => Some(url.replace("{date}", json.day().format("%Y-%m-%d").to_string().as_str())),
+                None => self.get_request_url_after(json.day()),
+            },
+            AnyResponse::Summaries(json) => self.get_request_url_after(json.day()?),
+        }
     }
 
     fn need_for_detail(&self, _response: &String) -> Option<Vec<String>> {
         None
     }
 
-    fn process_response_into_event_with_id(&self, responses: Vec<String>) -> Result<Vec<EventWithId>, Box<dyn Error>> {
-        let response = responses[0].clone();
-        let items = match serde_json::from_str::<Response>(response.as_str()) {
-            Ok(json) => json.data,
-            Err(e) => panic!("Cannot parse {} response!, {:#?}. The original response reads:\n{}", IDENTIFIER, e, response),
-        };
+    // Durations and summaries are the rows; a response which is not json at all fails the cycle.
+    fn process_response_into_event_with_id(&self, responses: Vec<String>) -> Result<ParsedEvents, ModuleError> {
+        let mut events = vec![];
+        let mut processed_days = BTreeSet::new();
+        for response in responses {
+            match serde_json::from_str::<AnyResponse>(response.as_str()) {
+                Ok(AnyResponse::Durations(json)) => {
+                    if let Some(timezone) = json.timezone() {
+                        self.timezone.set(Some(timezone));
+                    }
+                    processed_days.insert(json.day());
+                    events.extend(self.duration_events(&json));
+                }
+                Ok(AnyResponse::Summaries(json)) => events.extend(summary_events(&json, self.identifier.as_str())),
+                Err(e) => return Err(ModuleError::parse("durations or summaries", e, response.as_str())),
+            };
+        }
+        // A backfill only carries on a sync which has already started, so that the first regular cycle still
+        // starts from `backfill_from`.
+        if let Some(first_day) = processed_days.iter().next() {
+            let last_synced_day = self.last_synced_day
+                .or_else(|| self.backfill_range.is_none().then(|| *first_day - Days::new(1)));
+            if let Some(last_synced_day) = last_synced_day {
+                self.pending_synced_day.set(Some(advance_last_synced_day(last_synced_day, &processed_days, self.today())));
+            }
+        }
 
-        Ok(items.iter().map(|item| {
-            let created_at = Utc.timestamp(item.time.as_f64().unwrap().floor() as i64, 0);
-            EventWithId {
-                summary: format!("[Wakatime] {}", item.project),
-                description: format!("[link] https://wakatime.com/projects/{}", item.project),
-                duration: StartEnd(created_at, created_at + Duration::seconds(item.duration.as_f64().unwrap().floor() as i64)),
-                id: item.id(),
+        let mut parsed = ParsedEvents::default();
+        for (row, event) in events.into_iter().enumerate() {
+            match event {
+                Ok(event) => parsed.events.push(event),
+                Err(e) => parsed.skip(row, &e, ""),
             }
-        }).collect())
+        }
+        Ok(parsed)
+    }
+
+    fn set_backfill_range(&mut self, from: NaiveDate, to: NaiveDate) -> Result<(), Box<dyn Error>> {
+        self.backfill_range = Some((from, to));
+        Ok(())
     }
-}
\ No newline at end of file
+
+    fn get_request_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(500)
+    }
+
+    fn commit_cycle(&mut self) {
+        if let Some(day) = self.pending_synced_day.take() {
+            self.last_synced_day = Some(day);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::collections::{BTreeSet, HashSet};
+
+    use chrono::{NaiveDate, Utc};
+
+    use crate::calendar::event::Duration::WholeDay;
+    use crate::common::{Module, RequestConfig};
+    use crate::common::error::ModuleError;
+    use crate::test_support::{assert_golden, fetch_events, StubServer};
+    use crate::wakatime::{advance_last_synced_day, Response, Wakatime};
+
+    fn wakatime(slice_by: Option<&str>) -> Wakatime {
+        Wakatime {
+            identifier: "wakatime".to_string(),
+            request_config: RequestConfig::for_test("https://wakatime.com/api/v1/users/current/durations?date={date}"),
+            event_ids: HashSet::new(),
+            backfill_from: None,
+            backfill_range: None,
+            slice_by: slice_by.map(str::to_string),
+            summaries_url: None,
+            last_synced_day: None,
+            pending_synced_day: Cell::new(None),
+            timezone: Cell::new(None),
+        }
+    }
+
+    fn day(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2022, 10, d).unwrap()
+    }
+
+    #[test]
+    fn test_advance_last_synced_day() {
+        let processed_days: BTreeSet<NaiveDate> = vec![day(11), day(12), day(14), day(15)].into_iter().collect();
+        assert_eq!(advance_last_synced_day(day(10), &processed_days, day(20)), day(12));
+        assert_eq!(advance_last_synced_day(day(13), &processed_days, day(20)), day(15));
+        assert_eq!(advance_last_synced_day(day(13), &processed_days, day(15)), day(14));
+        assert_eq!(advance_last_synced_day(day(5), &processed_days, day(20)), day(5));
+    }
+
+    #[test]
+    fn test_response_day() {
+        let response = r#"{"branches": ["main"], "data": [], "end": "2022-10-22T04:59:59Z", "start": "2022-10-21T05:00:00Z", "timezone": "America/Chicago"}"#;
+        assert_eq!(serde_json::from_str::<Response>(response).unwrap().day(), day(21));
+        let response = r#"{"branches": [], "data": [], "end": "2022-10-21T14:59:59Z", "start": "2022-10-20T15:00:00Z", "timezone": "Asia/Tokyo"}"#;
+        assert_eq!(serde_json::from_str::<Response>(response).unwrap().day(), day(21));
+        let response = r#"{"branches": [], "data": [], "end": "2022-10-21T09:59:59Z", "start": "2022-10-20T10:00:00Z", "timezone": "Pacific/Kiritimati"}"#;
+        assert_eq!(serde_json::from_str::<Response>(response).unwrap().day(), day(21));
+    }
+
+    #[test]
+    fn test_sliced_durations() {
+        let response = r#"{"branches": ["main"], "data": [
+            {"color": null, "duration": 600.5, "language": "Rust", "project": "calendar-as-diary", "time": 1666364400.1},
+            {"color": null, "duration": 300, "language": "TOML", "project": "calendar-as-diary", "time": 1666365000.6}
+        ], "end": "2022-10-22T04:59:59Z", "start": "2022-10-21T05:00:00Z", "timezone": "America/Chicago"}"#;
+        let module = wakatime(Some("language"));
+        assert_eq!(module.get_request_url_on(day(21)), "https://wakatime.com/api/v1/users/current/durations?date=2022-10-21&slice_by=language");
+
+        let events = module.process_response_into_event_with_id(vec![response.to_string()]).unwrap().events;
+        assert_eq!(events[0].summary, "[Wakatime] calendar-as-diary (Rust, main)");
+        assert_eq!(events[0].description, "[link] https://wakatime.com/projects/calendar-as-diary\n[language] Rust\n[branch] main");
+        assert_eq!(events[0].id, "wakatime|1666364400.1|Rust");
+        assert_eq!(events[1].summary, "[Wakatime] calendar-as-diary (TOML, main)");
+
+        let events = wakatime(None).process_response_into_event_with_id(vec![response.to_string()]).unwrap().events;
+        assert_eq!(events[0].summary, "[Wakatime] calendar-as-diary (main)");
+        assert_eq!(events[0].id, "wakatime|1666364400.1");
+    }
+
+    #[test]
+    fn test_summaries() {
+        let response = r#"{"cumulative_total": {"seconds": 9000}, "data": [{
+            "grand_total": {"digital": "2:30", "hours": 2, "minutes": 30, "text": "2 hrs 30 mins", "total_seconds": 9000},
+            "range": {"date": "2022-10-21", "end": "2022-10-22T04:59:59Z", "start": "2022-10-21T05:00:00Z", "text": "Fri Oct 21st 2022", "timezone": "America/Chicago"},
+            "projects": [{"name": "calendar-as-diary", "text": "2 hrs 30 mins", "total_seconds": 9000}],
+            "languages": [{"name": "Rust", "text": "2 hrs", "total_seconds": 7200}, {"name": "TOML", "text": "30 mins", "total_seconds": 1800}],
+            "editors": [{"name": "IntelliJ", "text": "2 hrs 30 mins", "total_seconds": 9000}]
+        }], "end": "2022-10-22T04:59:59Z", "start": "2022-10-21T05:00:00Z"}"#;
+        let events = wakatime(None).process_response_into_event_with_id(vec![response.to_string()]).unwrap().events;
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].summary, "[Wakatime] 2 hrs 30 mins of coding");
+        assert_eq!(events[0].description, "[projects] calendar-as-diary (2 hrs 30 mins)\n[languages] Rust (2 hrs), TOML (30 mins)\n[editors] IntelliJ (2 hrs 30 mins)\n[hash] wakatime|summary|2022-10-21");
+        assert!(matches!(events[0].duration, WholeDay(d) if d == day(21)));
+    }
+
+    #[test]
+    fn test_unparsable_response() {
+        let error = wakatime(None).process_response_into_event_with_id(vec!["<html>Bad gateway</html>".to_string()]).unwrap_err();
+        assert!(matches!(error, ModuleError::Parse { ref snippet, .. } if snippet == "<html>Bad gateway</html>"));
+    }
+
+    #[tokio::test]
+    async fn test_fixture() {
+        let server = StubServer::start(&[
+            ("/api/v1/users/current/durations?date=2022-10-21&slice_by=language", "wakatime/durations_2022-10-21.json"),
+            ("/api/v1/users/current/durations?date=2022-10-22&slice_by=language", "wakatime/durations_2022-10-22.json"),
+            ("/api/v1/users/current/summaries?start=2022-10-21&end=2022-10-21", "wakatime/summaries_2022-10-21.json"),
+            ("/api/v1/users/current/summaries?start=2022-10-22&end=2022-10-22", "wakatime/summaries_2022-10-22.json"),
+        ]);
+        let mut module = wakatime(Some("language"));
+        module.request_config.url = server.url("/api/v1/users/current/durations?date={date}");
+        module.summaries_url = Some(server.url("/api/v1/users/current/summaries?start={date}&end={date}"));
+        let mut module: Box<dyn Module> = Box::new(module);
+        module.set_backfill_range(day(21), day(22)).unwrap();
+
+        let events = fetch_events(&mut module).await;
+        assert_eq!(server.requests().len(), 4);
+        assert_golden("wakatime/expected.txt", &events, &Utc);
+    }
+
+    #[test]
+    fn test_commit_cycle() {
+        let response = r#"{"branches": [], "data": [], "end": "2022-10-22T04:59:59Z", "start": "2022-10-21T05:00:00Z", "timezone": "America/Chicago"}"#;
+        let mut module = wakatime(None);
+        module.last_synced_day = Some(day(20));
+        module.process_response_into_event_with_id(vec![response.to_string()]).unwrap();
+        // Nothing counts as synced until the events of the cycle are posted.
+        assert_eq!(module.last_synced_day, Some(day(20)));
+        module.commit_cycle();
+        assert_eq!(module.last_synced_day, Some(day(21)));
+        assert_eq!(module.timezone.get(), Some(chrono_tz::America::Chicago));
+
+        // A backfill before anything is synced leaves the start to `backfill_from`.
+        let mut module = wakatime(None);
+        module.set_backfill_range(day(21), day(21)).unwrap();
+        module.process_response_into_event_with_id(vec![response.to_string()]).unwrap();
+        module.commit_cycle();
+        assert_eq!(module.last_synced_day, None);
+    }
+}
@@ -1,17 +1,22 @@
 use std::collections::HashSet;
 use std::error::Error;
-use std::fmt;
 
-use chrono::{Datelike, DateTime, Duration, Local, TimeZone, Utc};
+use chrono::{Datelike, DateTime, Duration, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use lazy_static::lazy_static;
 use regex::Regex;
+use reqwest::StatusCode;
 use scraper::{ElementRef, Html, Selector};
+use serde::Deserialize;
 
 use crate::calendar::event::*;
 use crate::calendar::event::Duration::StartEnd;
 use crate::common::*;
-use crate::youtube::ParseError::*;
+use crate::common::diagnostic::ParsedEvents;
+use crate::common::error::ModuleError;
 
 const IDENTIFIER: &str = "youtube";
+// Takeout records carry no watched length, so we take the gap until the next record capped by this.
+const TAKEOUT_MAX_LENGTH_SECONDS: i64 = 30 * 60;
 
 #[derive(Debug)]
 struct Item {
@@ -23,48 +28,276 @@ struct Item {
 }
 
 impl Item {
-    fn id(self: &Item) -> String {
-        let paths = self.link.split("=").collect::<Vec<&str>>();
-        let id = paths[1];
-        format!("{}|{}|{}", IDENTIFIER, id, self.start.format("%Y-%m-%d %H:%M").to_string())
+    fn id(self: &Item, identifier: &str) -> String {
+        // The video id follows "watch?v="; other links are kept whole.
+        let id = self.link.split('=').nth(1).unwrap_or(self.link.as_str());
+        format!("{}|{}|{}", identifier, id, self.start.format("%Y-%m-%d %H:%M"))
     }
+
+    fn to_event_with_id(self: &Item, identifier: &str) -> EventWithId {
+        let start_time = self.start.with_timezone(&Utc);
+        let end_time = (self.start + Duration::seconds(self.length as i64)).with_timezone(&Utc);
+        EventWithId {
+            summary: format!("[Youtube] {}", self.title),
+            description: format!("[link] {}\n[author] {}\n[hash] {}", self.link, self.author, self.id(identifier)),
+            duration: StartEnd(start_time, end_time),
+            id: self.id(identifier),
+            location: None,
+            url: None,
+            ical_uid: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TakeoutWatch {
+    link: String,
+    title: String,
+    author: String,
+    start: DateTime<Local>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TakeoutSubtitle {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TakeoutRecord {
+    title: String,
+    title_url: Option<String>,
+    subtitles: Option<Vec<TakeoutSubtitle>>,
+    details: Option<Vec<TakeoutSubtitle>>,
+    time: String,
 }
 
 pub struct Youtube {
+    identifier: String,
     request_config: RequestConfig,
     event_ids: HashSet<String>,
     daylight_saving: DaylightSavingConfig,
 }
 
-#[derive(Debug, Clone)]
-enum ParseError {
-    UnknownElement(String),
-    WrongStartTimeInput(String),
-    WrongTotalLengthInput(String),
-    WrongPercentStyleInput(String),
-    WrongViewDateInput(String),
+lazy_static! {
+    static ref DIV_SELECTOR: Selector = Selector::parse("div").unwrap();
+    static ref A_SELECTOR: Selector = Selector::parse("a").unwrap();
+}
+
+fn first_child<'a>(element: ElementRef<'a>, selector: &Selector) -> Option<ElementRef<'a>> {
+    element.select(selector).next()
+}
+
+fn next_element(element: ElementRef) -> Option<ElementRef> {
+    element.next_sibling().and_then(ElementRef::wrap)
+}
+
+fn parse_number<T: std::str::FromStr>(s: &str, context: &str) -> Result<T, ModuleError> where T::Err: std::fmt::Display {
+    s.parse::<T>().map_err(|e| ModuleError::parse(context, e, s))
+}
+
+// Headings of the history page read "今天", "昨天", "2021年12月31日", or "12月31日" within this year.
+fn parse_date_heading(heading: &ElementRef) -> Result<NaiveDate, ModuleError> {
+    lazy_static! {
+        static ref H2_SELECTOR: Selector = Selector::parse("h2").unwrap();
+        static ref YMD_RE: Regex = Regex::new(r"(\d+)年(\d+)月(\d+)日").unwrap();
+        static ref MD_RE: Regex = Regex::new(r"(\d+)月(\d+)日").unwrap();
+    }
+    let date_text = heading.select(&H2_SELECTOR).next()
+        .ok_or_else(|| ModuleError::parse("date heading", "h2 is missing", heading.html().as_str()))?
+        .inner_html();
+    let today = Local::now().date_naive();
+    let date = if date_text == "今天" {
+        Some(today)
+    } else if date_text == "昨天" {
+        today.pred_opt()
+    } else if let Some(cap) = YMD_RE.captures(date_text.as_str()) {
+        NaiveDate::from_ymd_opt(parse_number(&cap[1], "date heading")?, parse_number(&cap[2], "date heading")?, parse_number(&cap[3], "date heading")?)
+    } else if let Some(cap) = MD_RE.captures(date_text.as_str()) {
+        NaiveDate::from_ymd_opt(today.year(), parse_number(&cap[1], "date heading")?, parse_number(&cap[2], "date heading")?)
+    } else {
+        None
+    };
+    date.ok_or_else(|| ModuleError::parse("date heading", "unknown date", date_text.as_str()))
+}
+
+// e.g. "下午3:45" to (15, 45).
+fn parse_start_time(text: &str) -> Result<(u32, u32), ModuleError> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"(上午|下午)(\d+):(\d+)").unwrap();
+    }
+    let cap = RE.captures(text).ok_or_else(|| ModuleError::parse("start time", "no time of day", text))?;
+    let hour = parse_number::<u32>(&cap[2], "start time")? % 12;
+    let minute = parse_number::<u32>(&cap[3], "start time")?;
+    Ok(if &cap[1] == "上午" { (hour, minute) } else { (hour + 12, minute) })
+}
+
+// e.g. "1:02:03" or "2:03", in seconds.
+fn parse_total_length(text: &str) -> Result<u32, ModuleError> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"(?:(\d+):)?(\d+):(\d+)").unwrap();
+    }
+    let cap = RE.captures(text).ok_or_else(|| ModuleError::parse("total length", "no length", text))?;
+    let hours = match cap.get(1) {
+        Some(hours) => parse_number::<u32>(hours.as_str(), "total length")?,
+        None => 0,
+    };
+    Ok(hours * 3600 + parse_number::<u32>(&cap[2], "total length")? * 60 + parse_number::<u32>(&cap[3], "total length")?)
+}
+
+// The progress bar under a thumbnail is styled "width:42%".
+fn parse_percent(element: &ElementRef) -> Result<u32, ModuleError> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"width:(\d+)%").unwrap();
+    }
+    let cap = element.value().attr("style").and_then(|style| RE.captures(style))
+        .ok_or_else(|| ModuleError::parse("watched percent", "no width in style", element.html().as_str()))?;
+    parse_number(&cap[1], "watched percent")
+}
+
+fn parse_card(card: &ElementRef, date: NaiveDate, offset: i32) -> Result<Item, ModuleError> {
+    let missing = |name: &str| ModuleError::parse("history card", format!("{} is missing", name), card.html().as_str());
+    let card_root = first_child(*card, &DIV_SELECTOR)
+        .and_then(|e| first_child(e, &DIV_SELECTOR))
+        .and_then(|e| first_child(e, &DIV_SELECTOR))
+        .and_then(next_element)
+        .ok_or_else(|| missing("card root"))?;
+    let left_panel = first_child(card_root, &DIV_SELECTOR).ok_or_else(|| missing("left panel"))?;
+    let title_row = first_child(left_panel, &DIV_SELECTOR).ok_or_else(|| missing("title"))?;
+    let title_element = first_child(title_row, &A_SELECTOR).ok_or_else(|| missing("title link"))?;
+    let author_row = next_element(title_row).ok_or_else(|| missing("author"))?;
+    let author_element = first_child(author_row, &A_SELECTOR).ok_or_else(|| missing("author link"))?;
+    let start_time_element = next_element(author_row)
+        .and_then(|e| first_child(e, &DIV_SELECTOR))
+        .ok_or_else(|| missing("start time"))?;
+    let total_length_element = first_child(card_root, &DIV_SELECTOR)
+        .and_then(next_element)
+        .and_then(|e| first_child(e, &A_SELECTOR))
+        .and_then(|e| first_child(e, &DIV_SELECTOR))
+        .and_then(|e| first_child(e, &DIV_SELECTOR))
+        .and_then(next_element)
+        .ok_or_else(|| missing("total length"))?;
+    let percent_element = next_element(total_length_element).and_then(next_element);
+    let link = title_element.value().attr("href").ok_or_else(|| missing("href of the title link"))?;
+
+    let (hour, minute) = parse_start_time(start_time_element.inner_html().as_str())?;
+    let total_length = parse_total_length(total_length_element.inner_html().as_str())?;
+    let watched_length = match percent_element {
+        None => total_length,
+        Some(percent_element) => total_length * parse_percent(&percent_element)? / 100,
+    };
+    let start = date.and_hms_opt(hour, minute, 0)
+        .and_then(|naive| Local.from_local_datetime(&naive).earliest())
+        .ok_or_else(|| ModuleError::parse("start time", "not a local time", start_time_element.inner_html().as_str()))?;
+
+    Ok(Item {
+        link: link.to_string(),
+        title: title_element.inner_html(),
+        author: author_element.inner_html(),
+        length: watched_length,
+        start: start - Duration::hours(offset as i64),
+    })
+}
+
+fn parse_takeout_json(content: &str) -> Result<Vec<TakeoutWatch>, ModuleError> {
+    let records = serde_json::from_str::<Vec<TakeoutRecord>>(content)
+        .map_err(|e| ModuleError::parse("takeout json", e, content))?;
+    let mut watches = vec![];
+    for record in records {
+        let (link, title) = match (&record.title_url, record.title.strip_prefix("Watched ")) {
+            (Some(link), Some(title)) => (link.clone(), title.to_string()),
+            _ => {
+                debug!("Takeout record \"{}\" is not a watched video; skipped.", record.title);
+                continue;
+            }
+        };
+        if record.details.iter().flatten().any(|detail| detail.name == "From Google Ads") {
+            debug!("Takeout record \"{}\" is an advertisement; skipped.", record.title);
+            continue;
+        }
+        let start = DateTime::parse_from_rfc3339(record.time.as_str())
+            .map_err(|e| ModuleError::parse("takeout time", e, record.time.as_str()))?
+            .with_timezone(&Local);
+        let author = record.subtitles.iter().flatten().next().map(|s| s.name.clone()).unwrap_or_default();
+        watches.push(TakeoutWatch { link, title, author, start });
+    }
+    Ok(watches)
+}
+
+fn parse_takeout_time(time_str: &str) -> Result<DateTime<Local>, ModuleError> {
+    // e.g. "May 1, 2020, 12:34:56 PM EDT"; the trailing zone abbreviation is ignored and the time is
+    // taken to be local, as Takeout renders the page in the time zone of the exporting account.
+    let normalized = time_str.replace(['\u{a0}', '\u{202f}'], " ");
+    let without_zone = match normalized.trim().rsplit_once(' ') {
+        Some((rest, zone)) if zone.chars().all(|c| c.is_ascii_alphabetic()) && zone != "AM" && zone != "PM" => rest.to_string(),
+        _ => normalized.trim().to_string(),
+    };
+    ["%b %d, %Y, %I:%M:%S %p", "%d %b %Y, %H:%M:%S"].iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(without_zone.as_str(), format).ok())
+        .and_then(|naive| Local.from_local_datetime(&naive).earliest())
+        .ok_or_else(|| ModuleError::parse("takeout time", "unknown format", time_str))
 }
 
-impl fmt::Display for ParseError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            UnknownElement(t) => write!(f, "Unknown element type: {}", t),
-            WrongStartTimeInput(t) => write!(f, "Wrong start time input: {}", t),
-            WrongTotalLengthInput(t) => write!(f, "Wrong total length input: {}", t),
-            WrongPercentStyleInput(t) => write!(f, "Wrong percent style input: {}", t),
-            WrongViewDateInput(t) => write!(f, "Wrong view date input: {}", t)
+fn parse_takeout_html(content: &str) -> Result<Vec<TakeoutWatch>, ModuleError> {
+    lazy_static! {
+        static ref CELL_SELECTOR: Selector = Selector::parse("div.content-cell.mdl-cell--6-col.mdl-typography--body-1").unwrap();
+    }
+    let document = Html::parse_document(content);
+
+    let mut watches = vec![];
+    for cell in document.select(&CELL_SELECTOR) {
+        let texts: Vec<&str> = cell.text().map(|t| t.trim()).filter(|t| !t.is_empty()).collect();
+        if !texts.first().is_some_and(|t| t.starts_with("Watched")) {
+            continue;
         }
+        let mut links = cell.select(&A_SELECTOR);
+        let title_element = match links.next() {
+            Some(e) => e,
+            None => {
+                debug!("Takeout record \"{}\" refers to a removed video; skipped.", texts.join(" "));
+                continue;
+            }
+        };
+        let link = match title_element.value().attr("href") {
+            Some(href) => href.to_string(),
+            None => continue,
+        };
+        let title = title_element.text().collect::<String>();
+        let author = links.next().map(|e| e.text().collect::<String>()).unwrap_or_default();
+        let time_text = texts.last().ok_or_else(|| ModuleError::parse("takeout record", "no watched time", cell.html().as_str()))?;
+        let start = parse_takeout_time(time_text)?;
+        watches.push(TakeoutWatch { link, title, author, start });
     }
+    Ok(watches)
 }
 
-impl Error for ParseError {}
+// Takeout lists the newest record first; the watched length is estimated from the next record.
+fn takeout_watches_into_items(mut watches: Vec<TakeoutWatch>) -> Vec<Item> {
+    watches.sort_by_key(|w| w.start);
+    let next_starts: Vec<Option<DateTime<Local>>> = watches.iter().skip(1).map(|w| Some(w.start)).chain(vec![None]).collect();
+    watches.into_iter().zip(next_starts).map(|(watch, next_start)| {
+        let length = match next_start {
+            Some(next_start) => (next_start - watch.start).num_seconds().min(TAKEOUT_MAX_LENGTH_SECONDS),
+            None => TAKEOUT_MAX_LENGTH_SECONDS,
+        };
+        Item {
+            link: watch.link,
+            title: watch.title,
+            author: watch.author,
+            length: length as u32,
+            start: watch.start,
+        }
+    }).collect()
+}
 
 impl Module for Youtube {
-    fn new(calendar_id: Option<String>) -> Result<Box<dyn Module>, Box<dyn Error>> {
-        let request_config = RequestConfig::new(IDENTIFIER, calendar_id)?;
-        let event_ids = read_dumped_event_id(IDENTIFIER).unwrap_or(HashSet::new());
-        let daylight_saving = read_json::<DaylightSavingConfigWrapper>(format!("config/{}.json", IDENTIFIER).as_str())?.daylight_saving;
+    fn new(instance: Option<&str>, calendar_id: Option<String>) -> Result<Box<dyn Module>, Box<dyn Error>> {
+        let identifier = instance_identifier(IDENTIFIER, instance);
+        let (request_config, config) = read_configs::<DaylightSavingConfigWrapper>(identifier.as_str(), calendar_id, |_| vec![])?;
+        let event_ids = read_dumped_event_id(identifier.as_str()).unwrap_or_default();
+        let daylight_saving = config.daylight_saving;
         Ok(Box::new(Youtube {
+            identifier,
             request_config,
             event_ids,
             daylight_saving,
@@ -72,7 +305,7 @@ impl Module for Youtube {
     }
 
     fn dump(&self) {
-        dump_event_id_wrapper(IDENTIFIER, &self.event_ids);
+        dump_event_id_wrapper(self.identifier.as_str(), &self.event_ids);
     }
 
     fn get_config(&self) -> &RequestConfig {
@@ -84,175 +317,168 @@ impl Module for Youtube {
     }
 
     fn get_identifier(&self) -> &str {
-        IDENTIFIER
+        self.identifier.as_str()
     }
 
     fn get_request_url(&self) -> String {
         self.request_config.url.to_string()
     }
 
+    // My Activity sends to the Google sign-in page once the cookie expires.
+    fn check_auth_health(&self, status: StatusCode, final_url: &str, _response: &str) -> Option<String> {
+        if final_url.starts_with("https://accounts.google.com/") {
+            Some(format!("redirected to {}", final_url))
+        } else if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+            Some(format!("HTTP status {}", status))
+        } else {
+            None
+        }
+    }
+
     fn need_for_detail(&self, _response: &String) -> Option<Vec<String>> {
         None
     }
 
-    fn process_response_into_event_with_id(&self, responses: Vec<String>) -> Result<Vec<EventWithId>, Box<dyn Error>> {
-        let response = responses[0].clone();
-        let document = Html::parse_document(response.as_str());
-        let selector = Selector::parse("c-wiz[data-token]").unwrap();
-        let div_selector = Selector::parse("div").unwrap();
-        let a_selector = Selector::parse("a").unwrap();
-        let h2_selector = Selector::parse("h2").unwrap();
-
-        let first_cwiz_element = match document.select(&selector).next() {
-            None => return Ok(vec![]),
-            Some(e) => e,
-        };
+    fn process_response_into_event_with_id(&self, responses: Vec<String>) -> Result<ParsedEvents, ModuleError> {
+        lazy_static! {
+            static ref CWIZ_SELECTOR: Selector = Selector::parse("c-wiz[data-token]").unwrap();
+        }
+        let offset = self.daylight_saving.get_offset_on(Local::now().date_naive());
 
-        let list_div = first_cwiz_element.parent().and_then(ElementRef::wrap).unwrap();
-
-        let mut items = vec![];
-        let mut date = Local::today();
-        for e in list_div.children() {
-            let e = ElementRef::wrap(e).unwrap();
-            match e.value().name() {
-                "div" => {
-                    let date_h2 = e.select(&h2_selector).next().unwrap();
-                    let date_text = date_h2.inner_html();
-                    if date_text == "今天" {
-                        date = Local::today();
-                    } else if date_text == "昨天" {
-                        date = Local::today().pred();
-                    } else {
-                        let ymd_pattern = Regex::new(r"(\d+)年(\d+)月(\d+)日")?;
-                        if let Some(cap) = ymd_pattern.captures(date_text.as_str()) {
-                            let year = cap[1].parse::<i32>().unwrap();
-                            let month = cap[2].parse::<u32>().unwrap();
-                            let day = cap[3].parse::<u32>().unwrap();
-                            date = Local.ymd(year, month, day);
-                        } else {
-                            let md_pattern = Regex::new(r"(\d+)月(\d+)日")?;
-                            match md_pattern.captures(date_text.as_str()) {
-                                None => return Err(Box::new(WrongViewDateInput(date_text))),
-                                Some(cap) => {
-                                    let year = Local::today().year();
-                                    let month = cap[1].parse::<u32>().unwrap();
-                                    let day = cap[2].parse::<u32>().unwrap();
-                                    date = Local.ymd(year, month, day);
-                                }
-                            }
-                        }
+        let mut parsed = ParsedEvents::default();
+        let mut row = 0;
+        for response in responses {
+            let document = Html::parse_document(response.as_str());
+            // Date headings and the cards watched on that date are siblings under one list.
+            let list_div = match document.select(&CWIZ_SELECTOR).next().and_then(|e| e.parent()).and_then(ElementRef::wrap) {
+                None => continue,
+                Some(e) => e,
+            };
+            // Cards under a heading which cannot be parsed are skipped rather than put on a wrong date.
+            let mut date = Ok(Local::now().date_naive());
+            for e in list_div.children().filter_map(ElementRef::wrap) {
+                let result = match (e.value().name(), &date) {
+                    ("div", _) => {
+                        date = parse_date_heading(&e);
+                        date.as_ref().map(|_| None).map_err(|e| e.clone())
                     }
+                    ("c-wiz", Ok(date)) => parse_card(&e, *date, offset).map(Some),
+                    ("c-wiz", Err(_)) => Err(ModuleError::parse("history card", "the date heading above is broken", "")),
+                    (t, _) => Err(ModuleError::SourceChanged(format!("unknown element <{}> in the watch history", t))),
+                };
+                match result {
+                    Ok(Some(item)) => parsed.events.push(item.to_event_with_id(self.identifier.as_str())),
+                    Ok(None) => {}
+                    Err(error) => parsed.skip(row, &error, e.html().as_str()),
                 }
-                "c-wiz" => {
-                    let card_root = e
-                        .select(&div_selector).next().unwrap()
-                        .select(&div_selector).next().unwrap()
-                        .select(&div_selector).next().unwrap()
-                        .next_sibling().and_then(ElementRef::wrap).unwrap();
-                    let left_panel = card_root.select(&div_selector).next().unwrap();
-                    let title_element = left_panel
-                        .select(&div_selector).next().unwrap()
-                        .select(&a_selector).next().unwrap();
-                    let author_element = left_panel
-                        .select(&div_selector).next().unwrap()
-                        .next_sibling().and_then(ElementRef::wrap).unwrap()
-                        .select(&a_selector).next().unwrap();
-                    let start_time_element = left_panel
-                        .select(&div_selector).next().unwrap()
-                        .next_sibling().and_then(ElementRef::wrap).unwrap()
-                        .next_sibling().and_then(ElementRef::wrap).unwrap()
-                        .select(&div_selector).next().unwrap();
-                    let total_length_element = card_root
-                        .select(&div_selector).next().unwrap()
-                        .next_sibling().and_then(ElementRef::wrap).unwrap()
-                        .select(&a_selector).next().unwrap()
-                        .select(&div_selector).next().unwrap()
-                        .select(&div_selector).next().unwrap()
-                        .next_sibling().and_then(ElementRef::wrap).unwrap();
-                    let percent_element = {
-                        match total_length_element.next_sibling().and_then(ElementRef::wrap) {
-                            None => None,
-                            Some(e) => e.next_sibling().and_then(ElementRef::wrap),
-                        }
-                    };
-
-                    let start_hour_minute = {
-                        let full_text = start_time_element.inner_html();
-                        let am_const = "上午";
-                        let pattern = Regex::new(r"(上午|下午)(\d+):(\d+)")?;
-                        match pattern.captures(full_text.as_str()) {
-                            None => return Err(Box::new(WrongStartTimeInput(full_text))),
-                            Some(cap) => {
-                                let hour = *&cap[2].parse::<u32>().unwrap();
-                                let minute = *&cap[3].parse::<u32>().unwrap();
-                                if &cap[1] == am_const {
-                                    (hour % 12, minute)
-                                } else {
-                                    (hour % 12 + 12, minute)
-                                }
-                            }
-                        }
-                    };
-
-                    let total_length = {
-                        let full_text = total_length_element.inner_html();
-                        let one_hour_more_pattern = Regex::new(r"(\d+):(\d+):(\d+)")?;
-                        match one_hour_more_pattern.captures(full_text.as_str()) {
-                            None => {
-                                let pattern = Regex::new(r"(\d+):(\d+)")?;
-                                match pattern.captures(full_text.as_str()) {
-                                    None => return Err(Box::new(WrongTotalLengthInput(full_text))),
-                                    Some(cap) =>
-                                        *&cap[1].parse::<u32>().unwrap() * 60 + *&cap[2].parse::<u32>().unwrap()
-                                }
-                            }
-                            Some(cap) =>
-                                *&cap[1].parse::<u32>().unwrap() * 3600
-                                    + *&cap[2].parse::<u32>().unwrap() * 60
-                                    + *&cap[3].parse::<u32>().unwrap()
-                        }
-                    };
-
-                    let watched_length = match percent_element {
-                        None => total_length,
-                        Some(percent_element) => {
-                            let style_text = percent_element.value().attr("style");
-                            match style_text {
-                                None => return Err(Box::new(WrongPercentStyleInput(percent_element.html()))),
-                                Some(t) => {
-                                    let pattern = Regex::new(r"width:(\d+)%")?;
-                                    let percent = match pattern.captures(t) {
-                                        None => return Err(Box::new(WrongPercentStyleInput(percent_element.html()))),
-                                        Some(cap) => *&cap[1].parse::<u32>().unwrap()
-                                    };
-                                    total_length * percent / 100
-                                }
-                            }
-                        }
-                    };
-
-                    let offset = self.daylight_saving.get_offset_on(&Local::today());
-                    items.push(Item {
-                        link: title_element.value().attr("href").unwrap().to_string(),
-                        title: title_element.inner_html(),
-                        author: author_element.inner_html(),
-                        length: watched_length,
-                        start: date.and_hms(start_hour_minute.0, start_hour_minute.1, 0) - Duration::hours(offset as i64),
-                    })
-                }
-                t => return Err(Box::new(UnknownElement(t.to_string()))),
+                row += 1;
             }
         }
+        Ok(parsed)
+    }
 
-        Ok(items.iter().map(|item| {
-            let start_time = item.start.with_timezone(&Utc);
-            let end_time = (item.start + Duration::seconds(item.length as i64)).with_timezone(&Utc);
-            EventWithId {
-                summary: format!("[Youtube] {}", item.title),
-                description: format!("[link] {}\n[author] {}\n[hash] {}", item.link, item.author, item.id()),
-                duration: StartEnd(start_time, end_time),
-                id: item.id(),
-            }
-        }).collect())
+    fn process_import_into_event_with_id(&self, content: String) -> Result<Vec<EventWithId>, Box<dyn Error>> {
+        let watches = if content.trim_start().starts_with('[') {
+            parse_takeout_json(content.as_str())?
+        } else {
+            parse_takeout_html(content.as_str())?
+        };
+        info!("Read {} watched videos from the takeout file.", watches.len());
+        Ok(takeout_watches_into_items(watches).iter().map(|item| item.to_event_with_id(self.identifier.as_str())).collect())
+    }
+}
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use chrono::{Local, TimeZone};
+
+    use crate::common::{DaylightSavingConfig, Module, RequestConfig};
+    use crate::test_support::{assert_golden, fetch_events, StubServer};
+    use crate::youtube::{parse_takeout_html, parse_takeout_json, parse_takeout_time, takeout_watches_into_items, Youtube};
+
+    #[test]
+    fn test_parse_takeout_json() {
+        let content = r#"[{
+  "header": "YouTube",
+  "title": "Watched Rust in 100 Seconds",
+  "titleUrl": "https://www.youtube.com/watch?v=5C_HPTJg5ek",
+  "subtitles": [{"name": "Fireship", "url": "https://www.youtube.com/channel/UCsBjURrPoezykLs9EqgamOA"}],
+  "time": "2022-10-22T15:20:31.123Z",
+  "products": ["YouTube"]
+},{
+  "header": "YouTube",
+  "title": "Watched a video that has been removed",
+  "time": "2022-10-22T15:00:00.000Z",
+  "products": ["YouTube"]
+},{
+  "header": "YouTube",
+  "title": "Watched Some advertisement",
+  "titleUrl": "https://www.youtube.com/watch?v=advert",
+  "details": [{"name": "From Google Ads"}],
+  "time": "2022-10-22T14:59:00.000Z",
+  "products": ["YouTube"]
+},{
+  "header": "YouTube",
+  "title": "Watched Traits in Rust",
+  "titleUrl": "https://www.youtube.com/watch?v=T0Xfltu4h3A",
+  "subtitles": [{"name": "Let's Get Rusty", "url": "https://www.youtube.com/channel/UCSp-OaMpsO8K0KkOqyBl7_w"}],
+  "time": "2022-10-22T15:10:00.000Z",
+  "products": ["YouTube"]
+}]"#;
+        let watches = parse_takeout_json(content).unwrap();
+        assert_eq!(watches.len(), 2);
+        assert_eq!(watches[0].title, "Rust in 100 Seconds");
+        assert_eq!(watches[0].author, "Fireship");
+
+        let items = takeout_watches_into_items(watches);
+        assert_eq!(items[0].title, "Traits in Rust");
+        assert_eq!(items[0].length, 10 * 60 + 31);
+        assert_eq!(items[1].length, 30 * 60);
+        let start = Local.from_utc_datetime(&chrono::NaiveDate::from_ymd_opt(2022, 10, 22).unwrap().and_hms_opt(15, 20, 0).unwrap());
+        assert_eq!(items[1].id("youtube"), format!("youtube|5C_HPTJg5ek|{}", start.format("%Y-%m-%d %H:%M")));
+    }
+
+    #[test]
+    fn test_parse_takeout_html() {
+        let content = "
+<div class=\"outer-cell mdl-cell mdl-cell--12-col mdl-shadow--2dp\"><div class=\"mdl-grid\">
+<div class=\"header-cell mdl-cell mdl-cell--12-col\"><p class=\"mdl-typography--title\">YouTube<br></p></div>
+<div class=\"content-cell mdl-cell mdl-cell--6-col mdl-typography--body-1\">Watched&nbsp;<a href=\"https://www.youtube.com/watch?v=5C_HPTJg5ek\">Rust in 100 Seconds</a><br><a href=\"https://www.youtube.com/channel/UCsBjURrPoezykLs9EqgamOA\">Fireship</a><br>Oct 22, 2022, 10:20:31 AM CDT<br></div>
+<div class=\"content-cell mdl-cell mdl-cell--6-col mdl-typography--body-1 mdl-typography--text-right\"></div>
+</div></div>
+<div class=\"outer-cell mdl-cell mdl-cell--12-col mdl-shadow--2dp\"><div class=\"mdl-grid\">
+<div class=\"content-cell mdl-cell mdl-cell--6-col mdl-typography--body-1\">Watched a video that has been removed<br>Oct 22, 2022, 10:00:00 AM CDT<br></div>
+</div></div>";
+        let watches = parse_takeout_html(content).unwrap();
+        assert_eq!(watches.len(), 1);
+        assert_eq!(watches[0].link, "https://www.youtube.com/watch?v=5C_HPTJg5ek");
+        assert_eq!(watches[0].title, "Rust in 100 Seconds");
+        assert_eq!(watches[0].author, "Fireship");
+        assert_eq!(watches[0].start, Local.with_ymd_and_hms(2022, 10, 22, 10, 20, 31).unwrap());
+    }
+
+    #[test]
+    fn test_parse_takeout_time() {
+        let expected = Local.with_ymd_and_hms(2022, 10, 22, 22, 20, 31).unwrap();
+        assert_eq!(parse_takeout_time("Oct 22, 2022, 10:20:31 PM CDT").unwrap(), expected);
+        assert_eq!(parse_takeout_time("Oct 22, 2022, 10:20:31\u{202f}PM").unwrap(), expected);
+        assert_eq!(parse_takeout_time("22 Oct 2022, 22:20:31 BST").unwrap(), expected);
+        assert!(parse_takeout_time("yesterday").is_err());
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_fixture() {
+        let server = StubServer::start(&[("/activitycontrols/youtube", "youtube/my_activity.html")]);
+        let mut module: Box<dyn Module> = Box::new(Youtube {
+            identifier: "youtube".to_string(),
+            request_config: RequestConfig::for_test(&server.url("/activitycontrols/youtube?view=item")),
+            event_ids: HashSet::new(),
+            // Times on the page are taken as local ones.
+            daylight_saving: DaylightSavingConfig { start: (3, 11), end: (11, 4), effective: 0, standard: 0, local: 0 },
+        });
+
+        let events = fetch_events(&mut module).await;
+        assert_golden("youtube/expected.txt", &events, &Local);
+    }
+}